@@ -0,0 +1,839 @@
+// Copyright (C) 2023  Patrick Cleavelin <patrick@spacegirl.nl>
+
+//! Text assembler for the instruction set
+//!
+//! Takes one instruction per line, written the same way `Instruction` is
+//! displayed: destination operand first, `[addr]` or `[reg]` for memory operands and
+//! `WORD`/`BYTE` in front of 16 and 8 bit memory/immediate operands.
+//!
+//! ```text
+//! MOV A, 0x42       ; load a constant
+//! MOV [0x10], A     ; store it
+//! HALT
+//! ```
+//!
+//! A line can start with a `label:`, which can then be used in place of any
+//! number. Labels are addresses relative to the start of the program, so it
+//! has to be loaded at 0 for them to be right.
+//!
+//! ```text
+//! loop:
+//!     DEC A
+//!     JNZ loop
+//! ```
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+
+use crate::{
+    cpu::{ByteRegister, Register},
+    instr::{AddrExpr, Alu, AluOp, AluSource, Indexed, Instruction, Logic, Move, ShiftOp},
+};
+
+#[derive(Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl core::fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Size {
+    Dword,
+    Word,
+    Byte,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Operand {
+    Reg(Register),
+    /// `AL` or `AH` style byte registers, plain `A` is `Reg`
+    ByteReg(ByteRegister),
+    Imm(u32, Size),
+    Mem(u32, Size),
+    RegIndirect(Register),
+    /// `[reg + displacement]`, plain `[reg]` is `RegIndirect`
+    RegOffset(Register, i32),
+    Indexed(Indexed),
+}
+
+/// Label addresses, `None` while they're still being collected in the first
+/// pass, where every label resolves to 0. This doesn't change the size of
+/// anything since label operands are always 32 bits.
+type Labels<'src> = Option<BTreeMap<&'src str, u32>>;
+
+pub fn assemble(src: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut labels = BTreeMap::new();
+    let mut addr = 0u32;
+
+    for (index, line) in src.lines().enumerate() {
+        let to_error = |message| AssembleError {
+            line: index + 1,
+            message,
+        };
+
+        let (label, code) = split_label(line).map_err(to_error)?;
+        if let Some(label) = label {
+            if labels.insert(label, addr).is_some() {
+                return Err(to_error(format!("Duplicate label '{label}'")));
+            }
+        }
+
+        if let Some(instr) = parse_line(code, &None).map_err(to_error)? {
+            addr = addr.wrapping_add(instr.encode().map_err(to_error)?.len() as u32);
+        }
+    }
+
+    let labels = Some(labels);
+    let mut bytes = Vec::new();
+
+    for (index, line) in src.lines().enumerate() {
+        let to_error = |message| AssembleError {
+            line: index + 1,
+            message,
+        };
+
+        let (_, code) = split_label(line).map_err(to_error)?;
+        if let Some(instr) = parse_line(code, &labels).map_err(to_error)? {
+            bytes.extend(instr.encode().map_err(to_error)?);
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Splits a leading `label:` off of the code on a line, comments included
+fn split_label(line: &str) -> Result<(Option<&str>, &str), String> {
+    let line = match line.split_once(';') {
+        Some((code, _comment)) => code,
+        None => line,
+    }
+    .trim();
+
+    match line.split_once(':') {
+        Some((label, code)) => {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(format!("Invalid label '{label}'"));
+            }
+
+            Ok((Some(label), code.trim()))
+        }
+        None => Ok((None, line)),
+    }
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && parse_register(name).is_none()
+}
+
+fn parse_line(line: &str, labels: &Labels) -> Result<Option<Instruction>, String> {
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    let (mnemonic, operands) = match line.split_once(char::is_whitespace) {
+        Some((mnemonic, operands)) => (mnemonic, operands.trim()),
+        None => (line, ""),
+    };
+
+    let operands = if operands.is_empty() {
+        Vec::new()
+    } else {
+        operands
+            .split(',')
+            .map(|operand| parse_operand(operand.trim(), labels))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    parse_instruction(&mnemonic.to_ascii_uppercase(), &operands).map(Some)
+}
+
+fn parse_instruction(mnemonic: &str, operands: &[Operand]) -> Result<Instruction, String> {
+    use Operand::*;
+
+    Ok(match (mnemonic, operands) {
+        ("HALT", []) => Instruction::Halt,
+        ("RET", []) => Instruction::Ret,
+        ("NOP", []) => Instruction::Nop,
+        ("IRET", []) => Instruction::Iret,
+        ("PUSHA", []) => Instruction::PushAll,
+        ("POPA", []) => Instruction::PopAll,
+        ("CLI", []) => Instruction::Cli,
+        ("STI", []) => Instruction::Sti,
+
+        ("JMP", [Imm(addr, Size::Dword)]) => Instruction::Jump(*addr),
+        ("JZ", [Imm(addr, Size::Dword)]) => Instruction::JumpIfZero(*addr),
+        ("JNZ", [Imm(addr, Size::Dword)]) => Instruction::JumpIfNotZero(*addr),
+        ("JMPR", [Imm(offset, Size::Dword)]) => Instruction::JumpRel(*offset as i32),
+        ("JL", [Imm(addr, Size::Dword)]) => Instruction::JumpIfLess(*addr),
+        ("JGE", [Imm(addr, Size::Dword)]) => Instruction::JumpIfGreaterOrEqual(*addr),
+        ("JG", [Imm(addr, Size::Dword)]) => Instruction::JumpIfGreater(*addr),
+        ("JLE", [Imm(addr, Size::Dword)]) => Instruction::JumpIfLessOrEqual(*addr),
+        ("BEQ", [Reg(reg_a), Reg(reg_b), Imm(addr, Size::Dword)]) => {
+            Instruction::BranchIfEqual(*reg_a, *reg_b, *addr)
+        }
+        ("BNE", [Reg(reg_a), Reg(reg_b), Imm(addr, Size::Dword)]) => {
+            Instruction::BranchIfNotEqual(*reg_a, *reg_b, *addr)
+        }
+        ("CALL", [Imm(addr, Size::Dword)]) => Instruction::Call(*addr),
+
+        ("PUSH", [Reg(reg)]) => Instruction::Push(*reg),
+        ("POP", [Reg(reg)]) => Instruction::Pop(*reg),
+        ("NEG", [Reg(reg)]) => Instruction::Neg(*reg),
+        ("INC", [Reg(reg)]) => Instruction::Inc(*reg),
+        ("DEC", [Reg(reg)]) => Instruction::Dec(*reg),
+
+        ("MOV", [dst, src]) => Instruction::Move(parse_move(*dst, *src)?),
+        ("LEA", [Reg(dst), RegIndirect(base)]) => Instruction::Lea(
+            *dst,
+            AddrExpr {
+                base: *base,
+                displacement: 0,
+            },
+        ),
+        ("LEA", [Reg(dst), RegOffset(base, displacement)]) => Instruction::Lea(
+            *dst,
+            AddrExpr {
+                base: *base,
+                displacement: *displacement,
+            },
+        ),
+
+        ("ADD" | "SUB" | "AND" | "OR" | "XOR" | "CMP", [Reg(dst), src]) => {
+            Instruction::Alu(parse_alu(mnemonic, *dst, *src)?)
+        }
+        ("NOT", [Reg(reg)]) => Instruction::Logic(Logic::Not(*reg)),
+
+        ("SWAP", [Reg(reg_a), Reg(reg_b)]) => Instruction::Swap(*reg_a, *reg_b),
+        ("XCHG", [Reg(reg), Mem(addr, Size::Dword)]) => Instruction::Xchg(*reg, *addr),
+        ("MUL", [Reg(dst), Reg(src)]) => Instruction::Mul(*src, *dst),
+        ("DIV", [Reg(dst), Reg(src)]) => Instruction::Div(*src, *dst),
+
+        ("SHL" | "SHR" | "SAR" | "ROL" | "ROR", [Reg(reg), Imm(count, Size::Dword)]) => {
+            Instruction::Shift(parse_shift(mnemonic, *reg, *count)?)
+        }
+
+        _ => return Err(format!("Invalid operands for {mnemonic}: {operands:?}")),
+    })
+}
+
+/// Always assembles to the `Alu` group, the older groups it replaced are only
+/// decoded
+fn parse_alu(mnemonic: &str, dst: Register, src: Operand) -> Result<Alu, String> {
+    let op = match mnemonic {
+        "ADD" => AluOp::Add,
+        "SUB" => AluOp::Sub,
+        "AND" => AluOp::And,
+        "OR" => AluOp::Or,
+        "XOR" => AluOp::Xor,
+        _ => AluOp::Cmp,
+    };
+    let src = match src {
+        Operand::Reg(src) => AluSource::Reg(src),
+        Operand::Imm(imm, Size::Dword) => AluSource::Imm(imm),
+        Operand::Mem(addr, Size::Dword) => AluSource::Mem(addr),
+        _ => {
+            return Err(format!(
+                "Invalid operands for {mnemonic}: {:?}",
+                [Operand::Reg(dst), src]
+            ))
+        }
+    };
+
+    Ok(Alu { op, src, dst })
+}
+
+fn parse_move(dst: Operand, src: Operand) -> Result<Move, String> {
+    use Operand::*;
+
+    if let (Some(dst_size), Some(src_size)) = (explicit_size(dst), explicit_size(src)) {
+        if dst_size != src_size {
+            return Err(format!(
+                "Mismatched operand sizes for MOV: {dst_size:?} and {src_size:?}"
+            ));
+        }
+    }
+
+    Ok(match (dst, src) {
+        (Reg(dst), Reg(src)) => Move::RegToReg(src, dst),
+
+        (Reg(dst), Imm(imm, Size::Dword)) => Move::ImmToReg32(imm, dst),
+        (Reg(dst), Imm(imm, Size::Word)) => Move::ImmToReg16(imm16(imm)?, dst),
+        (Reg(dst), Imm(imm, Size::Byte)) => Move::ImmToReg8(imm8(imm)?, dst.low()),
+        (ByteReg(dst), Imm(imm, _)) => Move::ImmToReg8(imm8(imm)?, dst),
+
+        (Mem(addr, Size::Dword), Reg(src)) => Move::RegToMem32(src, addr),
+        (Mem(addr, Size::Word), Reg(src)) => Move::RegToMem16(src, addr),
+        (Mem(addr, Size::Byte), Reg(src)) => Move::RegToMem8(src.low(), addr),
+        (Mem(addr, _), ByteReg(src)) => Move::RegToMem8(src, addr),
+
+        (Reg(dst), Mem(addr, Size::Dword)) => Move::MemToReg32(addr, dst),
+        (Reg(dst), Mem(addr, Size::Word)) => Move::MemToReg16(addr, dst),
+        (Reg(dst), Mem(addr, Size::Byte)) => Move::MemToReg8(addr, dst.low()),
+        (ByteReg(dst), Mem(addr, _)) => Move::MemToReg8(addr, dst),
+
+        (Mem(addr, Size::Dword), Imm(imm, Size::Dword)) => Move::ImmToMem32(imm, addr),
+        (Mem(addr, Size::Word), Imm(imm, _)) | (Mem(addr, _), Imm(imm, Size::Word)) => {
+            Move::ImmToMem16(imm16(imm)?, addr)
+        }
+        (Mem(addr, Size::Byte), Imm(imm, _)) | (Mem(addr, _), Imm(imm, Size::Byte)) => {
+            Move::ImmToMem8(imm8(imm)?, addr)
+        }
+
+        (Reg(dst), RegIndirect(src)) => Move::RegIndirectToReg(src, dst),
+        (RegIndirect(dst), Reg(src)) => Move::RegToRegIndirect(src, dst),
+
+        (Reg(dst), Indexed(indexed)) => Move::IndexedToReg(indexed, dst),
+        (Indexed(indexed), Reg(src)) => Move::RegToIndexed(src, indexed),
+
+        (Mem(addr_dst, Size::Dword), Mem(addr_src, Size::Dword)) => {
+            Move::MemToMem32(addr_src, addr_dst)
+        }
+        (Mem(addr_dst, Size::Word), Mem(addr_src, _))
+        | (Mem(addr_dst, _), Mem(addr_src, Size::Word)) => Move::MemToMem16(addr_src, addr_dst),
+        (Mem(addr_dst, Size::Byte), Mem(addr_src, _))
+        | (Mem(addr_dst, _), Mem(addr_src, Size::Byte)) => Move::MemToMem8(addr_src, addr_dst),
+
+        _ => return Err(format!("Invalid operands for MOV: {dst:?}, {src:?}")),
+    })
+}
+
+/// The size an operand was written with, unsized operands are `None` and
+/// byte registers are always `Size::Byte`
+fn explicit_size(operand: Operand) -> Option<Size> {
+    match operand {
+        Operand::Imm(_, size) | Operand::Mem(_, size) if size != Size::Dword => Some(size),
+        Operand::ByteReg(_) => Some(Size::Byte),
+        _ => None,
+    }
+}
+
+// negative immediates were parsed as 32 bit two's complement, so they fit if
+// they're sign extended from the smaller width
+fn imm16(imm: u32) -> Result<u16, String> {
+    u16::try_from(imm)
+        .or_else(|_| i16::try_from(imm as i32).map(|imm| imm as u16))
+        .map_err(|_| format!("Immediate 0x{imm:x} doesn't fit in a WORD"))
+}
+
+fn imm8(imm: u32) -> Result<u8, String> {
+    u8::try_from(imm)
+        .or_else(|_| i8::try_from(imm as i32).map(|imm| imm as u8))
+        .map_err(|_| format!("Immediate 0x{imm:x} doesn't fit in a BYTE"))
+}
+
+fn parse_shift(mnemonic: &str, reg: Register, count: u32) -> Result<ShiftOp, String> {
+    let count =
+        u8::try_from(count).map_err(|_| format!("Shift count {count} doesn't fit in a byte"))?;
+
+    Ok(match mnemonic {
+        "SHL" => ShiftOp::Shl(reg, count),
+        "SHR" => ShiftOp::Shr(reg, count),
+        "SAR" => ShiftOp::Sar(reg, count),
+        "ROL" => ShiftOp::Rol(reg, count),
+        _ => ShiftOp::Ror(reg, count),
+    })
+}
+
+fn parse_operand(operand: &str, labels: &Labels) -> Result<Operand, String> {
+    let (size, operand) = match operand.split_once(char::is_whitespace) {
+        Some((size, rest)) if size.eq_ignore_ascii_case("WORD") => (Size::Word, rest.trim()),
+        Some((size, rest)) if size.eq_ignore_ascii_case("BYTE") => (Size::Byte, rest.trim()),
+        _ => (Size::Dword, operand),
+    };
+
+    if let Some(addr) = operand
+        .strip_prefix('[')
+        .and_then(|operand| operand.strip_suffix(']'))
+    {
+        let addr = addr.trim();
+
+        if let Some(reg) = parse_register(addr) {
+            if size != Size::Dword {
+                return Err(format!("Register indirect [{addr}] can't have a size"));
+            }
+
+            return Ok(Operand::RegIndirect(reg));
+        }
+
+        if let Some(operand) = parse_reg_offset(addr, labels)? {
+            if size != Size::Dword {
+                return Err(format!("Register offset [{addr}] can't have a size"));
+            }
+
+            return Ok(operand);
+        }
+
+        return Ok(Operand::Mem(parse_value(addr, labels)?, size));
+    }
+
+    if let Some(reg) = parse_register(operand) {
+        if size != Size::Dword {
+            return Err(format!("Register {operand} can't have a size"));
+        }
+
+        return Ok(Operand::Reg(reg));
+    }
+
+    if let Ok(reg) = operand.parse() {
+        if size != Size::Dword {
+            return Err(format!("Register {operand} can't have a size"));
+        }
+
+        return Ok(Operand::ByteReg(reg));
+    }
+
+    Ok(Operand::Imm(parse_value(operand, labels)?, size))
+}
+
+/// Parses `reg + displacement`, `reg - displacement` or
+/// `base + index * scale`, `None` if it doesn't start with a register
+fn parse_reg_offset(addr: &str, labels: &Labels) -> Result<Option<Operand>, String> {
+    let (base, displacement) = match addr.find(['+', '-']) {
+        Some(index) => addr.split_at(index),
+        None => return Ok(None),
+    };
+    let reg = match parse_register(base.trim()) {
+        Some(reg) => reg,
+        None => return Ok(None),
+    };
+
+    let negative = displacement.starts_with('-');
+    let displacement = displacement[1..].trim();
+
+    let (index, scale) = match displacement.split_once('*') {
+        Some((index, scale)) => (index.trim(), parse_number(scale.trim())?),
+        None => (displacement, 1),
+    };
+    if let Some(index) = parse_register(index) {
+        if negative {
+            return Err(format!("Index register {index} can't be subtracted"));
+        }
+        let scale = u8::try_from(scale)
+            .ok()
+            .filter(|scale| [1, 2, 4].contains(scale))
+            .ok_or_else(|| format!("Invalid scale {scale}, must be 1, 2 or 4"))?;
+
+        return Ok(Some(Operand::Indexed(Indexed {
+            base: reg,
+            index,
+            scale,
+        })));
+    }
+
+    let value = parse_value(displacement, labels)? as i32;
+    let displacement = if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    };
+
+    Ok(Some(Operand::RegOffset(reg, displacement)))
+}
+
+fn parse_value(value: &str, labels: &Labels) -> Result<u32, String> {
+    if !is_label(value) {
+        return parse_number(value);
+    }
+
+    match labels {
+        Some(labels) => labels
+            .get(value)
+            .copied()
+            .ok_or_else(|| format!("Undefined label '{value}'")),
+        None => Ok(0),
+    }
+}
+
+fn parse_register(name: &str) -> Option<Register> {
+    name.parse().ok()
+}
+
+// negative numbers are stored as their two's complement
+fn parse_number(number: &str) -> Result<u32, String> {
+    let (negative, digits) = match number.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, number),
+    };
+
+    // `_` can be used to group digits
+    let digits = digits.replace('_', "");
+    let parsed = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => digits.parse::<u32>(),
+    };
+
+    match parsed {
+        Ok(value) if negative => Ok(value.wrapping_neg()),
+        Ok(value) => Ok(value),
+        Err(_) => Err(format!("Invalid operand: '{number}'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec};
+
+    use super::*;
+    use crate::{cpu::MemIterator, instr::ReadMem};
+
+    fn alu(op: AluOp, src: AluSource, dst: Register) -> Instruction {
+        Instruction::Alu(Alu { op, src, dst })
+    }
+
+    fn decode_all(bytes: &[u8]) -> Vec<Instruction> {
+        let mut instrs = Vec::new();
+        let mut index = 0;
+
+        while index < bytes.len() {
+            let parsed = Instruction::read(MemIterator::new(index, bytes)).expect("should read");
+
+            index += parsed.delta_ip as usize;
+            instrs.push(parsed.instr);
+        }
+
+        instrs
+    }
+
+    #[test]
+    fn assemble_program() {
+        let src = r#"
+            ; load some values
+            MOV A, 0x42
+            mov   b ,  10       ; decimal works too
+            MOV X, WORD 0xBEEF
+            MOV [0x10], A
+            MOV BYTE [0x14], B
+            MOV Y, [0x10]
+            MOV [0x20], [0x10]
+
+            ADD A, B
+            SUB A, 1
+            PUSH A
+            POP SP
+            CALL 0x100
+            JZ 0x0
+            RET
+            NEG B
+            XOR A, A
+            NOT X
+            JMPR -0x10
+            SHL A, 4
+            ROR B, 0x1F
+            CMP A, B
+            CMP X, 3
+            INC A
+            DEC B
+            NOP
+            SWAP X, Y
+            XCHG B, [0x20]
+            MUL A, B
+            DIV X, A
+            LEA A, [X + 0x4]
+            LEA B, [SP - 8]
+            BEQ A, B, 0x100
+            BNE X, Y, 0x0
+            JL 0x10
+            JGE 0x14
+            JG 0x18
+            JLE 0x1C
+            STI
+            CLI
+            IRET
+            PUSHA
+            POPA
+            HALT
+        "#;
+
+        let bytes = assemble(src).expect("should assemble");
+
+        assert_eq!(
+            decode_all(&bytes),
+            vec![
+                Instruction::Move(Move::ImmToReg32(0x42, Register::A)),
+                Instruction::Move(Move::ImmToReg32(10, Register::B)),
+                Instruction::Move(Move::ImmToReg16(0xBEEF, Register::X)),
+                Instruction::Move(Move::RegToMem32(Register::A, 0x10)),
+                Instruction::Move(Move::RegToMem8(Register::B.low(), 0x14)),
+                Instruction::Move(Move::MemToReg32(0x10, Register::Y)),
+                Instruction::Move(Move::MemToMem32(0x10, 0x20)),
+                alu(AluOp::Add, AluSource::Reg(Register::B), Register::A),
+                alu(AluOp::Sub, AluSource::Imm(1), Register::A),
+                Instruction::Push(Register::A),
+                Instruction::Pop(Register::Sp),
+                Instruction::Call(0x100),
+                Instruction::JumpIfZero(0x0),
+                Instruction::Ret,
+                Instruction::Neg(Register::B),
+                alu(AluOp::Xor, AluSource::Reg(Register::A), Register::A),
+                Instruction::Logic(Logic::Not(Register::X)),
+                Instruction::JumpRel(-0x10),
+                Instruction::Shift(ShiftOp::Shl(Register::A, 4)),
+                Instruction::Shift(ShiftOp::Ror(Register::B, 0x1F)),
+                alu(AluOp::Cmp, AluSource::Reg(Register::B), Register::A),
+                alu(AluOp::Cmp, AluSource::Imm(3), Register::X),
+                Instruction::Inc(Register::A),
+                Instruction::Dec(Register::B),
+                Instruction::Nop,
+                Instruction::Swap(Register::X, Register::Y),
+                Instruction::Xchg(Register::B, 0x20),
+                Instruction::Mul(Register::B, Register::A),
+                Instruction::Div(Register::A, Register::X),
+                Instruction::Lea(
+                    Register::A,
+                    AddrExpr {
+                        base: Register::X,
+                        displacement: 4,
+                    },
+                ),
+                Instruction::Lea(
+                    Register::B,
+                    AddrExpr {
+                        base: Register::Sp,
+                        displacement: -8,
+                    },
+                ),
+                Instruction::BranchIfEqual(Register::A, Register::B, 0x100),
+                Instruction::BranchIfNotEqual(Register::X, Register::Y, 0x0),
+                Instruction::JumpIfLess(0x10),
+                Instruction::JumpIfGreaterOrEqual(0x14),
+                Instruction::JumpIfGreater(0x18),
+                Instruction::JumpIfLessOrEqual(0x1C),
+                Instruction::Sti,
+                Instruction::Cli,
+                Instruction::Iret,
+                Instruction::PushAll,
+                Instruction::PopAll,
+                Instruction::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_display_round_trip() {
+        let instrs = [
+            Instruction::Move(Move::MemToMem16(0x4, 0x8)),
+            Instruction::Move(Move::ImmToReg8(0x42, Register::Y.low())),
+            Instruction::Move(Move::ImmToReg8(0x42, Register::Y.high())),
+            Instruction::Move(Move::RegToMem8(Register::B.high(), 0x10)),
+            Instruction::Move(Move::MemToReg8(0x10, Register::X.high())),
+            alu(AluOp::Add, AluSource::Mem(0x4), Register::X),
+            alu(AluOp::And, AluSource::Imm(0xFF), Register::B),
+            Instruction::JumpIfNotZero(0x100),
+            Instruction::JumpRel(-42),
+            Instruction::Move(Move::RegIndirectToReg(Register::X, Register::A)),
+            Instruction::Move(Move::RegToRegIndirect(Register::A, Register::Y)),
+            Instruction::Move(Move::ImmToMem32(0xDEAD_BEEF, 0x10)),
+            Instruction::Move(Move::ImmToMem16(0xBEEF, 0x10)),
+            Instruction::Move(Move::ImmToMem8(0xEF, 0x10)),
+            Instruction::Move(Move::IndexedToReg(
+                Indexed {
+                    base: Register::X,
+                    index: Register::Y,
+                    scale: 4,
+                },
+                Register::A,
+            )),
+            Instruction::Move(Move::RegToIndexed(
+                Register::A,
+                Indexed {
+                    base: Register::Sp,
+                    index: Register::B,
+                    scale: 1,
+                },
+            )),
+        ];
+
+        for instr in instrs {
+            let bytes = assemble(&instr.to_string()).expect("should assemble");
+
+            assert_eq!(decode_all(&bytes), vec![instr]);
+        }
+
+        // the older ALU groups assemble to the ALU encoding
+        for instr in Instruction::variants_sample() {
+            if let Some(alu_instr) = instr.as_alu() {
+                let bytes = assemble(&instr.to_string()).expect("should assemble");
+
+                assert_eq!(decode_all(&bytes), vec![Instruction::Alu(alu_instr)]);
+            }
+        }
+    }
+
+    #[test]
+    fn byte_registers() {
+        let bytes = assemble(
+            r#"
+            MOV AH, 0x12
+            MOV al, 0x34
+            MOV BYTE [0x10], XH
+            MOV YL, [0x10]
+        "#,
+        )
+        .expect("should assemble");
+
+        assert_eq!(
+            decode_all(&bytes),
+            vec![
+                Instruction::Move(Move::ImmToReg8(0x12, Register::A.high())),
+                Instruction::Move(Move::ImmToReg8(0x34, Register::A.low())),
+                Instruction::Move(Move::RegToMem8(Register::X.high(), 0x10)),
+                Instruction::Move(Move::MemToReg8(0x10, Register::Y.low())),
+            ]
+        );
+        assert!(assemble("MOV AH, 0x100").is_err());
+        assert!(assemble("MOV SPH, 0x1").is_err());
+    }
+
+    #[test]
+    fn countdown_loop() {
+        let src = r#"
+            MOV A, 5
+        loop:
+            INC B
+            DEC A
+            JNZ loop      ; back to the INC
+            JMP done
+            MOV B, 0xFF   ; skipped
+        done: HALT
+        "#;
+
+        let bytes = assemble(src).expect("should assemble");
+        let instrs = decode_all(&bytes);
+        assert_eq!(instrs[3], Instruction::JumpIfNotZero(7));
+        assert_eq!(instrs[4], Instruction::Jump(28));
+
+        let mut machine = crate::Machine::new();
+        machine.load_program(0, &bytes).expect("should load");
+
+        assert_eq!(machine.run_until_halt(100), crate::RunResult::Halted);
+        assert_eq!(machine.cpu.registers.a, 0);
+        assert_eq!(machine.cpu.registers.b, 5);
+    }
+
+    #[test]
+    fn labels_as_addresses() {
+        let src = "MOV A, [data]\nMOV X, data\nHALT\ndata: NOP";
+
+        assert_eq!(
+            decode_all(&assemble(src).expect("should assemble")),
+            vec![
+                Instruction::Move(Move::MemToReg32(15, Register::A)),
+                Instruction::Move(Move::ImmToReg32(15, Register::X)),
+                Instruction::Halt,
+                Instruction::Nop,
+            ]
+        );
+    }
+
+    #[test]
+    fn label_errors() {
+        let err = assemble("NOP\nJMP nowhere").expect_err("should fail");
+        assert_eq!(err.line, 2);
+        assert_eq!(err.message, "Undefined label 'nowhere'");
+
+        let err = assemble("top:\nNOP\ntop: HALT").expect_err("should fail");
+        assert_eq!(err.line, 3);
+        assert_eq!(err.message, "Duplicate label 'top'");
+
+        let err = assemble("A: HALT").expect_err("should fail");
+        assert_eq!(err.message, "Invalid label 'A'");
+
+        let err = assemble("1st: HALT").expect_err("should fail");
+        assert_eq!(err.message, "Invalid label '1st'");
+    }
+
+    #[test]
+    fn immediates_must_fit() {
+        assert_eq!(
+            assemble("MOV A, BYTE 0x100")
+                .expect_err("should fail")
+                .message,
+            "Immediate 0x100 doesn't fit in a BYTE"
+        );
+        assert_eq!(
+            assemble("MOV A, WORD 0x1_0000")
+                .expect_err("should fail")
+                .message,
+            "Immediate 0x10000 doesn't fit in a WORD"
+        );
+        assert!(assemble("MOV BYTE [0x10], 0x1FF").is_err());
+        assert!(assemble("MOV BYTE [0x10], -129").is_err());
+
+        assert_eq!(
+            decode_all(
+                &assemble(
+                    "MOV A, BYTE 0xFF\nMOV B, BYTE -1\nMOV WORD [0x10], 0xFFFF\nMOV X, WORD -32768"
+                )
+                .expect("should assemble")
+            ),
+            vec![
+                Instruction::Move(Move::ImmToReg8(0xFF, Register::A.low())),
+                Instruction::Move(Move::ImmToReg8(0xFF, Register::B.low())),
+                Instruction::Move(Move::ImmToMem16(0xFFFF, 0x10)),
+                Instruction::Move(Move::ImmToReg16(0x8000, Register::X)),
+            ]
+        );
+    }
+
+    #[test]
+    fn mismatched_sizes() {
+        for src in [
+            "MOV WORD [0x10], BYTE 5",
+            "MOV BYTE [0x10], WORD 5",
+            "MOV WORD [0x10], BYTE [0x20]",
+            "MOV WORD [0x10], AL",
+            "MOV AH, WORD [0x10]",
+            "MOV AL, WORD 5",
+        ] {
+            assert!(
+                assemble(src)
+                    .expect_err(src)
+                    .message
+                    .starts_with("Mismatched operand sizes for MOV"),
+                "{src}"
+            );
+        }
+
+        assert_eq!(
+            decode_all(
+                &assemble("MOV WORD [0x10], WORD 5\nMOV BYTE [0x10], AL\nMOV AL, BYTE [0x10]")
+                    .expect("should assemble")
+            ),
+            vec![
+                Instruction::Move(Move::ImmToMem16(5, 0x10)),
+                Instruction::Move(Move::RegToMem8(Register::A.low(), 0x10)),
+                Instruction::Move(Move::MemToReg8(0x10, Register::A.low())),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let err = assemble("HALT\n\nMOV A, B\nFOO A").expect_err("should fail");
+        assert_eq!(err.line, 4);
+
+        let err = assemble("MOV A, 0xZZ").expect_err("should fail");
+        assert_eq!(err.line, 1);
+
+        let err = assemble("ADD 0x1, A").expect_err("should fail");
+        assert_eq!(err.line, 1);
+
+        let err = assemble("NOP\nPUSH 0x1").expect_err("should fail");
+        assert_eq!(err.line, 2);
+    }
+}
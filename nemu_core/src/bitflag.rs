@@ -1,13 +1,13 @@
 // Copyright (C) 2023  Patrick Cleavelin <patrick@spacegirl.nl>
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
 pub struct Bitflag<T> {
     value: T,
 }
 
 impl<T> Bitflag<T>
 where
-    T: std::ops::BitAnd + Default + Copy,
+    T: core::ops::BitAnd + Default + Copy,
 {
     pub fn value(&self) -> T {
         self.value
@@ -15,10 +15,31 @@ where
 
     pub fn contains(&self, v: T) -> bool
     where
-        <T as std::ops::BitAnd>::Output: PartialEq<T>,
+        <T as core::ops::BitAnd>::Output: PartialEq<T>,
     {
         (self.value & v) != T::default()
     }
+
+    pub fn set(&mut self, bit: T)
+    where
+        T: core::ops::BitOrAssign,
+    {
+        self.value |= bit;
+    }
+
+    pub fn clear(&mut self, bit: T)
+    where
+        T: core::ops::Not<Output = T> + core::ops::BitAndAssign,
+    {
+        self.value &= !bit;
+    }
+
+    pub fn toggle(&mut self, bit: T)
+    where
+        T: core::ops::BitXorAssign,
+    {
+        self.value ^= bit;
+    }
 }
 
 impl<T> From<T> for Bitflag<T> {
@@ -27,33 +48,48 @@ impl<T> From<T> for Bitflag<T> {
     }
 }
 
-impl<T> std::ops::BitOrAssign<T> for Bitflag<T>
+impl<T> core::ops::BitOrAssign<T> for Bitflag<T>
 where
-    T: std::ops::BitOrAssign,
+    T: core::ops::BitOrAssign,
 {
     fn bitor_assign(&mut self, rhs: T) {
         self.value |= rhs
     }
 }
 
-impl<T> std::ops::BitAndAssign<T> for Bitflag<T>
+impl<T> core::ops::BitAndAssign<T> for Bitflag<T>
 where
-    T: std::ops::BitAndAssign,
+    T: core::ops::BitAndAssign,
 {
     fn bitand_assign(&mut self, rhs: T) {
         self.value &= rhs
     }
 }
 
-impl<T> std::ops::BitXorAssign<T> for Bitflag<T>
+impl<T> core::ops::BitXorAssign<T> for Bitflag<T>
 where
-    T: std::ops::BitXorAssign,
+    T: core::ops::BitXorAssign,
 {
     fn bitxor_assign(&mut self, rhs: T) {
         self.value ^= rhs
     }
 }
 
+// only the inner value gets serialized
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for Bitflag<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for Bitflag<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self::from)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -83,5 +119,32 @@ mod test {
             assert!(bf.contains(0b1000));
             assert_eq!(bf.value(), 0b1000);
         }
+
+        #[test]
+        fn set_clear_toggle() {
+            let mut bf = Bitflag::<u8>::default();
+            assert_eq!(bf.value(), 0b0000);
+
+            bf.set(0b0010);
+
+            assert!(bf.contains(0b0010));
+            assert_eq!(bf.value(), 0b0010);
+
+            bf.toggle(0b1000);
+
+            assert!(bf.contains(0b1000));
+            assert_eq!(bf.value(), 0b1010);
+
+            bf.clear(0b0010);
+
+            assert!(!bf.contains(0b0010));
+            assert!(bf.contains(0b1000));
+            assert_eq!(bf.value(), 0b1000);
+
+            bf.toggle(0b1000);
+
+            assert!(!bf.contains(0b1000));
+            assert_eq!(bf.value(), 0b0000);
+        }
     }
 }
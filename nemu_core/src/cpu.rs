@@ -2,37 +2,251 @@
 
 //! Main CPU related logic/data structures
 
+use alloc::{boxed::Box, collections::BTreeSet, format, string::String, vec::Vec};
+use core::{marker::PhantomData, ops::Range};
+
 use crate::{
     bitflag::Bitflag,
-    instr::{self, Instruction, ReadMem},
+    device::Device,
+    instr::{self, DecodeError, DecodeOptions, Instruction},
+    mem::{MemSource, Memory},
 };
 
-const MAX_MEM: usize = 0x1000_0000;
+/// How much memory `Cpu::new` gives the machine
+pub(crate) const DEFAULT_MEM_SIZE: usize = 0x1000_0000;
+
+pub const ZERO: u8 = 0b0000_0001;
+pub const CARRY: u8 = 0b0000_0010;
+/// Interrupts are only taken while this is set, see `Cpu::interrupt`
+pub const INTERRUPT: u8 = 0b0000_0100;
+/// Set when the result of an addition or subtraction doesn't fit as a signed
+/// number
+pub const OVERFLOW: u8 = 0b0000_1000;
+/// The top bit of the last arithmetic result
+pub const SIGN: u8 = 0b0001_0000;
+
+/// Where the table of 256 little endian handler addresses, indexed by the
+/// interrupt vector, lives in memory
+pub const INTERRUPT_VECTOR_BASE: u32 = 0x0F00_0000;
+
+/// How wide the registers and addresses of a `Cpu` are. Instructions are
+/// encoded the same way for every width, their operands are cut down to fit
+/// when they execute.
+pub trait Word: Copy + core::fmt::Debug + Into<u32> + 'static {
+    const BITS: u32;
+    /// Every bit of a word set, which is also the highest address
+    const MASK: u32 = u32::MAX >> (32 - Self::BITS);
+    const BYTES: u32 = Self::BITS / 8;
+
+    /// The low `BITS` of `value`
+    fn truncate(value: u32) -> Self;
+
+    fn wrap(value: u32) -> u32 {
+        value & Self::MASK
+    }
+
+    /// Whether the top bit of the word is set
+    fn is_negative(value: u32) -> bool {
+        (value >> (Self::BITS - 1)) & 1 == 1
+    }
+}
+
+impl Word for u16 {
+    const BITS: u32 = 16;
+
+    fn truncate(value: u32) -> Self {
+        value as u16
+    }
+}
+
+impl Word for u32 {
+    const BITS: u32 = 32;
+
+    fn truncate(value: u32) -> Self {
+        value
+    }
+}
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Register {
     A,
     B,
     X,
     Y,
+    Sp,
     Ip,
 }
 
 impl Register {
-    pub(crate) fn try_from_id(id: u8) -> Result<Self, String> {
+    pub(crate) fn try_from_id(id: u8) -> Result<Self, DecodeError> {
         match id {
             0x0 => Ok(Self::A),
             0x1 => Ok(Self::B),
             0x2 => Ok(Self::X),
             0x3 => Ok(Self::Y),
-            _ => Err(format!("Got invalid register id: 0x{id:01x}")),
+            0x4 => Ok(Self::Sp),
+            0x5 => Ok(Self::Ip),
+            _ => Err(DecodeError::InvalidRegister(id)),
+        }
+    }
+
+    /// The byte this register is encoded as, the inverse of `try_from_id`
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::A => 0x0,
+            Self::B => 0x1,
+            Self::X => 0x2,
+            Self::Y => 0x3,
+            Self::Sp => 0x4,
+            Self::Ip => 0x5,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::A => "A",
+            Self::B => "B",
+            Self::X => "X",
+            Self::Y => "Y",
+            Self::Sp => "SP",
+            Self::Ip => "IP",
+        }
+    }
+}
+
+impl Register {
+    /// The low byte of this register, like `AL`
+    pub fn low(self) -> ByteRegister {
+        ByteRegister {
+            reg: self,
+            part: RegPart::Low,
+        }
+    }
+
+    /// The second lowest byte of this register, like `AH`
+    pub fn high(self) -> ByteRegister {
+        ByteRegister {
+            reg: self,
+            part: RegPart::High,
         }
     }
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RegPart {
+    /// Bits 0..8
+    Low,
+    /// Bits 8..16
+    High,
+}
+
+/// A single byte of a register, the operand of byte sized moves
+///
+/// It's encoded as the register's id, with the top bit set for the high byte.
+/// Only A, B, X and Y have an addressable high byte.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ByteRegister {
+    pub reg: Register,
+    pub part: RegPart,
+}
+
+const HIGH_BYTE_ID: u8 = 0x80;
+
+impl ByteRegister {
+    pub(crate) fn try_from_id(id: u8) -> Result<Self, DecodeError> {
+        if id & HIGH_BYTE_ID == 0 {
+            return Ok(Register::try_from_id(id)?.low());
+        }
+
+        match Register::try_from_id(id & !HIGH_BYTE_ID) {
+            Ok(reg @ (Register::A | Register::B | Register::X | Register::Y)) => Ok(reg.high()),
+            _ => Err(DecodeError::InvalidRegister(id)),
+        }
+    }
+
+    /// The byte this is encoded as, the inverse of `try_from_id`
+    pub fn id(&self) -> u8 {
+        match self.part {
+            RegPart::Low => self.reg.id(),
+            RegPart::High => self.reg.id() | HIGH_BYTE_ID,
+        }
+    }
+
+    fn shift(&self) -> u32 {
+        match self.part {
+            RegPart::Low => 0,
+            RegPart::High => 8,
+        }
+    }
+}
+
+impl core::fmt::Display for ByteRegister {
+    /// The low byte is written as just the register so existing listings
+    /// don't change, the high byte gets an `H` suffix
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.part {
+            RegPart::Low => write!(f, "{}", self.reg.name()),
+            RegPart::High => write!(f, "{}H", self.reg.name()),
+        }
+    }
+}
+
+impl core::str::FromStr for ByteRegister {
+    type Err = String;
+
+    /// Parses `AL` and `AH` style names, ignoring case. A plain register name
+    /// is its low byte.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if let Ok(reg) = name.parse::<Register>() {
+            return Ok(reg.low());
+        }
+
+        let (reg, part) = name.split_at(name.len().saturating_sub(1));
+        let reg = match reg.parse::<Register>() {
+            Ok(reg @ (Register::A | Register::B | Register::X | Register::Y)) => reg,
+            _ => return Err(format!("Unknown byte register '{name}'")),
+        };
+
+        match part {
+            "L" | "l" => Ok(reg.low()),
+            "H" | "h" => Ok(reg.high()),
+            _ => Err(format!("Unknown byte register '{name}'")),
+        }
+    }
+}
+
+impl From<Register> for u8 {
+    fn from(reg: Register) -> Self {
+        reg.id()
+    }
+}
+
+impl core::fmt::Display for Register {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl core::str::FromStr for Register {
+    type Err = String;
+
+    /// Parses a register name, ignoring case
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        (0..=Register::Ip.id())
+            .filter_map(|id| Register::try_from_id(id).ok())
+            .find(|reg| reg.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Unknown register '{name}'"))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CpuRegisters {
     pub instruction_pointer: u32,
+    pub stack_pointer: u32,
 
     pub a: u32,
     pub b: u32,
@@ -43,73 +257,242 @@ pub struct CpuRegisters {
     pub flags: Bitflag<u8>,
 }
 
+impl Default for CpuRegisters {
+    fn default() -> Self {
+        Self::for_mem_size(DEFAULT_MEM_SIZE)
+    }
+}
+
+impl CpuRegisters {
+    /// Zeroed registers, with the stack pointer at the top of `mem_size`
+    /// bytes of memory
+    pub fn for_mem_size(mem_size: usize) -> Self {
+        Self {
+            instruction_pointer: 0,
+            // the stack grows downwards from the top of memory
+            stack_pointer: mem_size as u32,
+
+            a: 0,
+            b: 0,
+
+            x: 0,
+            y: 0,
+
+            flags: Bitflag::default(),
+        }
+    }
+
+    pub fn zero(&self) -> bool {
+        self.flags.contains(ZERO)
+    }
+
+    pub fn carry(&self) -> bool {
+        self.flags.contains(CARRY)
+    }
+
+    pub fn interrupts_enabled(&self) -> bool {
+        self.flags.contains(INTERRUPT)
+    }
+
+    pub fn overflow(&self) -> bool {
+        self.flags.contains(OVERFLOW)
+    }
+
+    pub fn sign(&self) -> bool {
+        self.flags.contains(SIGN)
+    }
+
+    /// Whether the last comparison was signed less than, `SIGN != OVERFLOW`
+    pub fn less(&self) -> bool {
+        self.sign() != self.overflow()
+    }
+
+    pub fn set_zero(&mut self, zero: bool) {
+        self.set_flag(ZERO, zero);
+    }
+
+    pub fn set_carry(&mut self, carry: bool) {
+        self.set_flag(CARRY, carry);
+    }
+
+    pub fn set_interrupts_enabled(&mut self, enabled: bool) {
+        self.set_flag(INTERRUPT, enabled);
+    }
+
+    pub fn set_overflow(&mut self, overflow: bool) {
+        self.set_flag(OVERFLOW, overflow);
+    }
+
+    pub fn set_sign(&mut self, sign: bool) {
+        self.set_flag(SIGN, sign);
+    }
+
+    fn set_flag(&mut self, flag: u8, value: bool) {
+        if value {
+            self.flags.set(flag);
+        } else {
+            self.flags.clear(flag);
+        }
+    }
+}
+
 pub trait MemIter {
-    fn next8(&mut self) -> u8;
-    fn next16(&mut self) -> u16;
-    fn next32(&mut self) -> u32;
+    fn next8(&mut self) -> Result<u8, DecodeError>;
+    fn next16(&mut self) -> Result<u16, DecodeError>;
+    fn next32(&mut self) -> Result<u32, DecodeError>;
+
+    fn next_i8(&mut self) -> Result<i8, DecodeError> {
+        self.next8().map(|value| value as i8)
+    }
+    fn next_i16(&mut self) -> Result<i16, DecodeError> {
+        self.next16().map(|value| value as i16)
+    }
+    fn next_i32(&mut self) -> Result<i32, DecodeError> {
+        self.next32().map(|value| value as i32)
+    }
+    /// Reads `W::BYTES` bytes
+    fn next_word<W: Word>(&mut self) -> Result<W, DecodeError>
+    where
+        Self: Sized,
+    {
+        match W::BYTES {
+            2 => self.next16().map(u32::from),
+            _ => self.next32(),
+        }
+        .map(W::truncate)
+    }
+
+    fn peek8(&self) -> Result<u8, DecodeError>;
+    fn peek16(&self) -> Result<u16, DecodeError>;
+    fn peek32(&self) -> Result<u32, DecodeError>;
 
     fn travelled(&self) -> usize;
 }
 
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
 // TODO: move somewhere else
-pub struct MemIterator<'mem> {
+pub struct MemIterator<'mem, M: ?Sized> {
     index: usize,
     travelled: usize,
+    len: usize,
+    endian: Endian,
 
-    mem: &'mem [u8],
+    mem: &'mem M,
 }
 
-impl<'mem> MemIterator<'mem> {
-    pub(crate) fn new(start: usize, mem: &'mem [u8]) -> Self {
+impl<'mem, M: MemSource + ?Sized> MemIterator<'mem, M> {
+    pub fn new(start: usize, mem: &'mem M) -> Self {
+        Self::with_len(start, mem, mem.size())
+    }
+
+    /// Creates an iterator that reads multi-byte values as big-endian
+    pub fn new_be(start: usize, mem: &'mem M) -> Self {
+        Self {
+            endian: Endian::Big,
+            ..Self::new(start, mem)
+        }
+    }
+
+    /// Creates an iterator that can only travel `len` bytes before reads start
+    /// failing, such as when reading from a program of a known length
+    pub fn with_len(start: usize, mem: &'mem M, len: usize) -> Self {
         Self {
             index: start,
             travelled: 0,
+            len,
+            endian: Endian::default(),
             mem,
         }
     }
-}
 
-impl<'mem> MemIter for MemIterator<'mem> {
-    fn next32(&mut self) -> u32 {
-        if self.index >= self.mem.len() {
-            self.index = 0;
+    // a copy of the iterator that can be advanced without touching this one
+    fn lookahead(&self) -> Self {
+        Self {
+            index: self.index,
+            travelled: self.travelled,
+            len: self.len,
+            endian: self.endian,
+            mem: self.mem,
         }
+    }
 
-        let v = self.mem[self.index.wrapping_add(0)] as u32
-            | ((self.mem[self.index.wrapping_add(1)] as u32) << 8)
-            | ((self.mem[self.index.wrapping_add(2)] as u32) << 16)
-            | ((self.mem[self.index.wrapping_add(3)] as u32) << 24);
+    fn take(&mut self, count: usize) -> Result<(), DecodeError> {
+        let available = self.len.saturating_sub(self.travelled);
 
-        self.index += 4;
-        self.travelled += 4;
+        if count > available {
+            return Err(DecodeError::Truncated {
+                needed: count,
+                available,
+            });
+        }
 
-        v
+        Ok(())
     }
 
-    fn next16(&mut self) -> u16 {
-        if self.index >= self.mem.len() {
+    // wraps around the end of memory one byte at a time
+    fn read_byte(&mut self) -> u8 {
+        if self.index >= self.mem.size() {
             self.index = 0;
         }
 
-        let v = self.mem[self.index.wrapping_add(0)] as u16
-            | ((self.mem[self.index.wrapping_add(1)] as u16) << 8);
-
-        self.index += 2;
-        self.travelled += 2;
+        let v = self.mem.read8(self.index);
+        self.index += 1;
+        self.travelled += 1;
 
         v
     }
+}
 
-    fn next8(&mut self) -> u8 {
-        if self.index >= self.mem.len() {
-            self.index = 0;
-        }
+impl<'mem, M: MemSource + ?Sized> MemIter for MemIterator<'mem, M> {
+    fn next32(&mut self) -> Result<u32, DecodeError> {
+        self.take(4)?;
 
-        let v = self.mem[self.index];
-        self.index += 1;
-        self.travelled += 1;
+        let bytes = [
+            self.read_byte(),
+            self.read_byte(),
+            self.read_byte(),
+            self.read_byte(),
+        ];
 
-        v
+        Ok(match self.endian {
+            Endian::Little => u32::from_le_bytes(bytes),
+            Endian::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn next16(&mut self) -> Result<u16, DecodeError> {
+        self.take(2)?;
+
+        let bytes = [self.read_byte(), self.read_byte()];
+
+        Ok(match self.endian {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn next8(&mut self) -> Result<u8, DecodeError> {
+        self.take(1)?;
+
+        Ok(self.read_byte())
+    }
+
+    fn peek8(&self) -> Result<u8, DecodeError> {
+        self.lookahead().next8()
+    }
+
+    fn peek16(&self) -> Result<u16, DecodeError> {
+        self.lookahead().next16()
+    }
+
+    fn peek32(&self) -> Result<u32, DecodeError> {
+        self.lookahead().next32()
     }
 
     fn travelled(&self) -> usize {
@@ -117,171 +500,1921 @@ impl<'mem> MemIter for MemIterator<'mem> {
     }
 }
 
-pub struct Cpu {
-    pub registers: CpuRegisters,
-    pub mem: Box<[u8; MAX_MEM]>,
+/// Reads instructions straight out of a stream, little-endian only and
+/// without wrapping around. Only the bytes that are asked for get read (peeks
+/// included), so one can be made for each instruction to decode a stream of
+/// them in order. Read errors and EOF both look like a truncated instruction.
+#[cfg(feature = "std")]
+pub struct StreamMemIter<R> {
+    reader: core::cell::RefCell<R>,
+    // read from the stream but not consumed yet, because of a peek
+    buffer: core::cell::RefCell<alloc::collections::VecDeque<u8>>,
+    travelled: usize,
 }
 
-#[allow(clippy::new_without_default)]
-impl Cpu {
-    pub fn new() -> Self {
-        let slice = vec![0u8; MAX_MEM].into_boxed_slice();
-        let ptr = Box::into_raw(slice) as *mut [u8; MAX_MEM];
-        let mem = unsafe { Box::from_raw(ptr) };
-
+#[cfg(feature = "std")]
+impl<R: std::io::Read> StreamMemIter<R> {
+    pub fn new(reader: R) -> Self {
         Self {
-            registers: CpuRegisters::default(),
-            mem,
+            reader: core::cell::RefCell::new(reader),
+            buffer: Default::default(),
+            travelled: 0,
         }
     }
 
-    pub fn cycle(&mut self) {
-        let ip = self.registers.instruction_pointer as usize;
+    // makes sure at least `count` bytes are buffered
+    fn fill(&self, count: usize) -> Result<(), DecodeError> {
+        let mut buffer = self.buffer.borrow_mut();
+        let mut reader = self.reader.borrow_mut();
 
-        let parsed_instr = Instruction::read(MemIterator::new(ip, self.mem.as_slice()));
-
-        match parsed_instr {
-            Ok(parsed) => {
-                self.registers.instruction_pointer = self
-                    .registers
-                    .instruction_pointer
-                    .wrapping_add(parsed.delta_ip);
-
-                self.do_instruction(parsed.instr);
+        while buffer.len() < count {
+            let mut byte = [0];
+            match reader.read(&mut byte) {
+                Ok(1) => buffer.push_back(byte[0]),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                _ => {
+                    return Err(DecodeError::Truncated {
+                        needed: count,
+                        available: buffer.len(),
+                    })
+                }
             }
-            Err(e) => eprintln!("{e}"),
         }
+
+        Ok(())
     }
 
-    pub fn do_instruction(&mut self, instr: Instruction) {
-        match instr {
-            Instruction::Halt => {}
-            Instruction::Move(move_instr) => self.do_move_instruction(move_instr),
+    fn peek_bytes<const N: usize>(&self) -> Result<[u8; N], DecodeError> {
+        self.fill(N)?;
+
+        let buffer = self.buffer.borrow();
+        let mut bytes = [0; N];
+        for (byte, buffered) in bytes.iter_mut().zip(buffer.iter()) {
+            *byte = *buffered;
         }
+
+        Ok(bytes)
     }
 
-    fn do_move_instruction(&mut self, move_instr: instr::Move) {
-        match move_instr {
-            instr::Move::RegToReg(reg_src, reg_dst) => {
-                self.set_reg32(reg_dst, self.get_reg(reg_src));
-            }
-            instr::Move::ImmToReg32(imm, reg_dst) => {
-                self.set_reg32(reg_dst, imm);
-            }
-            instr::Move::ImmToReg16(imm, reg_dst) => {
-                self.set_reg16(reg_dst, imm);
-            }
-            instr::Move::ImmToReg8(imm, reg_dst) => {
-                self.set_reg8(reg_dst, imm);
-            }
+    fn next_bytes<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let bytes = self.peek_bytes::<N>()?;
 
-            instr::Move::RegToMem32(reg_src, addr) => {
-                self.write_mem32(addr, self.get_reg(reg_src));
-            }
-            instr::Move::RegToMem16(reg_src, addr) => {
-                self.write_mem16(addr, (self.get_reg(reg_src) & 0xFFFF) as u16);
-            }
-            instr::Move::RegToMem8(reg_src, addr) => {
-                self.write_mem8(addr, (self.get_reg(reg_src) & 0xFF) as u8);
-            }
+        self.buffer.get_mut().drain(..N);
+        self.travelled += N;
 
-            instr::Move::MemToReg32(addr, reg_dst) => {
-                self.set_reg32(reg_dst, self.read_mem32(addr));
-            }
-            instr::Move::MemToReg16(addr, reg_dst) => {
-                self.set_reg16(reg_dst, self.read_mem16(addr));
-            }
-            instr::Move::MemToReg8(addr, reg_dst) => {
-                self.set_reg8(reg_dst, self.read_mem8(addr));
-            }
+        Ok(bytes)
+    }
+}
 
-            instr::Move::MemToMem32(addr_src, addr_dest) => {
-                self.write_mem32(addr_dest, self.read_mem32(addr_src));
-            }
-            instr::Move::MemToMem16(addr_src, addr_dest) => {
-                self.write_mem16(addr_dest, self.read_mem16(addr_src));
-            }
-            instr::Move::MemToMem8(addr_src, addr_dest) => {
-                self.write_mem8(addr_dest, self.read_mem8(addr_src));
-            }
-        }
+#[cfg(feature = "std")]
+impl<R: std::io::Read> MemIter for StreamMemIter<R> {
+    fn next8(&mut self) -> Result<u8, DecodeError> {
+        self.next_bytes::<1>().map(|bytes| bytes[0])
     }
 
-    fn get_reg(&self, reg: Register) -> u32 {
-        match reg {
-            Register::A => self.registers.a,
-            Register::B => self.registers.b,
-            Register::X => self.registers.x,
-            Register::Y => self.registers.y,
-            Register::Ip => self.registers.instruction_pointer,
-        }
+    fn next16(&mut self) -> Result<u16, DecodeError> {
+        self.next_bytes().map(u16::from_le_bytes)
     }
 
-    fn set_reg32(&mut self, reg: Register, value: u32) {
-        match reg {
-            Register::A => self.registers.a = value,
-            Register::B => self.registers.b = value,
-            Register::X => self.registers.x = value,
-            Register::Y => self.registers.y = value,
-            Register::Ip => self.registers.instruction_pointer = value,
-        }
+    fn next32(&mut self) -> Result<u32, DecodeError> {
+        self.next_bytes().map(u32::from_le_bytes)
     }
 
-    fn set_reg16(&mut self, reg: Register, value: u16) {
-        match reg {
-            Register::A => self.registers.a = (self.registers.a & 0xFFFF_0000) | (value as u32),
-            Register::B => self.registers.b = (self.registers.b & 0xFFFF_0000) | (value as u32),
-            Register::X => self.registers.x = (self.registers.x & 0xFFFF_0000) | (value as u32),
-            Register::Y => self.registers.y = (self.registers.y & 0xFFFF_0000) | (value as u32),
-            Register::Ip => {
-                self.registers.instruction_pointer =
-                    (self.registers.instruction_pointer & 0xFFFF_0000) | (value as u32)
-            }
-        }
+    fn peek8(&self) -> Result<u8, DecodeError> {
+        self.peek_bytes::<1>().map(|bytes| bytes[0])
     }
 
-    fn set_reg8(&mut self, reg: Register, value: u8) {
-        match reg {
-            Register::A => self.registers.a = (self.registers.a & 0xFFFF_FF00) | (value as u32),
-            Register::B => self.registers.b = (self.registers.b & 0xFFFF_FF00) | (value as u32),
-            Register::X => self.registers.x = (self.registers.x & 0xFFFF_FF00) | (value as u32),
-            Register::Y => self.registers.y = (self.registers.y & 0xFFFF_FF00) | (value as u32),
-            Register::Ip => {
-                self.registers.instruction_pointer =
-                    (self.registers.instruction_pointer & 0xFFFF_FF00) | (value as u32)
-            }
-        }
+    fn peek16(&self) -> Result<u16, DecodeError> {
+        self.peek_bytes().map(u16::from_le_bytes)
     }
 
-    fn read_mem32(&self, addr: u32) -> u32 {
-        let mut iter = MemIterator::new(addr as usize, self.mem.as_slice());
+    fn peek32(&self) -> Result<u32, DecodeError> {
+        self.peek_bytes().map(u32::from_le_bytes)
+    }
 
-        iter.next32()
+    fn travelled(&self) -> usize {
+        self.travelled
     }
+}
 
-    fn read_mem16(&self, addr: u32) -> u16 {
-        let mut iter = MemIterator::new(addr as usize, self.mem.as_slice());
+#[derive(Debug, PartialEq)]
+pub enum CycleError {
+    Decode(DecodeError),
+    /// Every execute fault halts the CPU, see `Cpu::execute_fault`
+    Execute(String),
+}
 
-        iter.next16()
+impl core::fmt::Display for CycleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "Failed to decode instruction: {e}"),
+            Self::Execute(e) => write!(f, "Failed to execute instruction: {e}"),
+        }
     }
+}
 
-    fn read_mem8(&self, addr: u32) -> u8 {
-        self.mem[addr as usize]
-    }
+/// A store to a watched address, `old` and `new` are the byte at `addr`
+/// before and after the write
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WatchpointHit {
+    pub addr: u32,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// A data read or write made by an instruction, see `Cpu::enable_access_log`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct MemAccess {
+    pub addr: u32,
+    /// In bytes
+    pub width: u8,
+    /// What was read, or what was written
+    pub value: u32,
+    pub is_write: bool,
+    /// IP when the access happened, which is already past the instruction
+    /// that made it
+    pub ip: u32,
+}
+
+/// What ADD and SUB do when the unsigned result doesn't fit, CARRY is set
+/// either way
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum ArithMode {
+    #[default]
+    Wrapping,
+    /// Clamp to `0xFFFF_FFFF` or `0`
+    Saturating,
+}
+
+/// Cloning copies memory lazily, see `Memory`. `W` is how wide the
+/// registers and addresses are, see `Word`.
+#[derive(Clone)]
+pub struct Cpu<W: Word = u32> {
+    pub registers: CpuRegisters,
+    pub mem: Memory,
+    pub halted: bool,
+    /// Cycles spent so far, each instruction takes `Instruction::cost` and
+    /// one that failed to decode takes 1
+    pub cycles: u64,
+    /// Instructions that executed successfully
+    pub instructions_retired: u64,
+    watchpoints: BTreeSet<u32>,
+    watchpoint_hit: Option<WatchpointHit>,
+    last_fault: Option<DecodeError>,
+    execute_fault: Option<String>,
+    access_log: Option<Vec<MemAccess>>,
+    decode_options: DecodeOptions,
+    arith_mode: ArithMode,
+    stack_bounds: Option<Range<u32>>,
+    readonly: Vec<Range<u32>>,
+    devices: Vec<(Range<u32>, Box<dyn Device>)>,
+    width: PhantomData<W>,
+}
 
-    fn write_mem32(&mut self, addr: u32, value: u32) {
-        self.mem[addr as usize] = (value & 0xFF) as u8;
-        self.mem[(addr.wrapping_add(1)) as usize] = ((value & 0xFF00) >> 8) as u8;
-        self.mem[(addr.wrapping_add(2)) as usize] = ((value & 0xFF_0000) >> 16) as u8;
-        self.mem[(addr.wrapping_add(3)) as usize] = ((value & 0xFF00_0000) >> 24) as u8;
+#[allow(clippy::new_without_default)]
+impl Cpu {
+    pub fn new() -> Self {
+        Self::with_mem_size(DEFAULT_MEM_SIZE)
     }
 
-    fn write_mem16(&mut self, addr: u32, value: u16) {
-        self.mem[addr as usize] = (value & 0xFF) as u8;
-        self.mem[(addr.wrapping_add(1)) as usize] = ((value & 0xFF00) >> 8) as u8;
+    /// Creates a CPU with `size` bytes of memory, which addresses wrap around
+    pub fn with_mem_size(size: usize) -> Self {
+        Self::sized(size)
     }
+}
 
-    fn write_mem8(&mut self, addr: u32, value: u8) {
-        self.mem[addr as usize] = value;
+impl<W: Word> Cpu<W> {
+    /// `Cpu::with_mem_size` for any width, like `Cpu::<u16>::sized(0x1000)`.
+    /// Memory past the highest address a `W` can hold is never touched by
+    /// instructions, so a `Cpu<u16>` can't reach `INTERRUPT_VECTOR_BASE` and
+    /// faults when it takes an interrupt.
+    pub fn sized(size: usize) -> Self {
+        assert!(size > 0, "memory size must not be zero");
+
+        Self {
+            registers: Self::initial_registers(size),
+            mem: Memory::new(size),
+            halted: false,
+            cycles: 0,
+            instructions_retired: 0,
+            watchpoints: BTreeSet::new(),
+            watchpoint_hit: None,
+            last_fault: None,
+            execute_fault: None,
+            access_log: None,
+            decode_options: DecodeOptions::default(),
+            arith_mode: ArithMode::default(),
+            stack_bounds: None,
+            readonly: Vec::new(),
+            devices: Vec::new(),
+            width: PhantomData,
+        }
+    }
+
+    // the stack pointer wraps around to 0 when memory fills the address space
+    fn initial_registers(mem_size: usize) -> CpuRegisters {
+        let mut registers = CpuRegisters::for_mem_size(mem_size);
+        registers.stack_pointer = W::wrap(registers.stack_pointer);
+
+        registers
+    }
+
+    /// Puts the registers and flags back to their defaults, and zeroes all of
+    /// memory if `clear_mem` is set.
+    pub fn reset(&mut self, clear_mem: bool) {
+        self.registers = Self::initial_registers(self.mem.size());
+        self.halted = false;
+        self.cycles = 0;
+        self.instructions_retired = 0;
+        self.watchpoint_hit = None;
+        self.last_fault = None;
+        self.execute_fault = None;
+
+        if clear_mem {
+            self.mem.clear();
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn resume(&mut self) {
+        self.halted = false;
+        self.last_fault = None;
+        self.execute_fault = None;
+    }
+
+    /// The decode error that halted the CPU, until it's resumed or reset. IP
+    /// is left pointing at the instruction that failed to decode.
+    pub fn last_fault(&self) -> Option<DecodeError> {
+        self.last_fault
+    }
+
+    /// Why an instruction or device interrupt failed and halted the CPU,
+    /// until it's resumed or reset. Unlike `last_fault`, IP is already past
+    /// the instruction that failed since `fetch` moves it before running it.
+    pub fn execute_fault(&self) -> Option<&str> {
+        self.execute_fault.as_deref()
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn instructions_retired(&self) -> u64 {
+        self.instructions_retired
+    }
+
+    pub fn add_watchpoint(&mut self, addr: u32) {
+        self.watchpoints.insert(addr);
+    }
+
+    /// Returns whether there was a watchpoint at `addr`
+    pub fn remove_watchpoint(&mut self, addr: u32) -> bool {
+        self.watchpoints.remove(&addr)
+    }
+
+    /// How instructions are decoded when they're fetched
+    pub fn set_decode_options(&mut self, options: DecodeOptions) {
+        self.decode_options = options;
+    }
+
+    pub fn arith_mode(&self) -> ArithMode {
+        self.arith_mode
+    }
+
+    pub fn set_arith_mode(&mut self, mode: ArithMode) {
+        self.arith_mode = mode;
+    }
+
+    /// Keeps SP inside of `bounds`, with `bounds.end` being an empty stack.
+    /// A push or pop that would move SP outside of them fails and halts the
+    /// CPU instead. `None`, the default, lets the stack go anywhere.
+    pub fn set_stack_bounds(&mut self, bounds: Option<Range<u32>>) {
+        self.stack_bounds = bounds;
+    }
+
+    pub fn stack_bounds(&self) -> Option<Range<u32>> {
+        self.stack_bounds.clone()
+    }
+
+    /// Makes instructions that write to `range` fail instead, without
+    /// writing anything. Loading programs and other writes from outside of
+    /// the CPU still go through.
+    pub fn set_readonly(&mut self, range: Range<u32>) {
+        self.readonly.push(range);
+    }
+
+    /// Makes all of memory writable again
+    pub fn clear_readonly(&mut self) {
+        self.readonly.clear();
+    }
+
+    /// Starts logging every data read and write, instruction fetches aren't
+    /// logged
+    pub fn enable_access_log(&mut self) {
+        self.access_log.get_or_insert_with(Vec::new);
+    }
+
+    pub fn disable_access_log(&mut self) {
+        self.access_log = None;
+    }
+
+    /// The accesses logged since the last call, oldest first
+    pub fn take_access_log(&mut self) -> Vec<MemAccess> {
+        self.access_log
+            .as_mut()
+            .map(core::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Maps `device` over `range`, reads and writes by instructions in that
+    /// range go to the device instead of memory. Instructions are still
+    /// always fetched from memory.
+    pub fn add_device(&mut self, range: Range<u32>, device: Box<dyn Device>) -> Result<(), String> {
+        if range.is_empty() {
+            return Err(format!(
+                "Can't map a device over an empty range 0x{:08x}..0x{:08x}",
+                range.start, range.end
+            ));
+        }
+
+        if let Some((mapped, _)) = self
+            .devices
+            .iter()
+            .find(|(mapped, _)| mapped.start < range.end && range.start < mapped.end)
+        {
+            return Err(format!(
+                "Device range 0x{:08x}..0x{:08x} overlaps an existing device at 0x{:08x}..0x{:08x}",
+                range.start, range.end, mapped.start, mapped.end
+            ));
+        }
+
+        self.devices.push((range, device));
+
+        Ok(())
+    }
+
+    /// The device of type `D` mapped over `addr`, if there is one
+    pub fn device<D: Device + 'static>(&self, addr: u32) -> Option<&D> {
+        self.devices
+            .iter()
+            .find(|(range, _)| range.contains(&addr))
+            .and_then(|(_, device)| device.as_any().downcast_ref())
+    }
+
+    /// Takes the first watchpoint that was written to during the last cycle
+    pub fn take_watchpoint_hit(&mut self) -> Option<WatchpointHit> {
+        self.watchpoint_hit.take()
+    }
+
+    /// Reads bytes from `addr` up to, but not including, the first zero byte
+    /// or until `max_len` bytes have been read. Wraps around the end of
+    /// memory and ignores devices.
+    pub fn read_cstr(&self, addr: u32, max_len: usize) -> Vec<u8> {
+        let size = self.mem.size();
+
+        (0..max_len)
+            .map(|i| self.mem[(addr as usize).wrapping_add(i) % size])
+            .take_while(|byte| *byte != 0)
+            .collect()
+    }
+
+    /// See `Memory::hexdump`, devices are ignored
+    pub fn hexdump(&self, addr: u32, len: usize) -> String {
+        self.mem.hexdump(addr, len)
+    }
+
+    /// Sets `len` bytes starting at `addr` to `value`, nothing is written if
+    /// any of them are out of bounds
+    pub fn fill_mem(&mut self, addr: u32, len: usize, value: u8) -> Result<(), String> {
+        if len > self.mem.size() {
+            return Err(format!(
+                "Tried to fill {len} byte(s), more than the size of memory"
+            ));
+        }
+        self.check_bounds("fill", addr, len)?;
+
+        for i in 0..len as u32 {
+            self.store8(addr + i, value);
+        }
+
+        Ok(())
+    }
+
+    /// Whether the registers, flags, halted state and memory match, the
+    /// counters and devices aren't compared. Pages shared between clones are
+    /// skipped, other than that this reads through all of written memory.
+    pub fn state_eq(&self, other: &Self) -> bool {
+        self.registers == other.registers && self.halted == other.halted && self.mem == other.mem
+    }
+
+    /// Pushes the flags and IP, disables interrupts and jumps to the handler
+    /// for `vector`, waking the CPU up if it was halted. Returns whether the
+    /// interrupt was taken, it's dropped if interrupts are disabled or if a
+    /// fault halted the CPU, which only `resume` or `reset` get out of.
+    pub fn interrupt(&mut self, vector: u8) -> Result<bool, String> {
+        let faulted = self.last_fault.is_some() || self.execute_fault.is_some();
+        if faulted || !self.registers.interrupts_enabled() {
+            return Ok(false);
+        }
+
+        let handler = self.read_mem32(INTERRUPT_VECTOR_BASE + vector as u32 * 4)?;
+
+        let saved = self.registers;
+        let pushed = self
+            .push(self.registers.flags.value() as u32)
+            .and_then(|_| self.push(self.registers.instruction_pointer));
+        if let Err(e) = pushed {
+            self.registers = saved;
+            return Err(e);
+        }
+
+        self.registers.set_interrupts_enabled(false);
+        self.jump(handler);
+        self.halted = false;
+
+        Ok(true)
+    }
+
+    pub fn cycle(&mut self) -> Result<(), CycleError> {
+        let cycles = self.cycles;
+
+        if let Some(instr) = self.fetch().map_err(CycleError::Decode)? {
+            self.do_instruction(instr).map_err(CycleError::Execute)?;
+        }
+
+        self.tick_devices(self.cycles - cycles)
+            .map_err(CycleError::Execute)
+    }
+
+    /// Lets every device know `cycles` have gone by, raising the interrupts
+    /// they ask for in the order they were mapped. A halted CPU idles for at
+    /// least one cycle so that a device can wake it back up, the idle cycles
+    /// aren't counted in `cycles`.
+    pub fn tick_devices(&mut self, cycles: u64) -> Result<(), String> {
+        let cycles = if self.halted { cycles.max(1) } else { cycles };
+        if cycles == 0 {
+            return Ok(());
+        }
+
+        let vectors: Vec<u8> = self
+            .devices
+            .iter_mut()
+            .filter_map(|(_, device)| device.tick(cycles))
+            .collect();
+        for vector in vectors {
+            self.interrupt(vector).map_err(|e| self.fault(e))?;
+        }
+
+        Ok(())
+    }
+
+    fn fault(&mut self, e: String) -> String {
+        self.halted = true;
+        self.execute_fault = Some(e.clone());
+
+        e
+    }
+
+    /// Starts a cycle by decoding the instruction at IP and moving IP past
+    /// it, gives back `None` without doing anything if the CPU is halted.
+    pub fn fetch(&mut self) -> Result<Option<Instruction>, DecodeError> {
+        if self.halted {
+            return Ok(None);
+        }
+
+        self.watchpoint_hit = None;
+
+        // an illegal instruction traps, IP is left pointing at it
+        let (instr, len) = match self.decode_at(self.registers.instruction_pointer) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                self.cycles += 1;
+                self.halted = true;
+                self.last_fault = Some(e);
+                return Err(e);
+            }
+        };
+        self.cycles += u64::from(instr.cost());
+
+        // IP is advanced past the instruction *before* it is executed, so
+        // control flow instructions can simply overwrite it with their target
+        self.registers.instruction_pointer =
+            W::wrap(self.registers.instruction_pointer.wrapping_add(len));
+
+        Ok(Some(instr))
+    }
+
+    /// Decodes the instruction at `addr` the same way `fetch` would, without
+    /// running it. Gives back the instruction and its length in bytes.
+    pub fn decode_at(&self, addr: u32) -> Result<(Instruction, u32), DecodeError> {
+        let parsed = Instruction::read_with(
+            MemIterator::new(addr as usize, &self.mem),
+            self.decode_options,
+        )?;
+
+        Ok((parsed.instr, parsed.delta_ip))
+    }
+
+    /// Runs `instr`, halting the CPU if it fails
+    pub fn do_instruction(&mut self, instr: Instruction) -> Result<(), String> {
+        self.execute(instr).map_err(|e| self.fault(e))
+    }
+
+    fn execute(&mut self, instr: Instruction) -> Result<(), String> {
+        match instr {
+            Instruction::Halt => self.halted = true,
+            Instruction::Nop => {}
+            Instruction::Swap(reg_a, reg_b) => {
+                let (value_a, value_b) = (self.get_reg(reg_a), self.get_reg(reg_b));
+                self.set_reg32(reg_a, value_b);
+                self.set_reg32(reg_b, value_a);
+            }
+            Instruction::Xchg(reg, addr) => {
+                let old = self.read_word(addr)?;
+                self.write_word(addr, self.get_reg(reg))?;
+                self.set_reg32(reg, old);
+            }
+            Instruction::Mul(reg_src, reg_dst) => self.do_mul(reg_src, reg_dst),
+            Instruction::Div(reg_src, reg_dst) => self.do_div(reg_src, reg_dst)?,
+            Instruction::Move(move_instr) => self.do_move_instruction(move_instr)?,
+            Instruction::Logic(instr::Logic::Not(reg)) => {
+                let result = !self.get_reg(reg);
+                self.set_reg32(reg, result);
+                self.set_result_flags(result, None);
+            }
+            Instruction::Add(_)
+            | Instruction::Sub(_)
+            | Instruction::Logic(_)
+            | Instruction::Cmp(..)
+            | Instruction::CmpImm(..)
+            | Instruction::Alu(_) => match instr.as_alu() {
+                Some(alu_instr) => self.do_alu(alu_instr)?,
+                None => unreachable!("{instr} has an ALU encoding"),
+            },
+            Instruction::Shift(shift_instr) => self.do_shift_instruction(shift_instr),
+            Instruction::Lea(reg_dst, addr) => {
+                let addr = self
+                    .get_reg(addr.base)
+                    .wrapping_add(addr.displacement as u32);
+                self.set_reg32(reg_dst, addr);
+            }
+            Instruction::Jump(addr) => self.jump(addr),
+            // IP is already past the jump, so this is relative to the next instruction
+            Instruction::JumpRel(offset) => self.jump(
+                self.registers
+                    .instruction_pointer
+                    .wrapping_add(offset as u32),
+            ),
+            Instruction::JumpIfZero(addr) => {
+                if self.registers.zero() {
+                    self.jump(addr);
+                }
+            }
+            Instruction::JumpIfNotZero(addr) => {
+                if !self.registers.zero() {
+                    self.jump(addr);
+                }
+            }
+            Instruction::JumpIfLess(addr) => {
+                if self.registers.less() {
+                    self.jump(addr);
+                }
+            }
+            Instruction::JumpIfGreaterOrEqual(addr) => {
+                if !self.registers.less() {
+                    self.jump(addr);
+                }
+            }
+            Instruction::JumpIfGreater(addr) => {
+                if !self.registers.zero() && !self.registers.less() {
+                    self.jump(addr);
+                }
+            }
+            Instruction::JumpIfLessOrEqual(addr) => {
+                if self.registers.zero() || self.registers.less() {
+                    self.jump(addr);
+                }
+            }
+            Instruction::BranchIfEqual(reg_a, reg_b, addr) => {
+                if self.get_reg(reg_a) == self.get_reg(reg_b) {
+                    self.jump(addr);
+                }
+            }
+            Instruction::BranchIfNotEqual(reg_a, reg_b, addr) => {
+                if self.get_reg(reg_a) != self.get_reg(reg_b) {
+                    self.jump(addr);
+                }
+            }
+            Instruction::Push(reg) => self.push(self.get_reg(reg))?,
+            Instruction::Pop(reg) => {
+                let value = self.pop()?;
+                self.set_reg32(reg, value);
+            }
+            Instruction::Call(addr) => {
+                self.push(self.registers.instruction_pointer)?;
+                self.jump(addr);
+            }
+            Instruction::Ret => self.registers.instruction_pointer = self.pop()?,
+            Instruction::Iret => self.iret()?,
+            Instruction::PushAll => self.push_all()?,
+            Instruction::PopAll => self.pop_all()?,
+            Instruction::Cli => self.registers.set_interrupts_enabled(false),
+            Instruction::Sti => self.registers.set_interrupts_enabled(true),
+            Instruction::Neg(reg) => {
+                let value = self.get_reg(reg);
+                let result = (!value).wrapping_add(1);
+                self.set_reg32(reg, result);
+                // only the most negative number is its own negation
+                self.set_result_flags(result, Some(Self::signed_sub_overflows(0, value)));
+            }
+            Instruction::Inc(reg) => {
+                let value = self.get_reg(reg);
+                let result = value.wrapping_add(1);
+                self.set_reg32(reg, result);
+                self.set_result_flags(result, Some(Self::signed_add_overflows(value, 1)));
+            }
+            Instruction::Dec(reg) => {
+                let value = self.get_reg(reg);
+                let result = value.wrapping_sub(1);
+                self.set_reg32(reg, result);
+                self.set_result_flags(result, Some(Self::signed_sub_overflows(value, 1)));
+            }
+        }
+
+        self.instructions_retired += 1;
+
+        Ok(())
+    }
+
+    fn do_move_instruction(&mut self, move_instr: instr::Move) -> Result<(), String> {
+        match move_instr {
+            instr::Move::RegToReg(reg_src, reg_dst) => {
+                let value = self.get_reg(reg_src);
+                self.set_reg32(reg_dst, value);
+                self.set_zero_flag_from(value);
+            }
+            instr::Move::ImmToReg32(imm, reg_dst) => {
+                self.set_reg32(reg_dst, imm);
+                self.set_zero_flag_from(imm);
+            }
+            instr::Move::ImmToReg16(imm, reg_dst) => {
+                self.set_reg16(reg_dst, imm);
+                self.set_zero_flag_from(imm as u32);
+            }
+            instr::Move::ImmToReg8(imm, reg_dst) => {
+                self.set_reg8(reg_dst, imm);
+                self.set_zero_flag_from(imm as u32);
+            }
+
+            instr::Move::RegToMem32(reg_src, addr) => {
+                self.write_word(addr, self.get_reg(reg_src))?;
+            }
+            instr::Move::RegToMem16(reg_src, addr) => {
+                self.write_mem16(addr, self.get_reg16(reg_src))?;
+            }
+            instr::Move::RegToMem8(reg_src, addr) => {
+                self.write_mem8(addr, self.get_reg8(reg_src))?;
+            }
+
+            instr::Move::MemToReg32(addr, reg_dst) => {
+                let value = self.read_word(addr)?;
+                self.set_reg32(reg_dst, value);
+                self.set_zero_flag_from(value);
+            }
+            instr::Move::MemToReg16(addr, reg_dst) => {
+                let value = self.read_mem16(addr)?;
+                self.set_reg16(reg_dst, value);
+                self.set_zero_flag_from(value as u32);
+            }
+            instr::Move::MemToReg8(addr, reg_dst) => {
+                let value = self.read_mem8(addr)?;
+                self.set_reg8(reg_dst, value);
+                self.set_zero_flag_from(value as u32);
+            }
+
+            instr::Move::MemToMem32(addr_src, addr_dest) => {
+                let value = self.read_word(addr_src)?;
+                self.write_word(addr_dest, value)?;
+            }
+            instr::Move::MemToMem16(addr_src, addr_dest) => {
+                let value = self.read_mem16(addr_src)?;
+                self.write_mem16(addr_dest, value)?;
+            }
+            instr::Move::MemToMem8(addr_src, addr_dest) => {
+                let value = self.read_mem8(addr_src)?;
+                self.write_mem8(addr_dest, value)?;
+            }
+
+            instr::Move::ImmToMem32(imm, addr) => self.write_word(addr, imm)?,
+            instr::Move::ImmToMem16(imm, addr) => self.write_mem16(addr, imm)?,
+            instr::Move::ImmToMem8(imm, addr) => self.write_mem8(addr, imm)?,
+
+            instr::Move::RegIndirectToReg(reg_src, reg_dst) => {
+                let value = self.read_word(self.get_reg(reg_src))?;
+                self.set_reg32(reg_dst, value);
+                self.set_zero_flag_from(value);
+            }
+            instr::Move::RegToRegIndirect(reg_src, reg_dst) => {
+                self.write_word(self.get_reg(reg_dst), self.get_reg(reg_src))?;
+            }
+
+            instr::Move::IndexedToReg(indexed, reg_dst) => {
+                let value = self.read_word(self.indexed_addr(indexed))?;
+                self.set_reg32(reg_dst, value);
+                self.set_zero_flag_from(value);
+            }
+            instr::Move::RegToIndexed(reg_src, indexed) => {
+                self.write_word(self.indexed_addr(indexed), self.get_reg(reg_src))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // the address wraps around the end of memory, the access itself can still
+    // run off of the end and fault
+    fn indexed_addr(&self, indexed: instr::Indexed) -> u32 {
+        let addr = self.get_reg(indexed.base).wrapping_add(
+            self.get_reg(indexed.index)
+                .wrapping_mul(u32::from(indexed.scale)),
+        );
+
+        (W::wrap(addr) as usize % self.mem.size()) as u32
+    }
+
+    /// The sum and whether it carried, clamped in `ArithMode::Saturating`
+    fn add_values(&self, a: u32, b: u32) -> (u32, bool) {
+        let sum = u64::from(a) + u64::from(b);
+        let result = match self.arith_mode {
+            ArithMode::Wrapping => W::wrap(sum as u32),
+            ArithMode::Saturating => sum.min(W::MASK.into()) as u32,
+        };
+
+        (result, sum > W::MASK.into())
+    }
+
+    /// The difference and whether it borrowed, clamped in
+    /// `ArithMode::Saturating`
+    fn sub_values(&self, a: u32, b: u32) -> (u32, bool) {
+        let result = match self.arith_mode {
+            ArithMode::Wrapping => W::wrap(a.wrapping_sub(b)),
+            ArithMode::Saturating => a.saturating_sub(b),
+        };
+
+        (result, b > a)
+    }
+
+    /// Whether `a + b` doesn't fit as a signed word
+    fn signed_add_overflows(a: u32, b: u32) -> bool {
+        let result = a.wrapping_add(b);
+
+        W::is_negative(a) == W::is_negative(b) && W::is_negative(result) != W::is_negative(a)
+    }
+
+    /// Whether `a - b` doesn't fit as a signed word
+    fn signed_sub_overflows(a: u32, b: u32) -> bool {
+        let result = a.wrapping_sub(b);
+
+        W::is_negative(a) != W::is_negative(b) && W::is_negative(result) != W::is_negative(a)
+    }
+
+    /// ZERO is set from the result and CARRY/OVERFLOW from the unsigned and
+    /// signed carry out, which the bitwise operations always clear. CMP
+    /// doesn't write the result. ADD, SUB, CMP, AND, OR and XOR all run
+    /// through here no matter how they were encoded.
+    fn do_alu(&mut self, alu_instr: instr::Alu) -> Result<(), String> {
+        let value = match alu_instr.src {
+            instr::AluSource::Reg(reg_src) => self.get_reg(reg_src),
+            instr::AluSource::Imm(imm) => W::wrap(imm),
+            instr::AluSource::Mem(addr) => self.read_word(addr)?,
+        };
+        let dst = self.get_reg(alu_instr.dst);
+
+        let ((result, carry), overflow) = match alu_instr.op {
+            instr::AluOp::Add => (
+                self.add_values(dst, value),
+                Self::signed_add_overflows(dst, value),
+            ),
+            instr::AluOp::Sub => (
+                self.sub_values(dst, value),
+                Self::signed_sub_overflows(dst, value),
+            ),
+            instr::AluOp::Cmp => {
+                self.compare(dst, value);
+                return Ok(());
+            }
+            instr::AluOp::And => ((dst & value, false), false),
+            instr::AluOp::Or => ((dst | value, false), false),
+            instr::AluOp::Xor => ((dst ^ value, false), false),
+        };
+
+        self.set_reg32(alu_instr.dst, result);
+        self.set_result_flags(result, Some(overflow));
+        self.set_carry_flag(carry);
+
+        Ok(())
+    }
+
+    /// The low word goes into `reg_dst` and the high word into Y, which wins
+    /// if `reg_dst` is Y too. ZERO is set from the whole product, SIGN from
+    /// the low word and CARRY if it didn't fit in the low word.
+    fn do_mul(&mut self, reg_src: Register, reg_dst: Register) {
+        let product = self.get_reg(reg_dst) as u64 * self.get_reg(reg_src) as u64;
+        let high = (product >> W::BITS) as u32;
+
+        self.set_reg32(reg_dst, product as u32);
+        self.set_reg32(Register::Y, high);
+
+        self.set_result_flags(product as u32, None);
+        self.registers.set_zero(product == 0);
+        self.set_carry_flag(high != 0);
+    }
+
+    /// The quotient goes into `reg_dst` and the remainder into Y, which wins
+    /// if `reg_dst` is Y too. Dividing by zero faults and halts the CPU, and
+    /// leaves the registers alone.
+    fn do_div(&mut self, reg_src: Register, reg_dst: Register) -> Result<(), String> {
+        let divisor = self.get_reg(reg_src);
+        if divisor == 0 {
+            return Err(format!("Tried to divide {} by zero", reg_dst.name()));
+        }
+
+        let dividend = self.get_reg(reg_dst);
+        let quotient = dividend / divisor;
+
+        self.set_reg32(reg_dst, quotient);
+        self.set_reg32(Register::Y, dividend % divisor);
+        self.set_result_flags(quotient, None);
+
+        Ok(())
+    }
+
+    /// Does `a - b` like SUB but only keeps the flags
+    fn compare(&mut self, a: u32, b: u32) {
+        let result = W::wrap(a.wrapping_sub(b));
+        self.set_result_flags(result, Some(Self::signed_sub_overflows(a, b)));
+        self.set_carry_flag(b > a);
+    }
+
+    /// CARRY is set from the last bit shifted out, a (masked) count of 0
+    /// leaves the register alone and clears CARRY
+    fn do_shift_instruction(&mut self, shift_instr: instr::ShiftOp) {
+        let (reg, count) = match shift_instr {
+            instr::ShiftOp::Shl(reg, count)
+            | instr::ShiftOp::Shr(reg, count)
+            | instr::ShiftOp::Sar(reg, count)
+            | instr::ShiftOp::Rol(reg, count)
+            | instr::ShiftOp::Ror(reg, count) => (reg, u32::from(count) & (W::BITS - 1)),
+        };
+        let value = self.get_reg(reg);
+
+        let (result, carry) = if count == 0 {
+            (value, false)
+        } else {
+            match shift_instr {
+                instr::ShiftOp::Shl(..) => (
+                    W::wrap(value << count),
+                    (value >> (W::BITS - count)) & 1 == 1,
+                ),
+                instr::ShiftOp::Shr(..) => (value >> count, (value >> (count - 1)) & 1 == 1),
+                // the sign bit is moved to the top to be shifted in
+                instr::ShiftOp::Sar(..) => (
+                    W::wrap((((value << (32 - W::BITS)) as i32) >> (count + 32 - W::BITS)) as u32),
+                    (value >> (count - 1)) & 1 == 1,
+                ),
+                instr::ShiftOp::Rol(..) => {
+                    let result = W::wrap((value << count) | (value >> (W::BITS - count)));
+                    (result, result & 1 == 1)
+                }
+                instr::ShiftOp::Ror(..) => {
+                    let result = W::wrap((value >> count) | (value << (W::BITS - count)));
+                    (result, W::is_negative(result))
+                }
+            }
+        };
+
+        self.set_reg32(reg, result);
+        self.set_result_flags(result, None);
+        self.set_carry_flag(carry);
+    }
+
+    /// Sets the ZERO flag if `value` is zero, otherwise clears it
+    pub fn set_zero_flag_from(&mut self, value: u32) {
+        self.registers.set_zero(W::wrap(value) == 0);
+    }
+
+    fn set_carry_flag(&mut self, carry: bool) {
+        self.registers.set_carry(carry);
+    }
+
+    /// ZERO and SIGN come from `result`, OVERFLOW is only touched by the
+    /// instructions that can overflow
+    fn set_result_flags(&mut self, result: u32, overflow: Option<bool>) {
+        self.set_zero_flag_from(result);
+        self.registers.set_sign(W::is_negative(result));
+        if let Some(overflow) = overflow {
+            self.registers.set_overflow(overflow);
+        }
+    }
+
+    /// Control flow can't leave the addresses a word can hold, `addr` wraps
+    /// around instead
+    fn jump(&mut self, addr: u32) {
+        self.registers.instruction_pointer = W::wrap(addr);
+    }
+
+    fn push(&mut self, value: u32) -> Result<(), String> {
+        let stack_pointer = W::wrap(self.registers.stack_pointer.wrapping_sub(W::BYTES));
+        self.check_stack(stack_pointer, "overflow")?;
+        self.write_word(stack_pointer, value)?;
+        self.registers.stack_pointer = stack_pointer;
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<u32, String> {
+        let stack_pointer = W::wrap(self.registers.stack_pointer.wrapping_add(W::BYTES));
+        self.check_stack(stack_pointer, "underflow")?;
+        let value = self.read_word(self.registers.stack_pointer)?;
+        self.registers.stack_pointer = stack_pointer;
+
+        Ok(value)
+    }
+
+    fn check_stack(&self, stack_pointer: u32, fault: &str) -> Result<(), String> {
+        let bounds = match &self.stack_bounds {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+
+        if stack_pointer < bounds.start || stack_pointer > bounds.end {
+            return Err(format!(
+                "Stack {fault}: SP would move to 0x{stack_pointer:08x}, outside of the stack at 0x{:08x}..0x{:08x}",
+                bounds.start, bounds.end
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Nothing is changed if either of the pops fail
+    fn iret(&mut self) -> Result<(), String> {
+        let stack_pointer = self.registers.stack_pointer;
+
+        let popped = self.pop().and_then(|ip| Ok((ip, self.pop()?)));
+        let (ip, flags) = match popped {
+            Ok(popped) => popped,
+            Err(e) => {
+                self.registers.stack_pointer = stack_pointer;
+                return Err(e);
+            }
+        };
+
+        self.registers.instruction_pointer = ip;
+        self.registers.flags = (flags as u8).into();
+
+        Ok(())
+    }
+
+    /// Nothing is changed if any of the pushes fail
+    fn push_all(&mut self) -> Result<(), String> {
+        let saved = self.registers;
+
+        let pushed = [Register::A, Register::B, Register::X, Register::Y]
+            .into_iter()
+            .try_for_each(|reg| self.push(self.get_reg(reg)))
+            .and_then(|_| self.push(self.registers.flags.value() as u32));
+        if pushed.is_err() {
+            self.registers = saved;
+        }
+
+        pushed
+    }
+
+    /// The reverse of `push_all`, nothing is changed if any of the pops fail
+    fn pop_all(&mut self) -> Result<(), String> {
+        let saved = self.registers;
+
+        let popped = self.pop().and_then(|flags| {
+            let mut values = [0; 4];
+            for value in values.iter_mut().rev() {
+                *value = self.pop()?;
+            }
+
+            Ok((flags, values))
+        });
+        let (flags, [a, b, x, y]) = match popped {
+            Ok(popped) => popped,
+            Err(e) => {
+                self.registers = saved;
+                return Err(e);
+            }
+        };
+
+        self.registers.flags = (flags as u8).into();
+        self.registers.a = a;
+        self.registers.b = b;
+        self.registers.x = x;
+        self.registers.y = y;
+
+        Ok(())
+    }
+
+    fn get_reg(&self, reg: Register) -> u32 {
+        match reg {
+            Register::A => self.registers.a,
+            Register::B => self.registers.b,
+            Register::X => self.registers.x,
+            Register::Y => self.registers.y,
+            Register::Sp => self.registers.stack_pointer,
+            Register::Ip => self.registers.instruction_pointer,
+        }
+    }
+
+    /// The low halfword of `reg`, what `set_reg16` writes
+    fn get_reg16(&self, reg: Register) -> u16 {
+        (self.get_reg(reg) & 0xFFFF) as u16
+    }
+
+    fn get_reg8(&self, reg: ByteRegister) -> u8 {
+        ((self.get_reg(reg.reg) >> reg.shift()) & 0xFF) as u8
+    }
+
+    /// Only the low `W::BITS` of `value` are kept
+    fn set_reg32(&mut self, reg: Register, value: u32) {
+        let value = W::wrap(value);
+        match reg {
+            Register::A => self.registers.a = value,
+            Register::B => self.registers.b = value,
+            Register::X => self.registers.x = value,
+            Register::Y => self.registers.y = value,
+            Register::Sp => self.registers.stack_pointer = value,
+            Register::Ip => self.registers.instruction_pointer = value,
+        }
+    }
+
+    fn set_reg16(&mut self, reg: Register, value: u16) {
+        match reg {
+            Register::A => self.registers.a = (self.registers.a & 0xFFFF_0000) | (value as u32),
+            Register::B => self.registers.b = (self.registers.b & 0xFFFF_0000) | (value as u32),
+            Register::X => self.registers.x = (self.registers.x & 0xFFFF_0000) | (value as u32),
+            Register::Y => self.registers.y = (self.registers.y & 0xFFFF_0000) | (value as u32),
+            Register::Sp => {
+                self.registers.stack_pointer =
+                    (self.registers.stack_pointer & 0xFFFF_0000) | (value as u32)
+            }
+            Register::Ip => {
+                self.registers.instruction_pointer =
+                    (self.registers.instruction_pointer & 0xFFFF_0000) | (value as u32)
+            }
+        }
+    }
+
+    /// Only writes the byte of the register `reg` refers to
+    fn set_reg8(&mut self, reg: ByteRegister, value: u8) {
+        let mask = 0xFF << reg.shift();
+        let value = (self.get_reg(reg.reg) & !mask) | ((value as u32) << reg.shift());
+
+        self.set_reg32(reg.reg, value);
+    }
+
+    /// A register's worth, `W::BYTES` wide
+    fn read_word(&mut self, addr: u32) -> Result<u32, String> {
+        match W::BYTES {
+            2 => self.read_mem16(addr).map(u32::from),
+            _ => self.read_mem32(addr),
+        }
+    }
+
+    fn read_mem32(&mut self, addr: u32) -> Result<u32, String> {
+        self.check_bounds("read", addr, 4)?;
+
+        let value = u32::from_le_bytes([
+            self.load8(addr),
+            self.load8(addr + 1),
+            self.load8(addr + 2),
+            self.load8(addr + 3),
+        ]);
+        self.log_access(addr, 4, value, false);
+
+        Ok(value)
+    }
+
+    fn read_mem16(&mut self, addr: u32) -> Result<u16, String> {
+        self.check_bounds("read", addr, 2)?;
+
+        let value = u16::from_le_bytes([self.load8(addr), self.load8(addr + 1)]);
+        self.log_access(addr, 2, value.into(), false);
+
+        Ok(value)
+    }
+
+    fn read_mem8(&mut self, addr: u32) -> Result<u8, String> {
+        self.check_bounds("read", addr, 1)?;
+
+        let value = self.load8(addr);
+        self.log_access(addr, 1, value.into(), false);
+
+        Ok(value)
+    }
+
+    fn log_access(&mut self, addr: u32, width: u8, value: u32, is_write: bool) {
+        if let Some(log) = &mut self.access_log {
+            log.push(MemAccess {
+                addr,
+                width,
+                value,
+                is_write,
+                ip: self.registers.instruction_pointer,
+            });
+        }
+    }
+
+    fn device_at(&mut self, addr: u32) -> Option<(u32, &mut Box<dyn Device>)> {
+        self.devices
+            .iter_mut()
+            .find(|(range, _)| range.contains(&addr))
+            .map(|(range, device)| (addr - range.start, device))
+    }
+
+    // Every load goes through here, `addr` has already been bounds checked
+    fn load8(&mut self, addr: u32) -> u8 {
+        if let Some((offset, device)) = self.device_at(addr) {
+            return device.read(offset);
+        }
+
+        self.mem[addr as usize]
+    }
+
+    // Reads and writes fault instead of wrapping around the end of memory, an
+    // access that doesn't fit touches nothing. Devices can be mapped past the
+    // end of memory, so each byte is checked on its own.
+    fn check_bounds(&mut self, access: &str, addr: u32, len: usize) -> Result<(), String> {
+        for i in 0..len as u32 {
+            let in_bounds = match addr.checked_add(i) {
+                // addresses a word can't hold are out of bounds too
+                Some(byte_addr) if byte_addr <= W::MASK => {
+                    (byte_addr as usize) < self.mem.size() || self.device_at(byte_addr).is_some()
+                }
+                _ => false,
+            };
+
+            if !in_bounds {
+                return Err(format!(
+                    "Tried to {access} {len} byte(s) out of bounds at 0x{addr:08x}"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_writable(&self, addr: u32, len: u32) -> Result<(), String> {
+        let end = addr.saturating_add(len);
+
+        match self
+            .readonly
+            .iter()
+            .find(|range| addr < range.end && range.start < end)
+        {
+            Some(range) => Err(format!(
+                "Write protect fault: tried to write {len} byte(s) at 0x{addr:08x}, which is read-only in 0x{:08x}..0x{:08x}",
+                range.start, range.end
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn write_word(&mut self, addr: u32, value: u32) -> Result<(), String> {
+        match W::BYTES {
+            2 => self.write_mem16(addr, value as u16),
+            _ => self.write_mem32(addr, value),
+        }
+    }
+
+    fn write_mem32(&mut self, addr: u32, value: u32) -> Result<(), String> {
+        self.check_bounds("write", addr, 4)?;
+        self.check_writable(addr, 4)?;
+
+        self.store8(addr, (value & 0xFF) as u8);
+        self.store8(addr + 1, ((value & 0xFF00) >> 8) as u8);
+        self.store8(addr + 2, ((value & 0xFF_0000) >> 16) as u8);
+        self.store8(addr + 3, ((value & 0xFF00_0000) >> 24) as u8);
+        self.log_access(addr, 4, value, true);
+
+        Ok(())
+    }
+
+    fn write_mem16(&mut self, addr: u32, value: u16) -> Result<(), String> {
+        self.check_bounds("write", addr, 2)?;
+        self.check_writable(addr, 2)?;
+
+        self.store8(addr, (value & 0xFF) as u8);
+        self.store8(addr + 1, ((value & 0xFF00) >> 8) as u8);
+        self.log_access(addr, 2, value.into(), true);
+
+        Ok(())
+    }
+
+    fn write_mem8(&mut self, addr: u32, value: u8) -> Result<(), String> {
+        self.check_bounds("write", addr, 1)?;
+        self.check_writable(addr, 1)?;
+
+        self.store8(addr, value);
+        self.log_access(addr, 1, value.into(), true);
+
+        Ok(())
+    }
+
+    // Every store goes through here so watchpoints see each byte of wider
+    // writes, stores to devices aren't watched
+    fn store8(&mut self, addr: u32, value: u8) {
+        if let Some((offset, device)) = self.device_at(addr) {
+            device.write(offset, value);
+            return;
+        }
+
+        if self.watchpoint_hit.is_none() && self.watchpoints.contains(&addr) {
+            self.watchpoint_hit = Some(WatchpointHit {
+                addr,
+                old: self.mem[addr as usize],
+                new: value,
+            });
+        }
+
+        self.mem[addr as usize] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec};
+
+    use super::*;
+
+    mod cpu {
+        use super::*;
+
+        #[test]
+        fn reset() {
+            let mut cpu = Cpu::new();
+
+            cpu.registers.instruction_pointer = 0x100;
+            cpu.registers.stack_pointer = 0x200;
+            cpu.registers.a = 1;
+            cpu.registers.b = 2;
+            cpu.registers.x = 3;
+            cpu.registers.y = 4;
+            cpu.registers.flags |= ZERO | CARRY;
+            cpu.halted = true;
+            cpu.mem[0] = 0x42;
+            cpu.mem[DEFAULT_MEM_SIZE - 1] = 0x42;
+
+            cpu.reset(false);
+
+            assert_eq!(cpu.registers.instruction_pointer, 0);
+            assert_eq!(cpu.registers.stack_pointer, DEFAULT_MEM_SIZE as u32);
+            assert_eq!(cpu.registers.a, 0);
+            assert_eq!(cpu.registers.b, 0);
+            assert_eq!(cpu.registers.x, 0);
+            assert_eq!(cpu.registers.y, 0);
+            assert_eq!(cpu.registers.flags.value(), 0);
+            assert!(!cpu.is_halted());
+            assert_eq!(cpu.mem[0], 0x42);
+
+            cpu.reset(true);
+
+            assert_eq!(cpu.mem[0], 0);
+            assert_eq!(cpu.mem[DEFAULT_MEM_SIZE - 1], 0);
+        }
+
+        #[test]
+        fn execute_fault_halts() {
+            let mut cpu = Cpu::with_mem_size(0x10);
+            let instr = Instruction::Move(instr::Move::MemToReg32(0x100, Register::A));
+            for (i, byte) in instr.encode().unwrap().into_iter().enumerate() {
+                cpu.mem[i] = byte;
+            }
+
+            let err = match cpu.cycle() {
+                Err(CycleError::Execute(e)) => e,
+                result => panic!("expected an execute fault, got {result:?}"),
+            };
+            assert!(cpu.is_halted());
+            assert_eq!(cpu.execute_fault(), Some(err.as_str()));
+            assert_eq!(
+                cpu.registers.instruction_pointer,
+                instr.encode().unwrap().len() as u32
+            );
+            assert_eq!(cpu.cycle(), Ok(()));
+            assert_eq!(cpu.instructions_retired(), 0);
+
+            // interrupts can't wake it up
+            cpu.registers.set_interrupts_enabled(true);
+            assert_eq!(cpu.interrupt(0), Ok(false));
+            assert!(cpu.is_halted());
+
+            cpu.resume();
+            assert_eq!(cpu.execute_fault(), None);
+        }
+
+        #[test]
+        fn access_log() {
+            let mut cpu = Cpu::new();
+            let instr = Instruction::Move(instr::Move::MemToMem16(0x100, 0x200));
+            for (i, byte) in instr.encode().unwrap().into_iter().enumerate() {
+                cpu.mem[i] = byte;
+            }
+            cpu.mem[0x100] = 0x34;
+            cpu.mem[0x101] = 0x12;
+
+            cpu.cycle().expect("should run");
+            assert!(cpu.take_access_log().is_empty());
+
+            cpu.enable_access_log();
+            cpu.registers.instruction_pointer = 0;
+            cpu.cycle().expect("should run");
+
+            let ip = instr.encode().unwrap().len() as u32;
+            assert_eq!(
+                cpu.take_access_log(),
+                vec![
+                    MemAccess {
+                        addr: 0x100,
+                        width: 2,
+                        value: 0x1234,
+                        is_write: false,
+                        ip,
+                    },
+                    MemAccess {
+                        addr: 0x200,
+                        width: 2,
+                        value: 0x1234,
+                        is_write: true,
+                        ip,
+                    },
+                ]
+            );
+            assert!(cpu.take_access_log().is_empty());
+        }
+
+        #[test]
+        fn hexdump() {
+            let mut cpu = Cpu::new();
+            for (i, byte) in b"Hi\0\xff nemu!\n\x7f\x01ABCD".iter().enumerate() {
+                cpu.mem[0x100 + i] = *byte;
+            }
+
+            assert_eq!(
+                cpu.hexdump(0x100, 16),
+                "00000100: 48 69 00 ff 20 6e 65 6d 75 21 0a 7f 01 41 42 43  |Hi.. nemu!...ABC|\n"
+            );
+            assert_eq!(
+                cpu.hexdump(0x10e, 4),
+                "0000010e: 42 43 44 00                                      |BCD.|\n"
+            );
+            assert_eq!(cpu.hexdump(0x100, 0), "");
+        }
+
+        #[test]
+        fn hexdump_stops_at_end_of_memory() {
+            let cpu = Cpu::with_mem_size(0x20);
+
+            let dump = cpu.hexdump(0x18, 0x100);
+            assert_eq!(dump.lines().count(), 1);
+            assert!(dump.starts_with("00000018: 00 00 00 00 00 00 00 00  "));
+            assert_eq!(cpu.hexdump(0x40, 16), "");
+        }
+
+        #[test]
+        fn decode_at_doesnt_execute() {
+            let mut cpu = Cpu::new();
+            let instr = Instruction::Move(instr::Move::ImmToReg32(0x42, Register::A));
+            for (i, byte) in instr.encode().unwrap().into_iter().enumerate() {
+                cpu.mem[0x100 + i] = byte;
+            }
+
+            // group, opcode, dword and register
+            assert_eq!(cpu.decode_at(0x100), Ok((instr, 7)));
+            assert_eq!(cpu.registers.a, 0);
+            assert_eq!(cpu.registers.instruction_pointer, 0);
+            assert_eq!(cpu.cycles(), 0);
+
+            cpu.mem[0x200] = 0xFF;
+            assert_eq!(cpu.decode_at(0x200), Err(DecodeError::InvalidGroup(0xFF)));
+        }
+
+        #[test]
+        fn read_cstr() {
+            let mut cpu = Cpu::new();
+            for (i, byte) in b"hi\0there".iter().enumerate() {
+                cpu.mem[0x100 + i] = *byte;
+            }
+
+            assert_eq!(cpu.read_cstr(0x100, 16), vec![b'h', b'i']);
+            assert_eq!(cpu.read_cstr(0x103, 16), b"there".to_vec());
+            assert_eq!(cpu.read_cstr(0x103, 3), b"the".to_vec());
+            assert_eq!(cpu.read_cstr(0x100, 0), Vec::<u8>::new());
+        }
+
+        #[test]
+        fn fill_mem() {
+            let mut cpu = Cpu::new();
+
+            cpu.fill_mem(0x100, 16, 0xAA).expect("should fill");
+
+            assert_eq!(cpu.mem[0xFF], 0);
+            assert!((0x100..0x110).all(|addr| cpu.mem[addr] == 0xAA));
+            assert_eq!(cpu.mem[0x110], 0);
+        }
+
+        #[test]
+        fn fill_mem_out_of_bounds() {
+            let mut cpu = Cpu::with_mem_size(0x10);
+
+            assert_eq!(
+                cpu.fill_mem(0x8, 9, 0xAA),
+                Err(String::from(
+                    "Tried to fill 9 byte(s) out of bounds at 0x00000008"
+                ))
+            );
+            assert_eq!(cpu.mem[0x8], 0);
+            assert!(cpu.fill_mem(0, 0x11, 0xAA).is_err());
+            assert!(cpu.fill_mem(0x8, 8, 0xAA).is_ok());
+        }
+
+        #[test]
+        fn partial_registers() {
+            let mut cpu = Cpu::new();
+            cpu.registers.x = 0x0403_0201;
+
+            assert_eq!(cpu.get_reg(Register::X), 0x0403_0201);
+            assert_eq!(cpu.get_reg16(Register::X), 0x0201);
+            assert_eq!(cpu.get_reg8(Register::X.low()), 0x01);
+            assert_eq!(cpu.get_reg8(Register::X.high()), 0x02);
+
+            cpu.set_reg16(Register::X, 0xBEEF);
+            cpu.set_reg8(Register::X.low(), 0x42);
+            assert_eq!(cpu.get_reg16(Register::X), 0xBE42);
+            assert_eq!(cpu.get_reg(Register::X), 0x0403_BE42);
+        }
+
+        #[test]
+        fn high_and_low_bytes() {
+            let mut cpu = Cpu::new();
+            cpu.registers.a = 0x1234_5678;
+
+            assert_eq!(cpu.get_reg8(Register::A.low()), 0x78);
+            assert_eq!(cpu.get_reg8(Register::A.high()), 0x56);
+
+            cpu.set_reg8(Register::A.high(), 0xAB);
+            assert_eq!(cpu.registers.a, 0x1234_AB78);
+            assert_eq!(cpu.get_reg8(Register::A.low()), 0x78);
+
+            cpu.set_reg8(Register::A.low(), 0xCD);
+            assert_eq!(cpu.registers.a, 0x1234_ABCD);
+            assert_eq!(cpu.get_reg8(Register::A.high()), 0xAB);
+        }
+
+        #[test]
+        fn read_cstr_wraps_around() {
+            let mut cpu = Cpu::with_mem_size(0x10);
+            cpu.mem[0xF] = b'o';
+            cpu.mem[0x0] = b'k';
+
+            assert_eq!(cpu.read_cstr(0xF, 16), b"ok".to_vec());
+        }
+    }
+
+    mod flags {
+        use super::*;
+
+        #[test]
+        fn named_flags_are_independent() {
+            let mut registers = CpuRegisters::default();
+            assert!(!registers.zero());
+            assert!(!registers.carry());
+            assert!(!registers.interrupts_enabled());
+
+            registers.set_zero(true);
+            assert!(registers.zero());
+            assert!(!registers.carry());
+            assert_eq!(registers.flags.value(), ZERO);
+
+            registers.set_carry(true);
+            registers.set_interrupts_enabled(true);
+            assert!(registers.carry());
+            assert!(registers.interrupts_enabled());
+            assert_eq!(registers.flags.value(), ZERO | CARRY | INTERRUPT);
+
+            registers.set_zero(false);
+            assert!(!registers.zero());
+            assert!(registers.carry());
+            assert_eq!(registers.flags.value(), CARRY | INTERRUPT);
+
+            registers.set_overflow(true);
+            assert!(registers.overflow());
+            assert_eq!(registers.flags.value(), CARRY | INTERRUPT | OVERFLOW);
+
+            registers.set_carry(false);
+            registers.set_interrupts_enabled(false);
+            registers.set_overflow(false);
+            assert_eq!(registers.flags.value(), 0);
+        }
+    }
+
+    mod register {
+        use super::*;
+
+        const REGISTERS: [Register; 6] = [
+            Register::A,
+            Register::B,
+            Register::X,
+            Register::Y,
+            Register::Sp,
+            Register::Ip,
+        ];
+
+        #[test]
+        fn id_round_trips() {
+            for reg in REGISTERS {
+                assert_eq!(Register::try_from_id(reg.id()), Ok(reg));
+                assert_eq!(u8::from(reg), reg.id());
+            }
+        }
+
+        #[test]
+        fn invalid_id() {
+            assert_eq!(
+                Register::try_from_id(0x6),
+                Err(DecodeError::InvalidRegister(0x6))
+            );
+        }
+
+        #[test]
+        fn name_round_trips() {
+            for reg in REGISTERS {
+                assert_eq!(reg.to_string().parse::<Register>(), Ok(reg));
+                assert_eq!(reg.to_string().to_lowercase().parse::<Register>(), Ok(reg));
+            }
+            assert_eq!(Register::Sp.to_string(), "SP");
+        }
+
+        #[test]
+        fn byte_registers() {
+            for reg in REGISTERS {
+                let low = reg.low();
+                assert_eq!(ByteRegister::try_from_id(low.id()), Ok(low));
+                assert_eq!(low.to_string().parse::<ByteRegister>(), Ok(low));
+            }
+            for reg in &REGISTERS[..4] {
+                let high = reg.high();
+                assert_eq!(ByteRegister::try_from_id(high.id()), Ok(high));
+                assert_eq!(high.to_string().parse::<ByteRegister>(), Ok(high));
+            }
+
+            assert_eq!(Register::A.high().id(), 0x80);
+            assert_eq!(Register::Y.high().to_string(), "YH");
+            assert_eq!("al".parse::<ByteRegister>(), Ok(Register::A.low()));
+            assert_eq!(
+                ByteRegister::try_from_id(0x84),
+                Err(DecodeError::InvalidRegister(0x84))
+            );
+            assert!("SPH".parse::<ByteRegister>().is_err());
+            assert!("AX".parse::<ByteRegister>().is_err());
+        }
+
+        #[test]
+        fn unknown_name() {
+            assert_eq!(
+                "rax".parse::<Register>(),
+                Err(String::from("Unknown register 'rax'"))
+            );
+            assert!("".parse::<Register>().is_err());
+        }
+    }
+
+    #[cfg(feature = "std")]
+    mod stream_mem_iter {
+        use super::*;
+        use crate::instr::{Move, ReadMem};
+
+        #[test]
+        fn decode_move() {
+            let instrs = [
+                Instruction::Move(Move::ImmToReg32(0x1234_5678, Register::B)),
+                Instruction::Move(Move::RegToMem16(Register::A, 0x10)),
+            ];
+            let mut bytes = Vec::new();
+            for instr in instrs {
+                bytes.extend(instr.encode().expect("should encode"));
+            }
+            let mut cursor = std::io::Cursor::new(bytes);
+
+            for instr in instrs {
+                let parsed =
+                    Instruction::read(StreamMemIter::new(&mut cursor)).expect("should read");
+
+                assert_eq!(parsed.instr, instr);
+                assert_eq!(parsed.delta_ip as usize, instr.encode().unwrap().len());
+            }
+            assert_eq!(cursor.position() as usize, cursor.get_ref().len());
+        }
+
+        #[test]
+        fn peek_doesnt_consume() {
+            let mut iter = StreamMemIter::new([0x01, 0x02, 0x03].as_slice());
+
+            assert_eq!(iter.peek16(), Ok(0x0201));
+            assert_eq!(iter.travelled(), 0);
+            assert_eq!(iter.next8(), Ok(0x01));
+            assert_eq!(iter.next16(), Ok(0x0302));
+            assert_eq!(iter.travelled(), 3);
+        }
+
+        #[test]
+        fn eof_is_truncated() {
+            let mut iter = StreamMemIter::new([0x01, 0x02].as_slice());
+
+            assert_eq!(
+                iter.next32(),
+                Err(DecodeError::Truncated {
+                    needed: 4,
+                    available: 2
+                })
+            );
+            assert_eq!(
+                Instruction::read(StreamMemIter::new([].as_slice())).err(),
+                Some(DecodeError::Truncated {
+                    needed: 1,
+                    available: 0
+                })
+            );
+        }
+    }
+
+    mod mem_iterator {
+        use super::*;
+
+        #[test]
+        fn signed() {
+            let mem = vec![0xFFu8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F, 0x00];
+            let mut iter = MemIterator::new(0, mem.as_slice());
+
+            assert_eq!(iter.next_i8(), Ok(-1));
+            assert_eq!(iter.next_i16(), Ok(-1));
+            assert_eq!(iter.next_i32(), Ok(-1));
+            assert_eq!(iter.next_i16(), Ok(0x7F));
+        }
+
+        #[test]
+        fn signed_wraps_around() {
+            let mem = vec![0xFFu8, 0xFF, 0xFE, 0xFF];
+            let mut iter = MemIterator::new(2, mem.as_slice());
+
+            assert_eq!(iter.next_i32(), Ok(-2));
+            assert_eq!(iter.travelled(), 4);
+        }
+
+        #[test]
+        fn next32_wraps_around() {
+            let mem = vec![0x02u8, 0x03, 0x04, 0x01];
+            let mut iter = MemIterator::new(mem.len() - 1, mem.as_slice());
+
+            assert_eq!(iter.next32(), Ok(0x0403_0201));
+            assert_eq!(iter.travelled(), 4);
+        }
+
+        #[test]
+        fn next_past_len() {
+            let mem = vec![0x01u8, 0x02, 0x03, 0x04];
+            let mut iter = MemIterator::with_len(0, mem.as_slice(), 3);
+
+            assert!(iter.next32().is_err());
+            assert_eq!(iter.travelled(), 0);
+
+            assert_eq!(iter.next16(), Ok(0x0201));
+            assert_eq!(iter.next8(), Ok(0x03));
+            assert!(iter.next8().is_err());
+        }
+
+        #[test]
+        fn little_endian() {
+            let mem = vec![0x01u8, 0x02, 0x03, 0x04];
+            let mut iter = MemIterator::new(0, mem.as_slice());
+
+            assert_eq!(iter.peek32(), Ok(0x0403_0201));
+            assert_eq!(iter.next16(), Ok(0x0201));
+            assert_eq!(iter.next8(), Ok(0x03));
+        }
+
+        #[test]
+        fn big_endian() {
+            let mem = vec![0x01u8, 0x02, 0x03, 0x04];
+            let mut iter = MemIterator::new_be(0, mem.as_slice());
+
+            assert_eq!(iter.peek32(), Ok(0x0102_0304));
+            assert_eq!(iter.next16(), Ok(0x0102));
+            assert_eq!(iter.next8(), Ok(0x03));
+        }
+
+        #[test]
+        fn big_endian_wraps_around() {
+            let mem = vec![0x02u8, 0x03, 0x04, 0x01];
+            let mut iter = MemIterator::new_be(mem.len() - 1, mem.as_slice());
+
+            assert_eq!(iter.next32(), Ok(0x0102_0304));
+        }
+
+        #[test]
+        fn peek_doesnt_advance() {
+            let mem = vec![0x01u8, 0x02, 0x03, 0x04];
+            let mut iter = MemIterator::new(0, mem.as_slice());
+
+            assert_eq!(iter.peek8(), Ok(0x01));
+            assert_eq!(iter.next8(), Ok(0x01));
+            assert_eq!(iter.travelled(), 1);
+
+            assert_eq!(iter.peek16(), Ok(0x0302));
+            assert_eq!(iter.travelled(), 1);
+        }
+
+        #[test]
+        fn peek_wraps_around() {
+            let mem = vec![0x02u8, 0x03, 0x04, 0x01];
+            let iter = MemIterator::new(mem.len() - 1, mem.as_slice());
+
+            assert_eq!(iter.peek32(), Ok(0x0403_0201));
+            assert_eq!(iter.travelled(), 0);
+        }
+
+        #[test]
+        fn peek_past_len() {
+            let mem = vec![0x01u8, 0x02, 0x03, 0x04];
+            let iter = MemIterator::with_len(0, mem.as_slice(), 3);
+
+            assert!(iter.peek32().is_err());
+            assert_eq!(iter.peek16(), Ok(0x0201));
+        }
+
+        #[test]
+        fn next16_wraps_around() {
+            let mem = vec![0x02u8, 0x03, 0x04, 0x01];
+            let mut iter = MemIterator::new(mem.len() - 1, mem.as_slice());
+
+            assert_eq!(iter.next16(), Ok(0x0201));
+            assert_eq!(iter.travelled(), 2);
+            assert_eq!(iter.next8(), Ok(0x03));
+        }
+    }
+
+    mod word {
+        use super::*;
+
+        fn run<W: Word>(cpu: &mut Cpu<W>, program: &[Instruction]) {
+            let bytes: Vec<u8> = program
+                .iter()
+                .flat_map(|instr| instr.encode().unwrap())
+                .collect();
+            for (i, byte) in bytes.into_iter().enumerate() {
+                cpu.mem[i] = byte;
+            }
+
+            while !cpu.is_halted() {
+                cpu.cycle().expect("should run");
+            }
+        }
+
+        #[test]
+        fn same_move_program_under_both_widths() {
+            let program = [
+                Instruction::Move(instr::Move::ImmToReg32(0x1234, Register::A)),
+                Instruction::Move(instr::Move::RegToMem32(Register::A, 0x100)),
+                Instruction::Move(instr::Move::MemToReg32(0x100, Register::B)),
+                Instruction::Move(instr::Move::RegToReg(Register::B, Register::X)),
+                Instruction::Move(instr::Move::ImmToReg8(0x56, Register::Y.low())),
+                Instruction::Move(instr::Move::MemToMem16(0x100, 0x200)),
+                Instruction::Push(Register::A),
+                Instruction::Pop(Register::Y),
+                Instruction::Halt,
+            ];
+
+            let mut wide = Cpu::with_mem_size(0x1000);
+            let mut narrow = Cpu::<u16>::sized(0x1000);
+            run(&mut wide, &program);
+            run(&mut narrow, &program);
+
+            assert!(wide.registers == narrow.registers);
+            assert_eq!(narrow.registers.b, 0x1234);
+            assert_eq!(narrow.registers.y, 0x1234);
+            // the stacks differ, each push left a word behind
+            for addr in (0x100..0x104).chain(0x200..0x202) {
+                assert_eq!(narrow.mem[addr], wide.mem[addr], "0x{addr:x}");
+            }
+        }
+
+        #[test]
+        fn narrow_registers_wrap() {
+            let mut cpu = Cpu::<u16>::sized(0x1000);
+            cpu.registers.a = 0xFFFF;
+
+            run(
+                &mut cpu,
+                &[
+                    Instruction::Add(instr::Add::ImmToReg(1, Register::A)),
+                    Instruction::Halt,
+                ],
+            );
+
+            assert_eq!(cpu.registers.a, 0);
+            assert!(cpu.registers.zero());
+            assert!(cpu.registers.carry());
+
+            cpu.reset(false);
+            run(
+                &mut cpu,
+                &[
+                    Instruction::Move(instr::Move::ImmToReg32(0x1_8000, Register::A)),
+                    Instruction::Halt,
+                ],
+            );
+
+            assert_eq!(cpu.registers.a, 0x8000);
+        }
+
+        #[test]
+        fn narrow_words_are_two_bytes() {
+            let mut cpu = Cpu::<u16>::sized(0x1_0000);
+            assert_eq!(cpu.registers.stack_pointer, 0);
+            cpu.registers.a = 0xBEEF;
+            cpu.mem[0x102] = 0x42;
+
+            run(
+                &mut cpu,
+                &[
+                    Instruction::Move(instr::Move::RegToMem32(Register::A, 0x100)),
+                    Instruction::Push(Register::A),
+                    Instruction::Halt,
+                ],
+            );
+
+            assert_eq!(cpu.mem[0x100], 0xEF);
+            assert_eq!(cpu.mem[0x101], 0xBE);
+            assert_eq!(cpu.mem[0x102], 0x42);
+            assert_eq!(cpu.registers.stack_pointer, 0xFFFE);
+            assert_eq!(cpu.mem[0xFFFE], 0xEF);
+            assert_eq!(cpu.mem[0xFFFF], 0xBE);
+        }
+
+        #[test]
+        fn narrow_addresses_stop_at_word_max() {
+            let mut cpu = Cpu::<u16>::sized(0x2_0000);
+            let instr = Instruction::Move(instr::Move::MemToReg32(0x1_0000, Register::A));
+            for (i, byte) in instr.encode().unwrap().into_iter().enumerate() {
+                cpu.mem[i] = byte;
+            }
+
+            assert!(cpu.cycle().is_err());
+        }
+
+        #[test]
+        fn narrow_jumps_wrap() {
+            let mut cpu = Cpu::<u16>::sized(0x2_0000);
+            let program = [
+                // lands on the call
+                Instruction::Jump(0x1_0006),
+                Instruction::Halt,
+                Instruction::Call(0x1_0000),
+            ];
+            let bytes: Vec<u8> = program
+                .iter()
+                .flat_map(|instr| instr.encode().unwrap())
+                .collect();
+            for (i, byte) in bytes.into_iter().enumerate() {
+                cpu.mem[i] = byte;
+            }
+
+            cpu.cycle().expect("should jump");
+            assert_eq!(cpu.registers.instruction_pointer, 0x6);
+
+            cpu.cycle().expect("should call");
+            assert_eq!(cpu.registers.instruction_pointer, 0);
+        }
+
+        #[test]
+        fn mem_iterator_next_word() {
+            let mem = [0x01, 0x02, 0x03, 0x04];
+
+            let mut iter = MemIterator::new(0, mem.as_slice());
+            assert_eq!(iter.next_word::<u16>(), Ok(0x0201));
+            assert_eq!(iter.travelled(), 2);
+
+            let mut iter = MemIterator::new(0, mem.as_slice());
+            assert_eq!(iter.next_word::<u32>(), Ok(0x0403_0201));
+            assert_eq!(iter.travelled(), 4);
+        }
     }
 }
@@ -0,0 +1,502 @@
+// Copyright (C) 2023  Patrick Cleavelin <patrick@spacegirl.nl>
+
+//! Peripherals that can be mapped over a range of addresses
+
+use alloc::{boxed::Box, vec::Vec};
+use core::any::Any;
+
+/// Where `Machine::install_console` maps the console
+pub const CONSOLE_PORT: u32 = 0xF000_0000;
+/// Where `Machine::install_timer` maps the timer
+pub const TIMER_PORT: u32 = 0xF000_0010;
+/// Where `Machine::install_rng` maps the random number generator
+pub const RNG_PORT: u32 = 0xF000_0020;
+
+/// Something that handles the reads and writes to the addresses it's mapped
+/// over instead of memory, `offset` is relative to the start of its range.
+pub trait Device {
+    fn read(&mut self, offset: u32) -> u8;
+    fn write(&mut self, offset: u32, val: u8);
+
+    /// Called after every cycle with how many cycles it took, gives back the
+    /// vector of an interrupt to raise
+    fn tick(&mut self, _cycles: u64) -> Option<u8> {
+        None
+    }
+
+    /// Lets a mapped device be looked up as its concrete type again
+    fn as_any(&self) -> &dyn Any;
+
+    /// Lets a `Machine` be cloned along with its devices
+    fn clone_box(&self) -> Box<dyn Device>;
+}
+
+impl Clone for Box<dyn Device> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Captures every byte written to its port, reads always give back 0
+#[derive(Debug, Default, Clone)]
+pub struct ConsoleDevice {
+    output: Vec<u8>,
+}
+
+impl ConsoleDevice {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+}
+
+impl Device for ConsoleDevice {
+    fn read(&mut self, _offset: u32) -> u8 {
+        0
+    }
+
+    fn write(&mut self, _offset: u32, val: u8) {
+        self.output.push(val);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+}
+
+/// Counts down cycles and raises an interrupt whenever it reaches zero, then
+/// starts over from the reload value
+///
+/// | offset | size | register                                      |
+/// |--------|------|-----------------------------------------------|
+/// | 0      | 4    | reload value, writing it restarts the count   |
+/// | 4      | 4    | current count, read only                      |
+/// | 8      | 1    | control, bit 0 enables the timer              |
+/// | 9      | 1    | interrupt vector                              |
+#[derive(Debug, Default, Clone)]
+pub struct TimerDevice {
+    reload: u32,
+    count: u32,
+    enabled: bool,
+    vector: u8,
+}
+
+impl TimerDevice {
+    /// How many bytes of registers the timer has
+    pub const LEN: u32 = 10;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reload(&self) -> u32 {
+        self.reload
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn vector(&self) -> u8 {
+        self.vector
+    }
+}
+
+impl Device for TimerDevice {
+    fn read(&mut self, offset: u32) -> u8 {
+        match offset {
+            0..=3 => self.reload.to_le_bytes()[offset as usize],
+            4..=7 => self.count.to_le_bytes()[offset as usize - 4],
+            8 => self.enabled as u8,
+            9 => self.vector,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, val: u8) {
+        match offset {
+            0..=3 => {
+                let mut reload = self.reload.to_le_bytes();
+                reload[offset as usize] = val;
+                self.reload = u32::from_le_bytes(reload);
+                self.count = self.reload;
+            }
+            8 => self.enabled = val & 1 != 0,
+            9 => self.vector = val,
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) -> Option<u8> {
+        // a zero reload value would fire on every cycle
+        if !self.enabled || self.reload == 0 {
+            return None;
+        }
+
+        let mut fired = false;
+        let mut cycles = cycles;
+        while cycles >= self.count as u64 {
+            cycles -= self.count as u64;
+            self.count = self.reload;
+            fired = true;
+        }
+        self.count -= cycles as u32;
+
+        fired.then_some(self.vector)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+}
+
+/// Pseudo random bytes from a xorshift32 generator, the same seed always
+/// gives the same bytes
+///
+/// | offset | size | register                                       |
+/// |--------|------|------------------------------------------------|
+/// | 0      | 4    | every byte read is the next random byte        |
+/// | 4      | 4    | seed, writing it restarts the sequence         |
+#[derive(Debug, Clone)]
+pub struct RngDevice {
+    seed: u32,
+    state: u32,
+}
+
+impl RngDevice {
+    /// How many bytes of registers the generator has
+    pub const LEN: u32 = 8;
+
+    pub fn new(seed: u32) -> Self {
+        let mut rng = Self { seed: 0, state: 0 };
+        rng.set_seed(seed);
+
+        rng
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+        // xorshift gets stuck at zero
+        self.state = if seed == 0 { 0x2545_F491 } else { seed };
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+
+        (self.state >> 24) as u8
+    }
+}
+
+impl Device for RngDevice {
+    fn read(&mut self, offset: u32) -> u8 {
+        match offset {
+            0..=3 => self.next_byte(),
+            4..=7 => self.seed.to_le_bytes()[offset as usize - 4],
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, val: u8) {
+        if let 4..=7 = offset {
+            let mut seed = self.seed.to_le_bytes();
+            seed[offset as usize - 4] = val;
+            self.set_seed(u32::from_le_bytes(seed));
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, rc::Rc, vec};
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::{asm, Machine};
+
+    const PORT: u32 = 0xF000_0000;
+
+    type Written = Rc<RefCell<Vec<(u32, u8)>>>;
+
+    #[derive(Clone)]
+    struct CaptureDevice {
+        written: Written,
+    }
+
+    impl Device for CaptureDevice {
+        fn read(&mut self, offset: u32) -> u8 {
+            0x40 + offset as u8
+        }
+
+        fn write(&mut self, offset: u32, val: u8) {
+            self.written.borrow_mut().push((offset, val));
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn clone_box(&self) -> Box<dyn Device> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn machine_with_capture(range: core::ops::Range<u32>) -> (Machine, Written) {
+        let mut machine = Machine::new();
+        let written = Rc::new(RefCell::new(Vec::new()));
+
+        machine
+            .add_device(
+                range,
+                Box::new(CaptureDevice {
+                    written: written.clone(),
+                }),
+            )
+            .expect("should add device");
+
+        (machine, written)
+    }
+
+    #[test]
+    fn write_goes_to_device() {
+        let (mut machine, written) = machine_with_capture(PORT..PORT + 1);
+
+        let program =
+            asm::assemble("MOV A, 0x42\nMOV BYTE [0xF0000000], A\nHALT").expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+
+        assert_eq!(machine.run_until_halt(10), crate::RunResult::Halted);
+        assert_eq!(*written.borrow(), vec![(0, 0x42)]);
+    }
+
+    #[test]
+    fn read_comes_from_device() {
+        let (mut machine, _) = machine_with_capture(0x100..0x104);
+
+        let program = asm::assemble("MOV A, [0x100]\nHALT").expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+        machine.cpu.mem[0x100] = 0xFF;
+
+        assert_eq!(machine.run_until_halt(10), crate::RunResult::Halted);
+        assert_eq!(machine.cpu.registers.a, 0x4342_4140);
+    }
+
+    #[test]
+    fn write_partially_past_device() {
+        let (mut machine, written) = machine_with_capture(PORT..PORT + 1);
+
+        let program = asm::assemble("MOV [0xF0000000], A").expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+
+        assert!(matches!(
+            machine.run_until_halt(10),
+            crate::RunResult::ExecutionError(_)
+        ));
+        assert!(written.borrow().is_empty());
+    }
+
+    #[test]
+    fn overlapping_devices() {
+        let (mut machine, _) = machine_with_capture(0x100..0x104);
+
+        let written = Rc::new(RefCell::new(Vec::new()));
+        assert!(machine
+            .add_device(0x103..0x110, Box::new(CaptureDevice { written }))
+            .is_err());
+    }
+
+    #[test]
+    fn console_output() {
+        let mut machine = Machine::new();
+        machine.install_console().expect("should install console");
+
+        let program = asm::assemble(
+            r#"
+            MOV A, 0x48
+            MOV BYTE [0xF0000000], A
+            MOV A, 0x69
+            MOV BYTE [0xF0000000], A
+            HALT
+        "#,
+        )
+        .expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+
+        assert_eq!(machine.run_until_halt(10), crate::RunResult::Halted);
+        assert_eq!(
+            machine.console().expect("should have console").output(),
+            b"Hi"
+        );
+    }
+
+    #[test]
+    fn timer_raises_interrupt() {
+        let mut machine = Machine::new();
+        machine.install_timer().expect("should install timer");
+
+        // vector 1 every 10 cycles, each INC A is a single cycle
+        let program = asm::assemble(
+            r#"
+            MOV [0xF0000010], 10
+            MOV BYTE [0xF0000019], 1
+            MOV BYTE [0xF0000018], 1
+            STI
+        loop:
+            INC A
+            JMP loop
+        "#,
+        )
+        .expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+
+        let handler = asm::assemble("INC B\nIRET").expect("should assemble");
+        machine.load_program(0x200, &handler).expect("should load");
+        machine
+            .load_program(
+                crate::cpu::INTERRUPT_VECTOR_BASE + 4,
+                &0x200u32.to_le_bytes(),
+            )
+            .expect("should load");
+
+        assert_eq!(
+            machine.run_until_halt(100),
+            crate::RunResult::CycleLimitReached
+        );
+
+        let timer = machine.timer().expect("should have timer");
+        assert!(timer.enabled());
+        assert_eq!(timer.reload(), 10);
+        assert!(machine.cpu.registers.b > 1);
+        assert!(machine.cpu.registers.a > 0);
+    }
+
+    #[test]
+    fn timer_wakes_halted_cpu() {
+        let mut machine = Machine::new();
+        machine.install_timer().expect("should install timer");
+
+        let program = asm::assemble(
+            r#"
+            MOV [0xF0000010], 20
+            MOV BYTE [0xF0000019], 1
+            MOV BYTE [0xF0000018], 1
+            STI
+            HALT
+            MOV A, 1
+            HALT
+        "#,
+        )
+        .expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+
+        let handler = asm::assemble("INC B\nIRET").expect("should assemble");
+        machine.load_program(0x200, &handler).expect("should load");
+        machine
+            .load_program(
+                crate::cpu::INTERRUPT_VECTOR_BASE + 4,
+                &0x200u32.to_le_bytes(),
+            )
+            .expect("should load");
+
+        assert_eq!(machine.run_until_halt(100), crate::RunResult::Halted);
+        assert_eq!(machine.cpu.registers.b, 0);
+
+        // idle until the timer goes off
+        for _ in 0..20 {
+            machine.run_cycle().expect("should cycle");
+            if !machine.cpu.is_halted() {
+                break;
+            }
+        }
+        assert!(!machine.cpu.is_halted());
+
+        assert_eq!(machine.run_until_halt(100), crate::RunResult::Halted);
+        assert_eq!(machine.cpu.registers.b, 1);
+        assert_eq!(machine.cpu.registers.a, 1);
+    }
+
+    #[test]
+    fn timer_reloads() {
+        let mut timer = TimerDevice::new();
+        timer.write(0, 3);
+        timer.write(9, 0x20);
+
+        assert_eq!(timer.tick(5), None);
+        timer.write(8, 1);
+
+        assert_eq!(timer.tick(2), None);
+        assert_eq!(timer.count(), 1);
+        assert_eq!(timer.tick(1), Some(0x20));
+        assert_eq!(timer.count(), 3);
+        assert_eq!(timer.tick(4), Some(0x20));
+        assert_eq!(timer.count(), 2);
+        assert_eq!(timer.read(4), 2);
+    }
+
+    #[test]
+    fn rng_is_reproducible() {
+        let mut machine = Machine::new();
+        machine.install_rng(0).expect("should install rng");
+
+        let program = asm::assemble(
+            r#"
+            MOV [0xF0000024], 0xC0FFEE
+            MOV A, [0xF0000020]
+            MOV B, [0xF0000020]
+            MOV [0xF0000024], 0xC0FFEE
+            MOV X, [0xF0000020]
+            MOV Y, [0xF0000020]
+            HALT
+        "#,
+        )
+        .expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+
+        assert_eq!(machine.run_until_halt(100), crate::RunResult::Halted);
+        let registers = machine.cpu.registers;
+        assert_eq!(registers.a, registers.x);
+        assert_eq!(registers.b, registers.y);
+        assert_ne!(registers.a, registers.b);
+        assert_eq!(machine.rng().expect("should have rng").seed(), 0xC0FFEE);
+    }
+
+    #[test]
+    fn rng_seeds_differ() {
+        let bytes = |seed| {
+            let mut rng = RngDevice::new(seed);
+            (0..16).map(|_| rng.read(0)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(bytes(1), bytes(1));
+        assert_ne!(bytes(1), bytes(2));
+        assert_ne!(bytes(0), vec![0; 16]);
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright (C) 2023  Patrick Cleavelin <patrick@spacegirl.nl>
+
+//! The `nemu` executable format
+//!
+//! An image is a 12 byte header followed by the code:
+//!
+//! | offset | size | field                           |
+//! |--------|------|---------------------------------|
+//! | 0      | 4    | magic, `b"NEMU"`                |
+//! | 4      | 4    | entry point (little endian)     |
+//! | 8      | 4    | code length (little endian)     |
+//! | 12     | ..   | code, loaded at address 0       |
+
+use alloc::vec::Vec;
+
+pub const MAGIC: [u8; 4] = *b"NEMU";
+pub const HEADER_LEN: usize = 12;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LoadError {
+    BadMagic([u8; 4]),
+    Truncated { needed: usize, available: usize },
+    TooLarge { len: usize, mem_size: usize },
+    EntryOutOfBounds { entry: u32, mem_size: usize },
+}
+
+impl core::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::BadMagic(magic) => write!(
+                f,
+                "Not a nemu executable, expected magic {MAGIC:02x?} but got {magic:02x?}"
+            ),
+            Self::Truncated { needed, available } => write!(
+                f,
+                "Truncated executable: needed {needed} byte(s) but only {available} are available"
+            ),
+            Self::TooLarge { len, mem_size } => write!(
+                f,
+                "Executable code of {len} byte(s) doesn't fit in {mem_size} byte(s) of memory"
+            ),
+            Self::EntryOutOfBounds { entry, mem_size } => write!(
+                f,
+                "Executable entry point 0x{entry:08x} is past the end of {mem_size} byte(s) of memory"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Executable<'a> {
+    pub entry: u32,
+    pub code: &'a [u8],
+}
+
+impl<'a> Executable<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, LoadError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(LoadError::Truncated {
+                needed: HEADER_LEN,
+                available: bytes.len(),
+            });
+        }
+
+        let magic = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if magic != MAGIC {
+            return Err(LoadError::BadMagic(magic));
+        }
+
+        let entry = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let code_len = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+
+        let code = &bytes[HEADER_LEN..];
+        if code.len() < code_len {
+            return Err(LoadError::Truncated {
+                needed: HEADER_LEN + code_len,
+                available: bytes.len(),
+            });
+        }
+
+        Ok(Self {
+            entry,
+            code: &code[..code_len],
+        })
+    }
+
+    /// Builds the image for this executable, the inverse of `parse`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.code.len());
+
+        bytes.extend(MAGIC);
+        bytes.extend(self.entry.to_le_bytes());
+        bytes.extend((self.code.len() as u32).to_le_bytes());
+        bytes.extend(self.code);
+
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn round_trip() {
+            let exe = Executable {
+                entry: 0x10,
+                code: &[0x12, 0x12, 0x0],
+            };
+
+            assert_eq!(Executable::parse(&exe.to_bytes()), Ok(exe));
+        }
+
+        #[test]
+        fn bad_magic() {
+            let mut bytes = Executable {
+                entry: 0,
+                code: &[0x0],
+            }
+            .to_bytes();
+            bytes[0] = b'X';
+
+            assert_eq!(
+                Executable::parse(&bytes),
+                Err(LoadError::BadMagic(*b"XEMU"))
+            );
+        }
+
+        #[test]
+        fn truncated_header() {
+            assert_eq!(
+                Executable::parse(&MAGIC),
+                Err(LoadError::Truncated {
+                    needed: HEADER_LEN,
+                    available: 4
+                })
+            );
+        }
+
+        #[test]
+        fn truncated_code() {
+            let mut bytes = Executable {
+                entry: 0,
+                code: &[0x12, 0x12],
+            }
+            .to_bytes();
+            bytes.pop();
+
+            assert_eq!(
+                Executable::parse(&bytes),
+                Err(LoadError::Truncated {
+                    needed: HEADER_LEN + 2,
+                    available: HEADER_LEN + 1
+                })
+            );
+        }
+
+        #[test]
+        fn trailing_bytes_ignored() {
+            let mut bytes = Executable {
+                entry: 0,
+                code: &[0x12],
+            }
+            .to_bytes();
+            bytes.extend(vec![0xFF; 4]);
+
+            assert_eq!(
+                Executable::parse(&bytes).expect("should parse").code,
+                &[0x12]
+            );
+        }
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright (C) 2023  Patrick Cleavelin <patrick@spacegirl.nl>
+
+//! A minimal GDB remote serial protocol stub
+//!
+//! Only what's needed to poke at a program is supported: `?`, reading the
+//! registers (`g`), reading memory (`m`), adding and removing breakpoints
+//! (`Z0`/`z0`), single stepping (`s`), continuing (`c`), detaching (`D`) and
+//! killing (`k`). Anything else gets the empty "unsupported" reply.
+//!
+//! The `g` reply is every register as 8 hex digits of little endian bytes, in
+//! the order IP, A, B, X, Y, flags. The flags are zero extended to 32 bits.
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, ToSocketAddrs},
+};
+
+use crate::{cpu::CycleError, Machine, RunResult};
+
+/// Waits for a single debugger to connect to `addr` and serves it until it
+/// detaches, kills the program, or disconnects
+pub fn listen(machine: &mut Machine, addr: impl ToSocketAddrs) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let (stream, _) = listener.accept()?;
+
+    serve(machine, stream)
+}
+
+/// Serves a debugger connected over `stream`, see `listen`
+pub fn serve(machine: &mut Machine, mut stream: impl Read + Write) -> io::Result<()> {
+    while let Some(packet) = read_packet(&mut stream)? {
+        stream.write_all(b"+")?;
+
+        let (reply, done) = handle(machine, &packet);
+        write_packet(&mut stream, &reply)?;
+
+        if done {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Gives back the reply to `packet`, and whether the session is over
+fn handle(machine: &mut Machine, packet: &str) -> (String, bool) {
+    let reply = match packet.as_bytes().first() {
+        Some(b'?') => String::from("S05"),
+        Some(b'g') => read_registers(machine),
+        Some(b'm') => read_memory(machine, &packet[1..]).unwrap_or_else(|| String::from("E01")),
+        Some(b'Z' | b'z') => breakpoint(machine, packet).unwrap_or_else(|| String::from("E01")),
+        Some(b's') => stop_reply(match machine.step().error {
+            Some(CycleError::Decode(e)) => RunResult::DecodeError(e),
+            Some(CycleError::Execute(e)) => RunResult::ExecutionError(e),
+            None if machine.cpu.is_halted() => RunResult::Halted,
+            None => RunResult::CycleLimitReached,
+        }),
+        Some(b'c') => stop_reply(machine.run_until_halt(usize::MAX)),
+        Some(b'D') => return (String::from("OK"), true),
+        Some(b'k') => return (String::new(), true),
+        _ => String::new(),
+    };
+
+    (reply, false)
+}
+
+fn read_registers(machine: &Machine) -> String {
+    let registers = machine.snapshot().registers;
+
+    [
+        registers.instruction_pointer,
+        registers.a,
+        registers.b,
+        registers.x,
+        registers.y,
+        u32::from(registers.flags.value()),
+    ]
+    .iter()
+    .flat_map(|value| value.to_le_bytes())
+    .map(|byte| format!("{byte:02x}"))
+    .collect()
+}
+
+// `addr,len`, all in hex
+fn read_memory(machine: &Machine, args: &str) -> Option<String> {
+    let (addr, len) = args.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+
+    if addr.checked_add(len)? > machine.cpu.mem.size() {
+        return None;
+    }
+
+    Some(
+        (addr..addr + len)
+            .map(|addr| format!("{:02x}", machine.cpu.mem[addr]))
+            .collect(),
+    )
+}
+
+// `Z0,addr,kind` or `z0,addr,kind`, only software breakpoints are supported
+fn breakpoint(machine: &mut Machine, packet: &str) -> Option<String> {
+    let mut args = packet[1..].split(',');
+    if args.next()? != "0" {
+        return Some(String::new());
+    }
+    let addr = u32::from_str_radix(args.next()?, 16).ok()?;
+
+    if packet.starts_with('Z') {
+        machine.add_breakpoint(addr);
+    } else {
+        machine.remove_breakpoint(addr);
+    }
+
+    Some(String::from("OK"))
+}
+
+fn stop_reply(result: RunResult) -> String {
+    String::from(match result {
+        RunResult::Halted => "W00",
+        // SIGILL
+        RunResult::DecodeError(_) => "S04",
+        // SIGSEGV
+        RunResult::ExecutionError(_) => "S0b",
+        // SIGTRAP, stepping counts as reaching the cycle limit
+        RunResult::CycleLimitReached | RunResult::Stopped(_) => "S05",
+    })
+}
+
+/// Reads the next `$data#checksum` packet, skipping acks and anything else
+/// in between. `None` once the stream has ended.
+fn read_packet(stream: &mut impl Read) -> io::Result<Option<String>> {
+    let mut byte = [0];
+
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] == b'$' {
+            break;
+        }
+    }
+
+    let mut data = Vec::new();
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] == b'#' {
+            break;
+        }
+        data.push(byte[0]);
+    }
+
+    // the transport is assumed to be reliable, so the checksum isn't checked
+    let mut checksum = [0; 2];
+    stream.read_exact(&mut checksum)?;
+
+    String::from_utf8(data)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_packet(stream: &mut impl Write, data: &str) -> io::Result<()> {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+
+    write!(stream, "${data}#{checksum:02x}")?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod serve {
+        use std::{net::TcpStream, thread};
+
+        use super::*;
+        use crate::{cpu::Register, instr::Instruction};
+
+        // sends each packet and gives back the replies, without the acks
+        fn client(addr: std::net::SocketAddr, packets: Vec<String>) -> Vec<String> {
+            let mut stream = TcpStream::connect(addr).expect("should connect");
+            let mut replies = Vec::new();
+
+            for packet in packets {
+                write_packet(&mut stream, &packet).expect("should send");
+
+                let mut ack = [0];
+                stream.read_exact(&mut ack).expect("should ack");
+                assert_eq!(&ack, b"+");
+
+                replies.push(read_packet(&mut stream).unwrap().expect("should reply"));
+            }
+
+            replies
+        }
+
+        fn session(machine: &mut Machine, packets: &[&str]) -> Vec<String> {
+            let packets = packets.iter().map(|packet| packet.to_string()).collect();
+            let listener = TcpListener::bind("127.0.0.1:0").expect("should bind");
+            let addr = listener.local_addr().unwrap();
+
+            let client = thread::spawn(move || client(addr, packets));
+
+            let (stream, _) = listener.accept().expect("should accept");
+            serve(machine, stream).expect("should serve");
+
+            client.join().expect("client shouldn't panic")
+        }
+
+        #[test]
+        fn read_registers() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.instruction_pointer = 0x10;
+            machine.cpu.registers.a = 0x1234_5678;
+            machine.cpu.registers.b = 2;
+            machine.cpu.registers.x = 3;
+            machine.cpu.registers.y = 0xDEAD_BEEF;
+            machine.cpu.registers.set_carry(true);
+
+            let replies = session(&mut machine, &["g", "D"]);
+
+            assert_eq!(
+                replies,
+                ["10000000785634120200000003000000efbeadde02000000", "OK"]
+            );
+        }
+
+        #[test]
+        fn step_break_and_continue() {
+            let mut machine = Machine::from_instructions(&[
+                Instruction::Inc(Register::A),
+                Instruction::Inc(Register::A),
+                Instruction::Inc(Register::A),
+            ]);
+            machine.cpu.mem[0x100] = 0xAB;
+
+            // each INC is 2 bytes
+            let replies = session(
+                &mut machine,
+                &[
+                    "?",
+                    "s",
+                    "Z0,4,1",
+                    "c",
+                    "m100,2",
+                    "z0,4,1",
+                    "c",
+                    "vMustReplyEmpty",
+                    "k",
+                ],
+            );
+
+            assert_eq!(
+                replies,
+                ["S05", "S05", "OK", "S05", "ab00", "OK", "W00", "", ""]
+            );
+            assert_eq!(machine.cpu.registers.a, 3);
+        }
+
+        #[test]
+        fn read_memory_out_of_bounds() {
+            let mut machine = Machine::new();
+            let size = machine.cpu.mem.size();
+
+            let replies = session(&mut machine, &[&format!("m{:x},2", size - 1), "D"]);
+
+            assert_eq!(replies, ["E01", "OK"]);
+        }
+    }
+}
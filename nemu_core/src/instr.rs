@@ -1,12 +1,32 @@
 // Copyright (C) 2023  Patrick Cleavelin <patrick@spacegirl.nl>
 
 //! Instruction Set Implementation
-use crate::cpu::{MemIter, Register};
+use alloc::{format, string::String, vec, vec::Vec};
+
+use crate::cpu::{ByteRegister, MemIter, MemIterator, Register};
+
+/// Decodes the instruction at the start of `bytes`, giving back how many bytes
+/// it took up. Never reads past the end of `bytes` or panics, whatever they
+/// contain.
+pub fn decode(bytes: &[u8]) -> Result<(Instruction, usize), DecodeError> {
+    let parsed = Instruction::read(MemIterator::with_len(0, bytes, bytes.len()))?;
+
+    Ok((parsed.instr, parsed.delta_ip as usize))
+}
+
+/// How forgiving decoding is, the default accepts everything older programs
+/// might contain
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct DecodeOptions {
+    /// Reject reserved encodings instead of treating them like the closest
+    /// defined one
+    pub strict: bool,
+}
 
 pub trait ReadMem {
     type Item;
 
-    fn read(iter: impl MemIter) -> Result<ParsedInstruction, String>;
+    fn read(iter: impl MemIter) -> Result<ParsedInstruction, DecodeError>;
 }
 
 pub struct ParsedInstruction {
@@ -15,76 +35,567 @@ pub struct ParsedInstruction {
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DecodeError {
+    InvalidGroup(u8),
+    InvalidMoveOpcode(u8),
+    InvalidAddOpcode(u8),
+    InvalidSubOpcode(u8),
+    InvalidShiftOpcode(u8),
+    InvalidAluOpcode(u8),
+    InvalidScale(u8),
+    ReservedEncoding(u8),
+    InvalidRegister(u8),
+    Truncated { needed: usize, available: usize },
+}
+
+impl core::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidGroup(group) => {
+                write!(f, "Should have gotten a valid group value, not {group:01x}")
+            }
+            Self::InvalidMoveOpcode(opcode) => write!(
+                f,
+                "Should have gotten valid move opcode, instead got {opcode:08b}"
+            ),
+            Self::InvalidAddOpcode(opcode) => write!(
+                f,
+                "Should have gotten valid add opcode, instead got {opcode:08b}"
+            ),
+            Self::InvalidSubOpcode(opcode) => write!(
+                f,
+                "Should have gotten valid sub opcode, instead got {opcode:08b}"
+            ),
+            Self::InvalidShiftOpcode(opcode) => write!(
+                f,
+                "Should have gotten valid shift opcode, instead got {opcode:08b}"
+            ),
+            Self::InvalidAluOpcode(opcode) => write!(
+                f,
+                "Should have gotten valid alu opcode, instead got {opcode:08b}"
+            ),
+            Self::InvalidScale(scale) => write!(
+                f,
+                "Should have gotten a scale of 1, 2 or 4, instead got shift {scale}"
+            ),
+            Self::ReservedEncoding(opcode) => {
+                write!(f, "Got reserved encoding {opcode:08b}")
+            }
+            Self::InvalidRegister(id) => write!(f, "Got invalid register id: 0x{id:01x}"),
+            Self::Truncated { needed, available } => write!(
+                f,
+                "Truncated instruction: needed {needed} more byte(s) but only {available} are available"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instruction {
     Move(Move),
+    Add(Add),
+    Sub(Sub),
+    Logic(Logic),
+    Shift(ShiftOp),
+    Alu(Alu),
+    /// Loads the address `AddrExpr` points at into the register, without
+    /// touching memory
+    Lea(Register, AddrExpr),
+    Cmp(Register, Register),
+    CmpImm(Register, u32),
+    Jump(u32),
+    JumpIfZero(u32),
+    JumpIfNotZero(u32),
+    /// Jumps by an offset from the address right after the jump
+    JumpRel(i32),
+    /// Signed jumps, for after a `Cmp`
+    JumpIfLess(u32),
+    JumpIfGreaterOrEqual(u32),
+    JumpIfGreater(u32),
+    JumpIfLessOrEqual(u32),
+    /// Jumps to the address if the registers are equal, without touching
+    /// the flags
+    BranchIfEqual(Register, Register, u32),
+    /// Jumps to the address if the registers differ, without touching the
+    /// flags
+    BranchIfNotEqual(Register, Register, u32),
+    Push(Register),
+    Pop(Register),
+    Call(u32),
+    Ret,
+    Neg(Register),
+    Inc(Register),
+    Dec(Register),
+    Nop,
+    Swap(Register, Register),
+    /// Swaps a register with the dword at an address
+    Xchg(Register, u32),
+    /// `dst = dst * src`, with the high 32 bits of the product going into Y
+    Mul(Register, Register),
+    /// `dst = dst / src`, with the remainder going into Y. Dividing by zero
+    /// faults and halts the CPU.
+    Div(Register, Register),
+    /// Returns from an interrupt handler, restoring IP and the flags
+    Iret,
+    /// Pushes A, B, X, Y and then the flags
+    PushAll,
+    /// Pops everything `PushAll` pushed, in reverse
+    PopAll,
+    /// Disables interrupts
+    Cli,
+    /// Enables interrupts
+    Sti,
     Halt,
 }
 
 impl ReadMem for Instruction {
     type Item = u8;
 
-    fn read(mut iter: impl MemIter) -> Result<ParsedInstruction, String> {
-        let group_value = iter.next8();
+    fn read(iter: impl MemIter) -> Result<ParsedInstruction, DecodeError> {
+        Self::read_with(iter, DecodeOptions::default())
+    }
+}
+
+impl Instruction {
+    pub fn read_with(
+        mut iter: impl MemIter,
+        options: DecodeOptions,
+    ) -> Result<ParsedInstruction, DecodeError> {
+        let group_value = iter.next8()?;
 
         Ok(match group_value {
             0x0 => ParsedInstruction {
                 instr: Self::Halt,
                 delta_ip: 1,
             },
-            0x1 => {
-                let parsed = Move::read(iter)?;
+            // the group byte is read from the same iterator, so it is already
+            // counted in the delta_ip of the parsed instruction
+            0x1 => Move::read_with(iter, options)?,
+            0x2 => Add::read(iter)?,
+            0x3 => Sub::read(iter)?,
+            0xC => Logic::read(iter)?,
+            0xD => ShiftOp::read(iter)?,
+            0x4 => {
+                let addr = iter.next32()?;
+
+                ParsedInstruction {
+                    instr: Self::Jump(addr),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x5 => {
+                let addr = iter.next32()?;
+
+                ParsedInstruction {
+                    instr: Self::JumpIfZero(addr),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x6 => {
+                let addr = iter.next32()?;
+
+                ParsedInstruction {
+                    instr: Self::JumpIfNotZero(addr),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x7 => {
+                let reg = Register::try_from_id(iter.next8()?)?;
+
+                ParsedInstruction {
+                    instr: Self::Push(reg),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x8 => {
+                let reg = Register::try_from_id(iter.next8()?)?;
+
+                ParsedInstruction {
+                    instr: Self::Pop(reg),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x9 => {
+                let addr = iter.next32()?;
+
+                ParsedInstruction {
+                    instr: Self::Call(addr),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0xA => ParsedInstruction {
+                instr: Self::Ret,
+                delta_ip: 1,
+            },
+            0xB => {
+                let reg = Register::try_from_id(iter.next8()?)?;
+
+                ParsedInstruction {
+                    instr: Self::Neg(reg),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0xE => {
+                let reg_a = Register::try_from_id(iter.next8()?)?;
+                let reg_b = Register::try_from_id(iter.next8()?)?;
+
+                ParsedInstruction {
+                    instr: Self::Cmp(reg_a, reg_b),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0xF => {
+                let reg = Register::try_from_id(iter.next8()?)?;
+                let imm = iter.next32()?;
+
+                ParsedInstruction {
+                    instr: Self::CmpImm(reg, imm),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x10 => {
+                let reg = Register::try_from_id(iter.next8()?)?;
+
+                ParsedInstruction {
+                    instr: Self::Inc(reg),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x11 => {
+                let reg = Register::try_from_id(iter.next8()?)?;
+
+                ParsedInstruction {
+                    instr: Self::Dec(reg),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x12 => ParsedInstruction {
+                instr: Self::Nop,
+                delta_ip: 1,
+            },
+            0x13 => {
+                let reg_a = Register::try_from_id(iter.next8()?)?;
+                let reg_b = Register::try_from_id(iter.next8()?)?;
+
+                ParsedInstruction {
+                    instr: Self::Swap(reg_a, reg_b),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x14 => {
+                let reg_src = Register::try_from_id(iter.next8()?)?;
+                let reg_dst = Register::try_from_id(iter.next8()?)?;
+
+                ParsedInstruction {
+                    instr: Self::Mul(reg_src, reg_dst),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x15 => {
+                let reg_src = Register::try_from_id(iter.next8()?)?;
+                let reg_dst = Register::try_from_id(iter.next8()?)?;
+
+                ParsedInstruction {
+                    instr: Self::Div(reg_src, reg_dst),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x16 => {
+                let offset = iter.next_i32()?;
+
+                ParsedInstruction {
+                    instr: Self::JumpRel(offset),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x17 => ParsedInstruction {
+                instr: Self::Iret,
+                delta_ip: 1,
+            },
+            0x23 => ParsedInstruction {
+                instr: Self::PushAll,
+                delta_ip: 1,
+            },
+            0x24 => ParsedInstruction {
+                instr: Self::PopAll,
+                delta_ip: 1,
+            },
+            0x18 => ParsedInstruction {
+                instr: Self::Cli,
+                delta_ip: 1,
+            },
+            0x19 => ParsedInstruction {
+                instr: Self::Sti,
+                delta_ip: 1,
+            },
+            0x1A => Alu::read(iter)?,
+            0x1B => {
+                let reg_dst = Register::try_from_id(iter.next8()?)?;
+                let base = Register::try_from_id(iter.next8()?)?;
+                let displacement = iter.next_i32()?;
+
+                ParsedInstruction {
+                    instr: Self::Lea(reg_dst, AddrExpr { base, displacement }),
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x1C | 0x1D => {
+                let reg_a = Register::try_from_id(iter.next8()?)?;
+                let reg_b = Register::try_from_id(iter.next8()?)?;
+                let addr = iter.next32()?;
 
                 ParsedInstruction {
-                    instr: parsed.instr,
-                    delta_ip: parsed.delta_ip + 1,
+                    instr: if group_value == 0x1C {
+                        Self::BranchIfEqual(reg_a, reg_b, addr)
+                    } else {
+                        Self::BranchIfNotEqual(reg_a, reg_b, addr)
+                    },
+                    delta_ip: iter.travelled() as u32,
+                }
+            }
+            0x1E..=0x21 => {
+                let addr = iter.next32()?;
+
+                ParsedInstruction {
+                    instr: match group_value {
+                        0x1E => Self::JumpIfLess(addr),
+                        0x1F => Self::JumpIfGreaterOrEqual(addr),
+                        0x20 => Self::JumpIfGreater(addr),
+                        _ => Self::JumpIfLessOrEqual(addr),
+                    },
+                    delta_ip: iter.travelled() as u32,
                 }
             }
+            0x22 => {
+                let reg = Register::try_from_id(iter.next8()?)?;
+                let addr = iter.next32()?;
 
-            _ => {
-                return Err(format!(
-                    "Should have gotten a valid group value, not {group_value:01x}"
-                ));
+                ParsedInstruction {
+                    instr: Self::Xchg(reg, addr),
+                    delta_ip: iter.travelled() as u32,
+                }
             }
+
+            _ => return Err(DecodeError::InvalidGroup(group_value)),
         })
     }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Move {
     RegToReg(Register, Register),
     ImmToReg32(u32, Register),
     ImmToReg16(u16, Register),
-    ImmToReg8(u8, Register),
+    ImmToReg8(u8, ByteRegister),
 
     RegToMem32(Register, u32),
     RegToMem16(Register, u32),
-    RegToMem8(Register, u32),
+    RegToMem8(ByteRegister, u32),
 
     MemToReg32(u32, Register),
     MemToReg16(u32, Register),
-    MemToReg8(u32, Register),
+    MemToReg8(u32, ByteRegister),
 
     MemToMem32(u32, u32),
     MemToMem16(u32, u32),
     MemToMem8(u32, u32),
+
+    ImmToMem32(u32, u32),
+    ImmToMem16(u16, u32),
+    ImmToMem8(u8, u32),
+
+    RegIndirectToReg(Register, Register),
+    RegToRegIndirect(Register, Register),
+
+    IndexedToReg(Indexed, Register),
+    RegToIndexed(Register, Indexed),
+}
+
+/// `[base + index * scale]`, where the scale is 1, 2 or 4
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Indexed {
+    pub base: Register,
+    pub index: Register,
+    pub scale: u8,
 }
 
+impl Indexed {
+    // the scale is encoded as a shift
+    fn read(iter: &mut impl MemIter) -> Result<Self, DecodeError> {
+        let base = Register::try_from_id(iter.next8()?)?;
+        let index = Register::try_from_id(iter.next8()?)?;
+        let scale = match iter.next8()? {
+            shift @ 0..=2 => 1 << shift,
+            shift => return Err(DecodeError::InvalidScale(shift)),
+        };
+
+        Ok(Self { base, index, scale })
+    }
+
+    fn encode(&self) -> Result<[u8; 3], String> {
+        let shift = match self.scale {
+            1 => 0,
+            2 => 1,
+            4 => 2,
+            scale => return Err(format!("Invalid scale {scale}, must be 1, 2 or 4")),
+        };
+
+        Ok([self.base.id(), self.index.id(), shift])
+    }
+}
+
+// set in the move group byte of reg-to-mem and mem-to-reg moves to use the
+// address held in a register instead of an absolute address
+const MOVE_INDIRECT: u8 = 0b0000_1000;
+// set in the move group byte of reg-to-mem moves to store an immediate
+// instead of a register
+const MOVE_IMMEDIATE: u8 = 0b0000_0100;
+// set in the move group byte of reg-to-mem and mem-to-reg moves to use an
+// `Indexed` address
+const MOVE_INDEXED: u8 = 0b0000_0010;
+
+/// One of the addressing modes of `MOV`, as listed by `Move::all_modes`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MoveMode {
+    /// How it's written in the assembler
+    pub syntax: &'static str,
+    /// The move group byte following the `0x1` instruction group
+    pub opcode: u8,
+    /// What's encoded after the move group byte, in order
+    pub operands: &'static str,
+}
+
+const fn mode(syntax: &'static str, opcode: u8, operands: &'static str) -> MoveMode {
+    MoveMode {
+        syntax,
+        opcode,
+        operands,
+    }
+}
+
+const MOVE_MODES: [MoveMode; 20] = [
+    mode("MOV dst, src", 0b0000_0000, "src reg, dst reg"),
+    mode("MOV dst, BYTE imm", 0b0001_0000, "imm8, dst reg"),
+    mode("MOV dst, WORD imm", 0b0010_0000, "imm16, dst reg"),
+    mode("MOV dst, imm", 0b0011_0000, "imm32, dst reg"),
+    mode("MOV BYTE [addr], src", 0b0100_0000, "src reg, addr32"),
+    mode("MOV WORD [addr], src", 0b0101_0000, "src reg, addr32"),
+    mode("MOV [addr], src", 0b0110_0000, "src reg, addr32"),
+    mode(
+        "MOV BYTE [addr], imm",
+        0b0100_0000 | MOVE_IMMEDIATE,
+        "imm8, addr32",
+    ),
+    mode(
+        "MOV WORD [addr], imm",
+        0b0101_0000 | MOVE_IMMEDIATE,
+        "imm16, addr32",
+    ),
+    mode(
+        "MOV [addr], imm",
+        0b0110_0000 | MOVE_IMMEDIATE,
+        "imm32, addr32",
+    ),
+    mode(
+        "MOV [dst], src",
+        0b0110_0000 | MOVE_INDIRECT,
+        "src reg, dst reg",
+    ),
+    mode(
+        "MOV [base + index * scale], src",
+        0b0110_0000 | MOVE_INDEXED,
+        "src reg, base reg, index reg, scale shift",
+    ),
+    mode("MOV dst, BYTE [addr]", 0b1000_0000, "addr32, dst reg"),
+    mode("MOV dst, WORD [addr]", 0b1001_0000, "addr32, dst reg"),
+    mode("MOV dst, [addr]", 0b1010_0000, "addr32, dst reg"),
+    mode(
+        "MOV dst, [src]",
+        0b1010_0000 | MOVE_INDIRECT,
+        "src reg, dst reg",
+    ),
+    mode(
+        "MOV dst, [base + index * scale]",
+        0b1010_0000 | MOVE_INDEXED,
+        "base reg, index reg, scale shift, dst reg",
+    ),
+    mode(
+        "MOV BYTE [dst], [src]",
+        0b1100_0000,
+        "src addr32, dst addr32",
+    ),
+    mode(
+        "MOV WORD [dst], [src]",
+        0b1101_0000,
+        "src addr32, dst addr32",
+    ),
+    mode("MOV [dst], [src]", 0b1110_0000, "src addr32, dst addr32"),
+];
+
 impl ReadMem for Move {
     type Item = u8;
 
-    fn read(mut iter: impl MemIter) -> Result<ParsedInstruction, String> {
-        let move_group = iter.next8();
+    fn read(iter: impl MemIter) -> Result<ParsedInstruction, DecodeError> {
+        Self::read_with(iter, DecodeOptions::default())
+    }
+}
+
+impl Move {
+    /// How many operand bytes follow `move_group`, decided the same way as
+    /// `read_with` without strict decoding
+    fn operands_len(move_group: u8) -> usize {
+        let size = (move_group & 0x30) >> 4;
+
+        match (move_group & 0xC0) >> 6 {
+            0 => [2, 2, 3, 5][size as usize],
+            1 if move_group & MOVE_IMMEDIATE != 0 => [5, 6, 8, 8][size as usize],
+            1 | 2 if move_group & MOVE_INDIRECT != 0 => 2,
+            // a register and `Indexed`
+            1 | 2 if move_group & MOVE_INDEXED != 0 => 4,
+            1 | 2 => 5,
+            _ => 8,
+        }
+    }
+
+    /// The size bits of moves to and from memory only go up to `2` for 32
+    /// bits, `3` is reserved and read as 32 bits unless decoding is strict
+    pub fn read_with(
+        mut iter: impl MemIter,
+        options: DecodeOptions,
+    ) -> Result<ParsedInstruction, DecodeError> {
+        let move_group = iter.next8()?;
+
+        if options.strict && move_group & 0xC0 != 0 && move_group & 0x30 == 0x30 {
+            return Err(DecodeError::ReservedEncoding(move_group));
+        }
 
         match (move_group & 0xC0) >> 6 {
             0 => {
                 let move_instr = match (move_group & 0x30) >> 4 {
                     0 => Self::RegToReg(
-                        Register::try_from_id(iter.next8())?,
-                        Register::try_from_id(iter.next8())?,
+                        Register::try_from_id(iter.next8()?)?,
+                        Register::try_from_id(iter.next8()?)?,
                     ),
-                    1 => Self::ImmToReg8(iter.next8(), Register::try_from_id(iter.next8())?),
-                    2 => Self::ImmToReg16(iter.next16(), Register::try_from_id(iter.next8())?),
-                    3 => Self::ImmToReg32(iter.next32(), Register::try_from_id(iter.next8())?),
+                    1 => Self::ImmToReg8(iter.next8()?, ByteRegister::try_from_id(iter.next8()?)?),
+                    2 => Self::ImmToReg16(iter.next16()?, Register::try_from_id(iter.next8()?)?),
+                    3 => Self::ImmToReg32(iter.next32()?, Register::try_from_id(iter.next8()?)?),
+                    _ => unreachable!("there only can be 4 possiblities"),
+                };
+
+                Ok(ParsedInstruction {
+                    instr: Instruction::Move(move_instr),
+                    delta_ip: iter.travelled() as u32,
+                })
+            }
+
+            1 if move_group & MOVE_IMMEDIATE != 0 => {
+                let move_instr = match (move_group & 0x30) >> 4 {
+                    0 => Self::ImmToMem8(iter.next8()?, iter.next32()?),
+                    1 => Self::ImmToMem16(iter.next16()?, iter.next32()?),
+                    2 | 3 => Self::ImmToMem32(iter.next32()?, iter.next32()?),
                     _ => unreachable!("there only can be 4 possiblities"),
                 };
 
@@ -93,15 +604,53 @@ impl ReadMem for Move {
                     delta_ip: iter.travelled() as u32,
                 })
             }
+            1 if move_group & MOVE_INDIRECT != 0 => {
+                let reg_src = Register::try_from_id(iter.next8()?)?;
+                let reg_dst = Register::try_from_id(iter.next8()?)?;
+
+                Ok(ParsedInstruction {
+                    instr: Instruction::Move(Self::RegToRegIndirect(reg_src, reg_dst)),
+                    delta_ip: iter.travelled() as u32,
+                })
+            }
+            2 if move_group & MOVE_INDIRECT != 0 => {
+                let reg_src = Register::try_from_id(iter.next8()?)?;
+                let reg_dst = Register::try_from_id(iter.next8()?)?;
+
+                Ok(ParsedInstruction {
+                    instr: Instruction::Move(Self::RegIndirectToReg(reg_src, reg_dst)),
+                    delta_ip: iter.travelled() as u32,
+                })
+            }
+            1 if move_group & MOVE_INDEXED != 0 => {
+                let reg_src = Register::try_from_id(iter.next8()?)?;
+                let indexed = Indexed::read(&mut iter)?;
+
+                Ok(ParsedInstruction {
+                    instr: Instruction::Move(Self::RegToIndexed(reg_src, indexed)),
+                    delta_ip: iter.travelled() as u32,
+                })
+            }
+            2 if move_group & MOVE_INDEXED != 0 => {
+                let indexed = Indexed::read(&mut iter)?;
+                let reg_dst = Register::try_from_id(iter.next8()?)?;
+
+                Ok(ParsedInstruction {
+                    instr: Instruction::Move(Self::IndexedToReg(indexed, reg_dst)),
+                    delta_ip: iter.travelled() as u32,
+                })
+            }
 
             1 => {
-                let reg_src = Register::try_from_id(iter.next8())?;
-                let addr_dst = iter.next32();
+                // byte moves can address the high byte, so the id is only
+                // decoded once the size is known
+                let reg_src = iter.next8()?;
+                let addr_dst = iter.next32()?;
 
                 let move_instr = match (move_group & 0x30) >> 4 {
-                    0 => Self::RegToMem8(reg_src, addr_dst),
-                    1 => Self::RegToMem16(reg_src, addr_dst),
-                    2 | 3 => Self::RegToMem32(reg_src, addr_dst),
+                    0 => Self::RegToMem8(ByteRegister::try_from_id(reg_src)?, addr_dst),
+                    1 => Self::RegToMem16(Register::try_from_id(reg_src)?, addr_dst),
+                    2 | 3 => Self::RegToMem32(Register::try_from_id(reg_src)?, addr_dst),
                     _ => unreachable!("there only can be 4 possiblities"),
                 };
 
@@ -111,13 +660,13 @@ impl ReadMem for Move {
                 })
             }
             2 => {
-                let addr_src = iter.next32();
-                let reg_dst = Register::try_from_id(iter.next8())?;
+                let addr_src = iter.next32()?;
+                let reg_dst = iter.next8()?;
 
                 let move_instr = match (move_group & 0x30) >> 4 {
-                    0 => Self::MemToReg8(addr_src, reg_dst),
-                    1 => Self::MemToReg16(addr_src, reg_dst),
-                    2 | 3 => Self::MemToReg32(addr_src, reg_dst),
+                    0 => Self::MemToReg8(addr_src, ByteRegister::try_from_id(reg_dst)?),
+                    1 => Self::MemToReg16(addr_src, Register::try_from_id(reg_dst)?),
+                    2 | 3 => Self::MemToReg32(addr_src, Register::try_from_id(reg_dst)?),
                     _ => unreachable!("there can only be 4 possibilites"),
                 };
 
@@ -127,8 +676,8 @@ impl ReadMem for Move {
                 })
             }
             3 => {
-                let addr_src = iter.next32();
-                let addr_dst = iter.next32();
+                let addr_src = iter.next32()?;
+                let addr_dst = iter.next32()?;
 
                 let move_instr = match (move_group & 0x30) >> 4 {
                     0 => Self::MemToMem8(addr_src, addr_dst),
@@ -142,309 +691,4282 @@ impl ReadMem for Move {
                     delta_ip: iter.travelled() as u32,
                 })
             }
-            _ => Err(format!(
-                "Should have gotten valid move opcode, instead got {move_group:08b}"
-            )),
+            _ => Err(DecodeError::InvalidMoveOpcode(move_group)),
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Add {
+    RegToReg(Register, Register),
+    ImmToReg(u32, Register),
+    MemToReg(u32, Register),
+}
 
-    mod mov {
-        use super::*;
-        use crate::{cpu::MemIterator, Machine};
+impl ReadMem for Add {
+    type Item = u8;
 
-        #[test]
-        fn read_mem() {
-            let reg_to_reg = vec![0b0000_0000u8, 0, 0];
-            let imm_to_reg32 = vec![0b0011_0000u8, 0, 0, 0, 0, 0];
-            let imm_to_reg16 = vec![0b0010_0000u8, 0, 0, 0];
-            let imm_to_reg8 = vec![0b0001_0000u8, 0, 0];
+    fn read(mut iter: impl MemIter) -> Result<ParsedInstruction, DecodeError> {
+        let add_group = iter.next8()?;
 
-            let reg_to_mem32 = vec![0b0110_0000u8, 0, 0, 0, 0, 0];
-            let reg_to_mem16 = vec![0b0101_0000u8, 0, 0, 0, 0, 0];
-            let reg_to_mem8 = vec![0b0100_0000u8, 0, 0, 0, 0, 0];
+        let add_instr = match (add_group & 0xC0) >> 6 {
+            0 => Self::RegToReg(
+                Register::try_from_id(iter.next8()?)?,
+                Register::try_from_id(iter.next8()?)?,
+            ),
+            1 => Self::ImmToReg(iter.next32()?, Register::try_from_id(iter.next8()?)?),
+            2 => Self::MemToReg(iter.next32()?, Register::try_from_id(iter.next8()?)?),
+            _ => return Err(DecodeError::InvalidAddOpcode(add_group)),
+        };
 
-            let mem_to_reg32 = vec![0b1010_0000u8, 0, 0, 0, 0, 0];
-            let mem_to_reg16 = vec![0b1001_0000u8, 0, 0, 0, 0, 0];
-            let mem_to_reg8 = vec![0b1000_0000u8, 0, 0, 0, 0, 0];
+        Ok(ParsedInstruction {
+            instr: Instruction::Add(add_instr),
+            delta_ip: iter.travelled() as u32,
+        })
+    }
+}
 
-            let mem_to_mem32 = vec![0b1110_0000u8, 0, 0, 0, 0, 0, 0, 0, 0];
-            let mem_to_mem16 = vec![0b1101_0000u8, 0, 0, 0, 0, 0, 0, 0, 0];
-            let mem_to_mem8 = vec![0b1100_0000u8, 0, 0, 0, 0, 0, 0, 0, 0];
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Sub {
+    RegToReg(Register, Register),
+    ImmToReg(u32, Register),
+    MemToReg(u32, Register),
+}
 
-            let reg_to_reg_instr =
-                Move::read(MemIterator::new(0, reg_to_reg.as_slice())).expect("should read");
-            let imm_to_reg32_instr =
-                Move::read(MemIterator::new(0, imm_to_reg32.as_slice())).expect("should read");
-            let imm_to_reg16_instr =
-                Move::read(MemIterator::new(0, imm_to_reg16.as_slice())).expect("should read");
-            let imm_to_reg8_instr =
-                Move::read(MemIterator::new(0, imm_to_reg8.as_slice())).expect("should read");
-            let reg_to_mem32_instr =
-                Move::read(MemIterator::new(0, reg_to_mem32.as_slice())).expect("should read");
-            let reg_to_mem16_instr =
-                Move::read(MemIterator::new(0, reg_to_mem16.as_slice())).expect("should read");
-            let reg_to_mem8_instr =
-                Move::read(MemIterator::new(0, reg_to_mem8.as_slice())).expect("should read");
-            let mem_to_reg32_instr =
-                Move::read(MemIterator::new(0, mem_to_reg32.as_slice())).expect("should read");
-            let mem_to_reg16_instr =
-                Move::read(MemIterator::new(0, mem_to_reg16.as_slice())).expect("should read");
-            let mem_to_reg8_instr =
-                Move::read(MemIterator::new(0, mem_to_reg8.as_slice())).expect("should read");
-            let mem_to_mem32_instr =
-                Move::read(MemIterator::new(0, mem_to_mem32.as_slice())).expect("should read");
-            let mem_to_mem16_instr =
-                Move::read(MemIterator::new(0, mem_to_mem16.as_slice())).expect("should read");
-            let mem_to_mem8_instr =
-                Move::read(MemIterator::new(0, mem_to_mem8.as_slice())).expect("should read");
+impl ReadMem for Sub {
+    type Item = u8;
 
+    fn read(mut iter: impl MemIter) -> Result<ParsedInstruction, DecodeError> {
+        let sub_group = iter.next8()?;
+
+        let sub_instr = match (sub_group & 0xC0) >> 6 {
+            0 => Self::RegToReg(
+                Register::try_from_id(iter.next8()?)?,
+                Register::try_from_id(iter.next8()?)?,
+            ),
+            1 => Self::ImmToReg(iter.next32()?, Register::try_from_id(iter.next8()?)?),
+            2 => Self::MemToReg(iter.next32()?, Register::try_from_id(iter.next8()?)?),
+            _ => return Err(DecodeError::InvalidSubOpcode(sub_group)),
+        };
+
+        Ok(ParsedInstruction {
+            instr: Instruction::Sub(sub_instr),
+            delta_ip: iter.travelled() as u32,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Logic {
+    And(Register, Register),
+    Or(Register, Register),
+    Xor(Register, Register),
+    Not(Register),
+}
+
+impl ReadMem for Logic {
+    type Item = u8;
+
+    fn read(mut iter: impl MemIter) -> Result<ParsedInstruction, DecodeError> {
+        let logic_group = iter.next8()?;
+
+        let logic_instr = match (logic_group & 0xC0) >> 6 {
+            0 => Self::And(
+                Register::try_from_id(iter.next8()?)?,
+                Register::try_from_id(iter.next8()?)?,
+            ),
+            1 => Self::Or(
+                Register::try_from_id(iter.next8()?)?,
+                Register::try_from_id(iter.next8()?)?,
+            ),
+            2 => Self::Xor(
+                Register::try_from_id(iter.next8()?)?,
+                Register::try_from_id(iter.next8()?)?,
+            ),
+            3 => Self::Not(Register::try_from_id(iter.next8()?)?),
+            _ => unreachable!("there can only be 4 possibilites"),
+        };
+
+        Ok(ParsedInstruction {
+            instr: Instruction::Logic(logic_instr),
+            delta_ip: iter.travelled() as u32,
+        })
+    }
+}
+
+/// Shifts/rotates a register by an immediate count, which gets masked to `count & 31`
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShiftOp {
+    Shl(Register, u8),
+    Shr(Register, u8),
+    Sar(Register, u8),
+    Rol(Register, u8),
+    Ror(Register, u8),
+}
+
+impl ReadMem for ShiftOp {
+    type Item = u8;
+
+    fn read(mut iter: impl MemIter) -> Result<ParsedInstruction, DecodeError> {
+        let shift_group = iter.next8()?;
+        let reg = Register::try_from_id(iter.next8()?)?;
+        let count = iter.next8()?;
+
+        let shift_instr = match (shift_group & 0xE0) >> 5 {
+            0 => Self::Shl(reg, count),
+            1 => Self::Shr(reg, count),
+            2 => Self::Sar(reg, count),
+            3 => Self::Rol(reg, count),
+            4 => Self::Ror(reg, count),
+            _ => return Err(DecodeError::InvalidShiftOpcode(shift_group)),
+        };
+
+        Ok(ParsedInstruction {
+            instr: Instruction::Shift(shift_instr),
+            delta_ip: iter.travelled() as u32,
+        })
+    }
+}
+
+/// `base + displacement`
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddrExpr {
+    pub base: Register,
+    pub displacement: i32,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AluOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    /// Like `Sub` but only keeps the flags
+    Cmp,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AluSource {
+    Reg(Register),
+    Imm(u32),
+    Mem(u32),
+}
+
+/// `dst = dst <op> src` for any operation and source, encoded as a single
+/// opcode byte of `mode << 6 | op << 3` where the modes are the same as `Add`
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Alu {
+    pub op: AluOp,
+    pub src: AluSource,
+    pub dst: Register,
+}
+
+impl ReadMem for Alu {
+    type Item = u8;
+
+    fn read(mut iter: impl MemIter) -> Result<ParsedInstruction, DecodeError> {
+        let alu_group = iter.next8()?;
+
+        let op = match (alu_group & 0x38) >> 3 {
+            0 => AluOp::Add,
+            1 => AluOp::Sub,
+            2 => AluOp::And,
+            3 => AluOp::Or,
+            4 => AluOp::Xor,
+            5 => AluOp::Cmp,
+            _ => return Err(DecodeError::InvalidAluOpcode(alu_group)),
+        };
+        if alu_group & 0x07 != 0 {
+            return Err(DecodeError::InvalidAluOpcode(alu_group));
+        }
+
+        let src = match (alu_group & 0xC0) >> 6 {
+            0 => AluSource::Reg(Register::try_from_id(iter.next8()?)?),
+            1 => AluSource::Imm(iter.next32()?),
+            2 => AluSource::Mem(iter.next32()?),
+            _ => return Err(DecodeError::InvalidAluOpcode(alu_group)),
+        };
+        let dst = Register::try_from_id(iter.next8()?)?;
+
+        Ok(ParsedInstruction {
+            instr: Instruction::Alu(Alu { op, src, dst }),
+            delta_ip: iter.travelled() as u32,
+        })
+    }
+}
+
+const MNEMONICS: [&str; 42] = [
+    "HALT", "MOV", "LEA", "ADD", "SUB", "JMP", "JZ", "JNZ", "JL", "JGE", "JG", "JLE", "JMPR",
+    "BEQ", "BNE", "PUSH", "POP", "PUSHA", "POPA", "CALL", "RET", "NEG", "AND", "OR", "XOR", "NOT",
+    "SHL", "SHR", "SAR", "ROL", "ROR", "CMP", "INC", "DEC", "NOP", "SWAP", "XCHG", "MUL", "DIV",
+    "IRET", "CLI", "STI",
+];
+
+impl Instruction {
+    /// The `Alu` instruction this runs as. ADD, SUB, CMP and the bitwise
+    /// `Logic` groups predate `Alu` and are only kept as aliases of it, `None`
+    /// for everything else including NOT.
+    pub fn as_alu(&self) -> Option<Alu> {
+        let (op, src, dst) = match *self {
+            Self::Alu(alu_instr) => return Some(alu_instr),
+            Self::Add(Add::RegToReg(src, dst)) => (AluOp::Add, AluSource::Reg(src), dst),
+            Self::Add(Add::ImmToReg(imm, dst)) => (AluOp::Add, AluSource::Imm(imm), dst),
+            Self::Add(Add::MemToReg(addr, dst)) => (AluOp::Add, AluSource::Mem(addr), dst),
+            Self::Sub(Sub::RegToReg(src, dst)) => (AluOp::Sub, AluSource::Reg(src), dst),
+            Self::Sub(Sub::ImmToReg(imm, dst)) => (AluOp::Sub, AluSource::Imm(imm), dst),
+            Self::Sub(Sub::MemToReg(addr, dst)) => (AluOp::Sub, AluSource::Mem(addr), dst),
+            Self::Logic(Logic::And(src, dst)) => (AluOp::And, AluSource::Reg(src), dst),
+            Self::Logic(Logic::Or(src, dst)) => (AluOp::Or, AluSource::Reg(src), dst),
+            Self::Logic(Logic::Xor(src, dst)) => (AluOp::Xor, AluSource::Reg(src), dst),
+            Self::Cmp(reg_a, reg_b) => (AluOp::Cmp, AluSource::Reg(reg_b), reg_a),
+            Self::CmpImm(reg, imm) => (AluOp::Cmp, AluSource::Imm(imm), reg),
+            _ => return None,
+        };
+
+        Some(Alu { op, src, dst })
+    }
+
+    /// Every mnemonic the assembler accepts and `Display` writes
+    pub fn all_mnemonics() -> &'static [&'static str] {
+        &MNEMONICS
+    }
+
+    /// One of each variant with made up operands, including every variant of
+    /// the instruction groups, meant for testing the decoder and executor
+    pub fn variants_sample() -> Vec<Instruction> {
+        let mut sample = Move::variants_sample()
+            .into_iter()
+            .map(Self::Move)
+            .collect::<Vec<_>>();
+
+        sample.extend([
+            Self::Add(Add::RegToReg(Register::A, Register::B)),
+            Self::Add(Add::ImmToReg(0xDEAD_BEEF, Register::X)),
+            Self::Add(Add::MemToReg(0x0102_0304, Register::Y)),
+            Self::Sub(Sub::RegToReg(Register::B, Register::A)),
+            Self::Sub(Sub::ImmToReg(0xDEAD_BEEF, Register::Y)),
+            Self::Sub(Sub::MemToReg(0x0102_0304, Register::X)),
+            Self::Logic(Logic::And(Register::A, Register::B)),
+            Self::Logic(Logic::Or(Register::X, Register::Y)),
+            Self::Logic(Logic::Xor(Register::Sp, Register::A)),
+            Self::Logic(Logic::Not(Register::B)),
+            Self::Shift(ShiftOp::Shl(Register::A, 1)),
+            Self::Shift(ShiftOp::Shr(Register::B, 31)),
+            Self::Shift(ShiftOp::Sar(Register::X, 4)),
+            Self::Shift(ShiftOp::Rol(Register::Y, 8)),
+            Self::Shift(ShiftOp::Ror(Register::A, 16)),
+        ]);
+
+        let sources = [
+            AluSource::Reg(Register::B),
+            AluSource::Imm(0xDEAD_BEEF),
+            AluSource::Mem(0x0102_0304),
+        ];
+        let ops = [
+            AluOp::Add,
+            AluOp::Sub,
+            AluOp::And,
+            AluOp::Or,
+            AluOp::Xor,
+            AluOp::Cmp,
+        ];
+        sample.extend(
+            ops.into_iter()
+                .zip(sources.into_iter().cycle())
+                .map(|(op, src)| {
+                    Self::Alu(Alu {
+                        op,
+                        src,
+                        dst: Register::A,
+                    })
+                }),
+        );
+
+        sample.extend([
+            Self::Lea(
+                Register::A,
+                AddrExpr {
+                    base: Register::X,
+                    displacement: -4,
+                },
+            ),
+            Self::Cmp(Register::A, Register::B),
+            Self::CmpImm(Register::X, 0xDEAD_BEEF),
+            Self::Jump(0x0102_0304),
+            Self::JumpIfZero(0x0102_0304),
+            Self::JumpIfNotZero(0x0102_0304),
+            Self::JumpRel(-0x10),
+            Self::JumpIfLess(0x0102_0304),
+            Self::JumpIfGreaterOrEqual(0x0102_0304),
+            Self::JumpIfGreater(0x0102_0304),
+            Self::JumpIfLessOrEqual(0x0102_0304),
+            Self::BranchIfEqual(Register::A, Register::B, 0x0102_0304),
+            Self::BranchIfNotEqual(Register::X, Register::Y, 0x0102_0304),
+            Self::Push(Register::A),
+            Self::Pop(Register::B),
+            Self::Call(0x0102_0304),
+            Self::Ret,
+            Self::Neg(Register::X),
+            Self::Inc(Register::Y),
+            Self::Dec(Register::A),
+            Self::Nop,
+            Self::Swap(Register::A, Register::B),
+            Self::Xchg(Register::Y, 0x0102_0304),
+            Self::Mul(Register::B, Register::A),
+            Self::Div(Register::X, Register::B),
+            Self::Iret,
+            Self::PushAll,
+            Self::PopAll,
+            Self::Cli,
+            Self::Sti,
+            Self::Halt,
+        ]);
+
+        sample
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Move(_) => "MOV",
+            Self::Add(_) => "ADD",
+            Self::Sub(_) => "SUB",
+            Self::Logic(Logic::And(..)) => "AND",
+            Self::Logic(Logic::Or(..)) => "OR",
+            Self::Logic(Logic::Xor(..)) => "XOR",
+            Self::Logic(Logic::Not(..)) => "NOT",
+            Self::Shift(ShiftOp::Shl(..)) => "SHL",
+            Self::Shift(ShiftOp::Shr(..)) => "SHR",
+            Self::Shift(ShiftOp::Sar(..)) => "SAR",
+            Self::Shift(ShiftOp::Rol(..)) => "ROL",
+            Self::Shift(ShiftOp::Ror(..)) => "ROR",
+            Self::Alu(alu_instr) => alu_instr.op.mnemonic(),
+            Self::Lea(..) => "LEA",
+            Self::Jump(_) => "JMP",
+            Self::JumpIfZero(_) => "JZ",
+            Self::JumpIfNotZero(_) => "JNZ",
+            Self::JumpRel(_) => "JMPR",
+            Self::JumpIfLess(_) => "JL",
+            Self::JumpIfGreaterOrEqual(_) => "JGE",
+            Self::JumpIfGreater(_) => "JG",
+            Self::JumpIfLessOrEqual(_) => "JLE",
+            Self::BranchIfEqual(..) => "BEQ",
+            Self::BranchIfNotEqual(..) => "BNE",
+            Self::Push(_) => "PUSH",
+            Self::Pop(_) => "POP",
+            Self::Call(_) => "CALL",
+            Self::Ret => "RET",
+            Self::Neg(_) => "NEG",
+            Self::Inc(_) => "INC",
+            Self::Dec(_) => "DEC",
+            Self::Nop => "NOP",
+            Self::Swap(..) => "SWAP",
+            Self::Xchg(..) => "XCHG",
+            Self::Mul(..) => "MUL",
+            Self::Div(..) => "DIV",
+            Self::Cmp(..) | Self::CmpImm(..) => "CMP",
+            Self::Iret => "IRET",
+            Self::PushAll => "PUSHA",
+            Self::PopAll => "POPA",
+            Self::Cli => "CLI",
+            Self::Sti => "STI",
+            Self::Halt => "HALT",
+        }
+    }
+
+    /// The length of the instruction at the start of `bytes` from just its
+    /// group and opcode bytes, the same as the `delta_ip` that `read` gives
+    /// back. Operands aren't looked at, so they can be missing or invalid.
+    pub fn length_from(bytes: &[u8]) -> Result<usize, DecodeError> {
+        let byte = |index: usize| {
+            bytes.get(index).copied().ok_or(DecodeError::Truncated {
+                needed: 1,
+                available: 0,
+            })
+        };
+
+        let group_value = byte(0)?;
+        Ok(match group_value {
+            0x0 | 0xA | 0x12 | 0x17..=0x19 | 0x23 | 0x24 => 1,
+            0x7 | 0x8 | 0xB | 0x10 | 0x11 => 2,
+            0xE | 0x13..=0x15 => 3,
+            0x4..=0x6 | 0x9 | 0x16 | 0x1E..=0x21 => 5,
+            0xF | 0x22 => 6,
+            0x1B..=0x1D => 7,
+            0x1 => 2 + Move::operands_len(byte(1)?),
+            0x2 | 0x3 => {
+                let opcode = byte(1)?;
+                match (opcode & 0xC0) >> 6 {
+                    0 => 4,
+                    1 | 2 => 7,
+                    _ if group_value == 0x2 => return Err(DecodeError::InvalidAddOpcode(opcode)),
+                    _ => return Err(DecodeError::InvalidSubOpcode(opcode)),
+                }
+            }
+            0xC => match byte(1)? >> 6 {
+                3 => 3,
+                _ => 4,
+            },
+            0xD => {
+                let opcode = byte(1)?;
+                if (opcode & 0xE0) >> 5 > 4 {
+                    return Err(DecodeError::InvalidShiftOpcode(opcode));
+                }
+
+                4
+            }
+            0x1A => {
+                let opcode = byte(1)?;
+                if (opcode & 0x38) >> 3 > 5 || opcode & 0x07 != 0 {
+                    return Err(DecodeError::InvalidAluOpcode(opcode));
+                }
+
+                match (opcode & 0xC0) >> 6 {
+                    0 => 4,
+                    1 | 2 => 7,
+                    _ => return Err(DecodeError::InvalidAluOpcode(opcode)),
+                }
+            }
+            _ => return Err(DecodeError::InvalidGroup(group_value)),
+        })
+    }
+
+    /// How many cycles the instruction takes, one to execute plus one for
+    /// every memory access, with multiplying and dividing taking longer
+    pub fn cost(&self) -> u32 {
+        match self {
+            Self::Move(move_instr) => match move_instr {
+                Move::RegToReg(..)
+                | Move::ImmToReg32(..)
+                | Move::ImmToReg16(..)
+                | Move::ImmToReg8(..) => 1,
+                Move::MemToMem32(..) | Move::MemToMem16(..) | Move::MemToMem8(..) => 3,
+                _ => 2,
+            },
+            Self::Add(Add::MemToReg(..)) | Self::Sub(Sub::MemToReg(..)) => 2,
+            Self::Alu(Alu {
+                src: AluSource::Mem(_),
+                ..
+            }) => 2,
+            Self::Push(_) | Self::Pop(_) | Self::Call(_) | Self::Ret => 2,
+            Self::Xchg(..) | Self::Iret => 3,
+            // one access for each of A, B, X, Y and the flags
+            Self::PushAll | Self::PopAll => 6,
+            Self::Mul(..) => 3,
+            Self::Div(..) => 4,
+            _ => 1,
+        }
+    }
+
+    /// Encodes the instruction into the bytes that `Instruction::read` decodes
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+
+        match self {
+            Self::Halt => bytes.push(0x0),
+            Self::Move(move_instr) => {
+                bytes.push(0x1);
+                bytes.extend(move_instr.encode()?);
+            }
+            Self::Add(add_instr) => {
+                bytes.push(0x2);
+                bytes.extend(add_instr.encode()?);
+            }
+            Self::Sub(sub_instr) => {
+                bytes.push(0x3);
+                bytes.extend(sub_instr.encode()?);
+            }
+            Self::Logic(logic_instr) => {
+                bytes.push(0xC);
+                bytes.extend(logic_instr.encode()?);
+            }
+            Self::Shift(shift_instr) => {
+                bytes.push(0xD);
+                bytes.extend(shift_instr.encode()?);
+            }
+            Self::Jump(addr) => {
+                bytes.push(0x4);
+                bytes.extend(addr.to_le_bytes());
+            }
+            Self::JumpIfZero(addr) => {
+                bytes.push(0x5);
+                bytes.extend(addr.to_le_bytes());
+            }
+            Self::JumpIfNotZero(addr) => {
+                bytes.push(0x6);
+                bytes.extend(addr.to_le_bytes());
+            }
+            Self::JumpRel(offset) => {
+                bytes.push(0x16);
+                bytes.extend(offset.to_le_bytes());
+            }
+            Self::JumpIfLess(addr)
+            | Self::JumpIfGreaterOrEqual(addr)
+            | Self::JumpIfGreater(addr)
+            | Self::JumpIfLessOrEqual(addr) => {
+                bytes.push(match self {
+                    Self::JumpIfLess(_) => 0x1E,
+                    Self::JumpIfGreaterOrEqual(_) => 0x1F,
+                    Self::JumpIfGreater(_) => 0x20,
+                    _ => 0x21,
+                });
+                bytes.extend(addr.to_le_bytes());
+            }
+            Self::BranchIfEqual(reg_a, reg_b, addr)
+            | Self::BranchIfNotEqual(reg_a, reg_b, addr) => {
+                bytes.push(match self {
+                    Self::BranchIfEqual(..) => 0x1C,
+                    _ => 0x1D,
+                });
+                bytes.push(reg_a.id());
+                bytes.push(reg_b.id());
+                bytes.extend(addr.to_le_bytes());
+            }
+            Self::Push(reg) => {
+                bytes.push(0x7);
+                bytes.push(reg.id());
+            }
+            Self::Pop(reg) => {
+                bytes.push(0x8);
+                bytes.push(reg.id());
+            }
+            Self::Call(addr) => {
+                bytes.push(0x9);
+                bytes.extend(addr.to_le_bytes());
+            }
+            Self::Ret => bytes.push(0xA),
+            Self::Neg(reg) => {
+                bytes.push(0xB);
+                bytes.push(reg.id());
+            }
+            Self::Inc(reg) => {
+                bytes.push(0x10);
+                bytes.push(reg.id());
+            }
+            Self::Dec(reg) => {
+                bytes.push(0x11);
+                bytes.push(reg.id());
+            }
+            Self::Nop => bytes.push(0x12),
+            Self::Swap(reg_a, reg_b) => {
+                bytes.push(0x13);
+                bytes.push(reg_a.id());
+                bytes.push(reg_b.id());
+            }
+            Self::Xchg(reg, addr) => {
+                bytes.push(0x22);
+                bytes.push(reg.id());
+                bytes.extend(addr.to_le_bytes());
+            }
+            Self::Mul(reg_src, reg_dst) => {
+                bytes.push(0x14);
+                bytes.push(reg_src.id());
+                bytes.push(reg_dst.id());
+            }
+            Self::Div(reg_src, reg_dst) => {
+                bytes.push(0x15);
+                bytes.push(reg_src.id());
+                bytes.push(reg_dst.id());
+            }
+            Self::Iret => bytes.push(0x17),
+            Self::PushAll => bytes.push(0x23),
+            Self::PopAll => bytes.push(0x24),
+            Self::Cli => bytes.push(0x18),
+            Self::Sti => bytes.push(0x19),
+            Self::Alu(alu_instr) => {
+                bytes.push(0x1A);
+                bytes.extend(alu_instr.encode()?);
+            }
+            Self::Lea(reg_dst, addr) => {
+                bytes.push(0x1B);
+                bytes.push(reg_dst.id());
+                bytes.push(addr.base.id());
+                bytes.extend(addr.displacement.to_le_bytes());
+            }
+            Self::Cmp(reg_a, reg_b) => {
+                bytes.push(0xE);
+                bytes.push(reg_a.id());
+                bytes.push(reg_b.id());
+            }
+            Self::CmpImm(reg, imm) => {
+                bytes.push(0xF);
+                bytes.push(reg.id());
+                bytes.extend(imm.to_le_bytes());
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl Move {
+    /// Every addressing mode `Move` can be encoded with
+    pub fn all_modes() -> &'static [MoveMode] {
+        &MOVE_MODES
+    }
+
+    /// One of each variant with made up operands
+    pub fn variants_sample() -> Vec<Move> {
+        let indexed = Indexed {
+            base: Register::X,
+            index: Register::Y,
+            scale: 4,
+        };
+
+        vec![
+            Self::RegToReg(Register::A, Register::B),
+            Self::ImmToReg32(0xDEAD_BEEF, Register::A),
+            Self::ImmToReg16(0xBEEF, Register::B),
+            Self::ImmToReg8(0xEF, Register::X.low()),
+            Self::RegToMem32(Register::A, 0x0102_0304),
+            Self::RegToMem16(Register::B, 0x0102_0304),
+            Self::RegToMem8(Register::X.low(), 0x0102_0304),
+            Self::MemToReg32(0x0102_0304, Register::Y),
+            Self::MemToReg16(0x0102_0304, Register::A),
+            Self::MemToReg8(0x0102_0304, Register::B.low()),
+            Self::MemToMem32(0x0102_0304, 0x0506_0708),
+            Self::MemToMem16(0x0102_0304, 0x0506_0708),
+            Self::MemToMem8(0x0102_0304, 0x0506_0708),
+            Self::ImmToMem32(0xDEAD_BEEF, 0x0102_0304),
+            Self::ImmToMem16(0xBEEF, 0x0102_0304),
+            Self::ImmToMem8(0xEF, 0x0102_0304),
+            Self::RegIndirectToReg(Register::X, Register::A),
+            Self::RegToRegIndirect(Register::A, Register::X),
+            Self::IndexedToReg(indexed, Register::A),
+            Self::RegToIndexed(Register::B, indexed),
+        ]
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+
+        match *self {
+            Self::RegToReg(reg_src, reg_dst) => {
+                bytes.push(0b0000_0000);
+                bytes.push(reg_src.id());
+                bytes.push(reg_dst.id());
+            }
+            Self::ImmToReg8(imm, reg_dst) => {
+                bytes.push(0b0001_0000);
+                bytes.push(imm);
+                bytes.push(reg_dst.id());
+            }
+            Self::ImmToReg16(imm, reg_dst) => {
+                bytes.push(0b0010_0000);
+                bytes.extend(imm.to_le_bytes());
+                bytes.push(reg_dst.id());
+            }
+            Self::ImmToReg32(imm, reg_dst) => {
+                bytes.push(0b0011_0000);
+                bytes.extend(imm.to_le_bytes());
+                bytes.push(reg_dst.id());
+            }
+
+            Self::RegToMem8(reg_src, addr_dst) => {
+                bytes.push(0b0100_0000);
+                bytes.push(reg_src.id());
+                bytes.extend(addr_dst.to_le_bytes());
+            }
+            Self::RegToMem16(reg_src, addr_dst) | Self::RegToMem32(reg_src, addr_dst) => {
+                bytes.push(match self {
+                    Self::RegToMem16(..) => 0b0101_0000,
+                    _ => 0b0110_0000,
+                });
+                bytes.push(reg_src.id());
+                bytes.extend(addr_dst.to_le_bytes());
+            }
+
+            Self::MemToReg8(addr_src, reg_dst) => {
+                bytes.push(0b1000_0000);
+                bytes.extend(addr_src.to_le_bytes());
+                bytes.push(reg_dst.id());
+            }
+            Self::MemToReg16(addr_src, reg_dst) | Self::MemToReg32(addr_src, reg_dst) => {
+                bytes.push(match self {
+                    Self::MemToReg16(..) => 0b1001_0000,
+                    _ => 0b1010_0000,
+                });
+                bytes.extend(addr_src.to_le_bytes());
+                bytes.push(reg_dst.id());
+            }
+
+            Self::MemToMem8(addr_src, addr_dst)
+            | Self::MemToMem16(addr_src, addr_dst)
+            | Self::MemToMem32(addr_src, addr_dst) => {
+                bytes.push(match self {
+                    Self::MemToMem8(..) => 0b1100_0000,
+                    Self::MemToMem16(..) => 0b1101_0000,
+                    _ => 0b1110_0000,
+                });
+                bytes.extend(addr_src.to_le_bytes());
+                bytes.extend(addr_dst.to_le_bytes());
+            }
+
+            Self::ImmToMem8(imm, addr_dst) => {
+                bytes.push(0b0100_0000 | MOVE_IMMEDIATE);
+                bytes.push(imm);
+                bytes.extend(addr_dst.to_le_bytes());
+            }
+            Self::ImmToMem16(imm, addr_dst) => {
+                bytes.push(0b0101_0000 | MOVE_IMMEDIATE);
+                bytes.extend(imm.to_le_bytes());
+                bytes.extend(addr_dst.to_le_bytes());
+            }
+            Self::ImmToMem32(imm, addr_dst) => {
+                bytes.push(0b0110_0000 | MOVE_IMMEDIATE);
+                bytes.extend(imm.to_le_bytes());
+                bytes.extend(addr_dst.to_le_bytes());
+            }
+
+            Self::RegToRegIndirect(reg_src, reg_dst) => {
+                bytes.push(0b0110_0000 | MOVE_INDIRECT);
+                bytes.push(reg_src.id());
+                bytes.push(reg_dst.id());
+            }
+            Self::RegIndirectToReg(reg_src, reg_dst) => {
+                bytes.push(0b1010_0000 | MOVE_INDIRECT);
+                bytes.push(reg_src.id());
+                bytes.push(reg_dst.id());
+            }
+
+            Self::RegToIndexed(reg_src, indexed) => {
+                bytes.push(0b0110_0000 | MOVE_INDEXED);
+                bytes.push(reg_src.id());
+                bytes.extend(indexed.encode()?);
+            }
+            Self::IndexedToReg(indexed, reg_dst) => {
+                bytes.push(0b1010_0000 | MOVE_INDEXED);
+                bytes.extend(indexed.encode()?);
+                bytes.push(reg_dst.id());
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl Add {
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        encode_arithmetic(match *self {
+            Self::RegToReg(reg_src, reg_dst) => ArithmeticOperand::Reg(reg_src, reg_dst),
+            Self::ImmToReg(imm, reg_dst) => ArithmeticOperand::Imm(imm, reg_dst),
+            Self::MemToReg(addr, reg_dst) => ArithmeticOperand::Mem(addr, reg_dst),
+        })
+    }
+}
+
+impl Sub {
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        encode_arithmetic(match *self {
+            Self::RegToReg(reg_src, reg_dst) => ArithmeticOperand::Reg(reg_src, reg_dst),
+            Self::ImmToReg(imm, reg_dst) => ArithmeticOperand::Imm(imm, reg_dst),
+            Self::MemToReg(addr, reg_dst) => ArithmeticOperand::Mem(addr, reg_dst),
+        })
+    }
+}
+
+impl Logic {
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+
+        match *self {
+            Self::And(reg_src, reg_dst)
+            | Self::Or(reg_src, reg_dst)
+            | Self::Xor(reg_src, reg_dst) => {
+                bytes.push(match self {
+                    Self::And(..) => 0b0000_0000,
+                    Self::Or(..) => 0b0100_0000,
+                    _ => 0b1000_0000,
+                });
+                bytes.push(reg_src.id());
+                bytes.push(reg_dst.id());
+            }
+            Self::Not(reg) => {
+                bytes.push(0b1100_0000);
+                bytes.push(reg.id());
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl ShiftOp {
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let (op, reg, count) = match *self {
+            Self::Shl(reg, count) => (0, reg, count),
+            Self::Shr(reg, count) => (1, reg, count),
+            Self::Sar(reg, count) => (2, reg, count),
+            Self::Rol(reg, count) => (3, reg, count),
+            Self::Ror(reg, count) => (4, reg, count),
+        };
+
+        Ok(vec![op << 5, reg.id(), count])
+    }
+}
+
+impl AluOp {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Add => "ADD",
+            Self::Sub => "SUB",
+            Self::And => "AND",
+            Self::Or => "OR",
+            Self::Xor => "XOR",
+            Self::Cmp => "CMP",
+        }
+    }
+}
+
+impl Alu {
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let op = match self.op {
+            AluOp::Add => 0,
+            AluOp::Sub => 1,
+            AluOp::And => 2,
+            AluOp::Or => 3,
+            AluOp::Xor => 4,
+            AluOp::Cmp => 5,
+        };
+
+        let mut bytes = encode_arithmetic(match self.src {
+            AluSource::Reg(reg_src) => ArithmeticOperand::Reg(reg_src, self.dst),
+            AluSource::Imm(imm) => ArithmeticOperand::Imm(imm, self.dst),
+            AluSource::Mem(addr) => ArithmeticOperand::Mem(addr, self.dst),
+        })?;
+        bytes[0] |= op << 3;
+
+        Ok(bytes)
+    }
+}
+
+enum ArithmeticOperand {
+    Reg(Register, Register),
+    Imm(u32, Register),
+    Mem(u32, Register),
+}
+
+fn encode_arithmetic(operand: ArithmeticOperand) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+
+    match operand {
+        ArithmeticOperand::Reg(reg_src, reg_dst) => {
+            bytes.push(0b0000_0000);
+            bytes.push(reg_src.id());
+            bytes.push(reg_dst.id());
+        }
+        ArithmeticOperand::Imm(imm, reg_dst) => {
+            bytes.push(0b0100_0000);
+            bytes.extend(imm.to_le_bytes());
+            bytes.push(reg_dst.id());
+        }
+        ArithmeticOperand::Mem(addr, reg_dst) => {
+            bytes.push(0b1000_0000);
+            bytes.extend(addr.to_le_bytes());
+            bytes.push(reg_dst.id());
+        }
+    }
+
+    Ok(bytes)
+}
+
+// Operands are written destination first, with 16 and 8 bit memory and
+// immediate operands tagged with their size
+impl core::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Move(move_instr) => write!(f, "{move_instr}"),
+            Self::Add(add_instr) => write!(f, "{add_instr}"),
+            Self::Sub(sub_instr) => write!(f, "{sub_instr}"),
+            Self::Logic(logic_instr) => write!(f, "{logic_instr}"),
+            Self::Shift(shift_instr) => write!(f, "{shift_instr}"),
+            Self::Alu(alu_instr) => write!(f, "{alu_instr}"),
+            Self::Lea(dst, addr) => write!(f, "LEA {}, {addr}", dst.name()),
+            Self::Jump(addr) => write!(f, "JMP 0x{addr:08x}"),
+            Self::JumpIfZero(addr) => write!(f, "JZ 0x{addr:08x}"),
+            Self::JumpIfNotZero(addr) => write!(f, "JNZ 0x{addr:08x}"),
+            Self::JumpRel(offset) => write!(f, "JMPR {offset}"),
+            Self::JumpIfLess(addr) => write!(f, "JL 0x{addr:08x}"),
+            Self::JumpIfGreaterOrEqual(addr) => write!(f, "JGE 0x{addr:08x}"),
+            Self::JumpIfGreater(addr) => write!(f, "JG 0x{addr:08x}"),
+            Self::JumpIfLessOrEqual(addr) => write!(f, "JLE 0x{addr:08x}"),
+            Self::BranchIfEqual(reg_a, reg_b, addr) => {
+                write!(f, "BEQ {}, {}, 0x{addr:08x}", reg_a.name(), reg_b.name())
+            }
+            Self::BranchIfNotEqual(reg_a, reg_b, addr) => {
+                write!(f, "BNE {}, {}, 0x{addr:08x}", reg_a.name(), reg_b.name())
+            }
+            Self::Push(reg) => write!(f, "PUSH {}", reg.name()),
+            Self::Pop(reg) => write!(f, "POP {}", reg.name()),
+            Self::Call(addr) => write!(f, "CALL 0x{addr:08x}"),
+            Self::Ret => write!(f, "RET"),
+            Self::Neg(reg) => write!(f, "NEG {}", reg.name()),
+            Self::Inc(reg) => write!(f, "INC {}", reg.name()),
+            Self::Dec(reg) => write!(f, "DEC {}", reg.name()),
+            Self::Nop => write!(f, "NOP"),
+            Self::Swap(reg_a, reg_b) => write!(f, "SWAP {}, {}", reg_a.name(), reg_b.name()),
+            Self::Xchg(reg, addr) => write!(f, "XCHG {}, [0x{addr:08x}]", reg.name()),
+            Self::Mul(src, dst) => write!(f, "MUL {}, {}", dst.name(), src.name()),
+            Self::Div(src, dst) => write!(f, "DIV {}, {}", dst.name(), src.name()),
+            Self::Iret => write!(f, "IRET"),
+            Self::PushAll => write!(f, "PUSHA"),
+            Self::PopAll => write!(f, "POPA"),
+            Self::Cli => write!(f, "CLI"),
+            Self::Sti => write!(f, "STI"),
+            Self::Cmp(reg_a, reg_b) => write!(f, "CMP {}, {}", reg_a.name(), reg_b.name()),
+            Self::CmpImm(reg, imm) => write!(f, "CMP {}, 0x{imm:08x}", reg.name()),
+            Self::Halt => write!(f, "HALT"),
+        }
+    }
+}
+
+impl core::fmt::Display for Move {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::RegToReg(src, dst) => write!(f, "MOV {}, {}", dst.name(), src.name()),
+            Self::ImmToReg32(imm, dst) => write!(f, "MOV {}, 0x{imm:08x}", dst.name()),
+            Self::ImmToReg16(imm, dst) => write!(f, "MOV {}, WORD 0x{imm:04x}", dst.name()),
+            Self::ImmToReg8(imm, dst) => write!(f, "MOV {dst}, BYTE 0x{imm:02x}"),
+
+            Self::RegToMem32(src, addr) => write!(f, "MOV [0x{addr:08x}], {}", src.name()),
+            Self::RegToMem16(src, addr) => write!(f, "MOV WORD [0x{addr:08x}], {}", src.name()),
+            Self::RegToMem8(src, addr) => write!(f, "MOV BYTE [0x{addr:08x}], {src}"),
+
+            Self::MemToReg32(addr, dst) => write!(f, "MOV {}, [0x{addr:08x}]", dst.name()),
+            Self::MemToReg16(addr, dst) => write!(f, "MOV {}, WORD [0x{addr:08x}]", dst.name()),
+            Self::MemToReg8(addr, dst) => write!(f, "MOV {dst}, BYTE [0x{addr:08x}]"),
+
+            Self::MemToMem32(src, dst) => write!(f, "MOV [0x{dst:08x}], [0x{src:08x}]"),
+            Self::MemToMem16(src, dst) => write!(f, "MOV WORD [0x{dst:08x}], [0x{src:08x}]"),
+            Self::MemToMem8(src, dst) => write!(f, "MOV BYTE [0x{dst:08x}], [0x{src:08x}]"),
+
+            Self::ImmToMem32(imm, addr) => write!(f, "MOV [0x{addr:08x}], 0x{imm:08x}"),
+            Self::ImmToMem16(imm, addr) => write!(f, "MOV WORD [0x{addr:08x}], 0x{imm:04x}"),
+            Self::ImmToMem8(imm, addr) => write!(f, "MOV BYTE [0x{addr:08x}], 0x{imm:02x}"),
+
+            Self::RegIndirectToReg(src, dst) => write!(f, "MOV {}, [{}]", dst.name(), src.name()),
+            Self::RegToRegIndirect(src, dst) => write!(f, "MOV [{}], {}", dst.name(), src.name()),
+
+            Self::IndexedToReg(indexed, dst) => write!(f, "MOV {}, {indexed}", dst.name()),
+            Self::RegToIndexed(src, indexed) => write!(f, "MOV {indexed}, {}", src.name()),
+        }
+    }
+}
+
+impl core::fmt::Display for Add {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::RegToReg(src, dst) => write!(f, "ADD {}, {}", dst.name(), src.name()),
+            Self::ImmToReg(imm, dst) => write!(f, "ADD {}, 0x{imm:08x}", dst.name()),
+            Self::MemToReg(addr, dst) => write!(f, "ADD {}, [0x{addr:08x}]", dst.name()),
+        }
+    }
+}
+
+impl core::fmt::Display for Sub {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::RegToReg(src, dst) => write!(f, "SUB {}, {}", dst.name(), src.name()),
+            Self::ImmToReg(imm, dst) => write!(f, "SUB {}, 0x{imm:08x}", dst.name()),
+            Self::MemToReg(addr, dst) => write!(f, "SUB {}, [0x{addr:08x}]", dst.name()),
+        }
+    }
+}
+
+impl core::fmt::Display for Logic {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::And(src, dst) => write!(f, "AND {}, {}", dst.name(), src.name()),
+            Self::Or(src, dst) => write!(f, "OR {}, {}", dst.name(), src.name()),
+            Self::Xor(src, dst) => write!(f, "XOR {}, {}", dst.name(), src.name()),
+            Self::Not(reg) => write!(f, "NOT {}", reg.name()),
+        }
+    }
+}
+
+impl core::fmt::Display for ShiftOp {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Shl(reg, count) => write!(f, "SHL {}, {count}", reg.name()),
+            Self::Shr(reg, count) => write!(f, "SHR {}, {count}", reg.name()),
+            Self::Sar(reg, count) => write!(f, "SAR {}, {count}", reg.name()),
+            Self::Rol(reg, count) => write!(f, "ROL {}, {count}", reg.name()),
+            Self::Ror(reg, count) => write!(f, "ROR {}, {count}", reg.name()),
+        }
+    }
+}
+
+impl core::fmt::Display for Indexed {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "[{} + {} * {}]",
+            self.base.name(),
+            self.index.name(),
+            self.scale
+        )
+    }
+}
+
+impl core::fmt::Display for AddrExpr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let sign = if self.displacement < 0 { '-' } else { '+' };
+        write!(
+            f,
+            "[{} {sign} 0x{:x}]",
+            self.base.name(),
+            self.displacement.unsigned_abs()
+        )
+    }
+}
+
+impl core::fmt::Display for Alu {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (op, dst) = (self.op.mnemonic(), self.dst.name());
+        match self.src {
+            AluSource::Reg(src) => write!(f, "{op} {dst}, {}", src.name()),
+            AluSource::Imm(imm) => write!(f, "{op} {dst}, 0x{imm:08x}"),
+            AluSource::Mem(addr) => write!(f, "{op} {dst}, [0x{addr:08x}]"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, string::ToString};
+
+    use super::*;
+
+    mod mov {
+        use super::*;
+        use crate::{
+            cpu::{MemIterator, DEFAULT_MEM_SIZE, ZERO},
+            Machine,
+        };
+
+        #[test]
+        fn read_mem() {
+            let reg_to_reg = vec![0b0000_0000u8, 0, 0];
+            let imm_to_reg32 = vec![0b0011_0000u8, 0, 0, 0, 0, 0];
+            let imm_to_reg16 = vec![0b0010_0000u8, 0, 0, 0];
+            let imm_to_reg8 = vec![0b0001_0000u8, 0, 0];
+
+            let reg_to_mem32 = vec![0b0110_0000u8, 0, 0, 0, 0, 0];
+            let reg_to_mem16 = vec![0b0101_0000u8, 0, 0, 0, 0, 0];
+            let reg_to_mem8 = vec![0b0100_0000u8, 0, 0, 0, 0, 0];
+
+            let mem_to_reg32 = vec![0b1010_0000u8, 0, 0, 0, 0, 0];
+            let mem_to_reg16 = vec![0b1001_0000u8, 0, 0, 0, 0, 0];
+            let mem_to_reg8 = vec![0b1000_0000u8, 0, 0, 0, 0, 0];
+
+            let mem_to_mem32 = vec![0b1110_0000u8, 0, 0, 0, 0, 0, 0, 0, 0];
+            let mem_to_mem16 = vec![0b1101_0000u8, 0, 0, 0, 0, 0, 0, 0, 0];
+            let mem_to_mem8 = vec![0b1100_0000u8, 0, 0, 0, 0, 0, 0, 0, 0];
+
+            let reg_to_reg_instr =
+                Move::read(MemIterator::new(0, reg_to_reg.as_slice())).expect("should read");
+            let imm_to_reg32_instr =
+                Move::read(MemIterator::new(0, imm_to_reg32.as_slice())).expect("should read");
+            let imm_to_reg16_instr =
+                Move::read(MemIterator::new(0, imm_to_reg16.as_slice())).expect("should read");
+            let imm_to_reg8_instr =
+                Move::read(MemIterator::new(0, imm_to_reg8.as_slice())).expect("should read");
+            let reg_to_mem32_instr =
+                Move::read(MemIterator::new(0, reg_to_mem32.as_slice())).expect("should read");
+            let reg_to_mem16_instr =
+                Move::read(MemIterator::new(0, reg_to_mem16.as_slice())).expect("should read");
+            let reg_to_mem8_instr =
+                Move::read(MemIterator::new(0, reg_to_mem8.as_slice())).expect("should read");
+            let mem_to_reg32_instr =
+                Move::read(MemIterator::new(0, mem_to_reg32.as_slice())).expect("should read");
+            let mem_to_reg16_instr =
+                Move::read(MemIterator::new(0, mem_to_reg16.as_slice())).expect("should read");
+            let mem_to_reg8_instr =
+                Move::read(MemIterator::new(0, mem_to_reg8.as_slice())).expect("should read");
+            let mem_to_mem32_instr =
+                Move::read(MemIterator::new(0, mem_to_mem32.as_slice())).expect("should read");
+            let mem_to_mem16_instr =
+                Move::read(MemIterator::new(0, mem_to_mem16.as_slice())).expect("should read");
+            let mem_to_mem8_instr =
+                Move::read(MemIterator::new(0, mem_to_mem8.as_slice())).expect("should read");
+
+            assert_eq!(
+                reg_to_reg_instr.instr,
+                Instruction::Move(Move::RegToReg(Register::A, Register::A))
+            );
+            assert_eq!(
+                imm_to_reg32_instr.instr,
+                Instruction::Move(Move::ImmToReg32(0, Register::A))
+            );
+            assert_eq!(
+                imm_to_reg16_instr.instr,
+                Instruction::Move(Move::ImmToReg16(0, Register::A))
+            );
+            assert_eq!(
+                imm_to_reg8_instr.instr,
+                Instruction::Move(Move::ImmToReg8(0, Register::A.low()))
+            );
+            assert_eq!(
+                reg_to_mem32_instr.instr,
+                Instruction::Move(Move::RegToMem32(Register::A, 0))
+            );
+            assert_eq!(
+                reg_to_mem16_instr.instr,
+                Instruction::Move(Move::RegToMem16(Register::A, 0))
+            );
+            assert_eq!(
+                reg_to_mem8_instr.instr,
+                Instruction::Move(Move::RegToMem8(Register::A.low(), 0))
+            );
+            assert_eq!(
+                mem_to_reg32_instr.instr,
+                Instruction::Move(Move::MemToReg32(0, Register::A))
+            );
+            assert_eq!(
+                mem_to_reg16_instr.instr,
+                Instruction::Move(Move::MemToReg16(0, Register::A))
+            );
+            assert_eq!(
+                mem_to_reg8_instr.instr,
+                Instruction::Move(Move::MemToReg8(0, Register::A.low()))
+            );
+            assert_eq!(
+                mem_to_mem32_instr.instr,
+                Instruction::Move(Move::MemToMem32(0, 0))
+            );
+            assert_eq!(
+                mem_to_mem16_instr.instr,
+                Instruction::Move(Move::MemToMem16(0, 0))
+            );
+            assert_eq!(
+                mem_to_mem8_instr.instr,
+                Instruction::Move(Move::MemToMem8(0, 0))
+            );
+        }
+
+        #[test]
+        fn read_mem_truncated() {
+            let reg_to_mem32 = vec![0b0110_0000u8, 0, 0, 0];
+            let mem_to_mem32 = vec![0b1110_0000u8];
+            let instr_mem_to_mem32 = vec![0x1u8, 0b1110_0000, 0, 0, 0, 0];
+
+            assert!(Move::read(MemIterator::new(0, reg_to_mem32.as_slice())).is_err());
+            assert!(Move::read(MemIterator::new(0, mem_to_mem32.as_slice())).is_err());
+            assert!(Instruction::read(MemIterator::new(0, instr_mem_to_mem32.as_slice())).is_err());
+        }
+
+        #[test]
+        fn move_indexed() {
+            let mut machine = Machine::new();
+            let element = Indexed {
+                base: Register::X,
+                index: Register::Y,
+                scale: 4,
+            };
+            // 4 byte elements starting at 0x100
+            for (i, value) in [0x10u32, 0x20, 0x30, 0x40].into_iter().enumerate() {
+                machine.cpu.mem[0x100 + i * 4] = value as u8;
+            }
+            machine.cpu.registers.x = 0x100;
+            machine.cpu.registers.y = 3;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Move(Move::IndexedToReg(element, Register::A)))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x40);
+
+            machine.cpu.registers.y = 1;
+            machine
+                .cpu
+                .do_instruction(Instruction::Move(Move::RegToIndexed(Register::A, element)))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.mem[0x104], 0x40);
+        }
+
+        #[test]
+        fn move_indexed_wraps_around() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::IndexedToReg(
+                Indexed {
+                    base: Register::X,
+                    index: Register::Y,
+                    scale: 2,
+                },
+                Register::A,
+            ));
+            machine.cpu.mem[0x10] = 0x42;
+            machine.cpu.registers.x = DEFAULT_MEM_SIZE as u32 - 0x10;
+            machine.cpu.registers.y = 0x10;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x42);
+        }
+
+        #[test]
+        fn reserved_size() {
+            let strict = DecodeOptions { strict: true };
+            // a 32 bit reg-to-mem, mem-to-reg and mem-to-mem with the reserved
+            // size bits
+            let reserved = [
+                (0b0111_0000u8, Move::RegToMem32(Register::B, 0x0100_0000)),
+                (0b1011_0000u8, Move::MemToReg32(0x1, Register::B)),
+                (0b1111_0000u8, Move::MemToMem32(0x1, 0x1)),
+            ];
+
+            for (opcode, move_instr) in reserved {
+                let bytes = [opcode, 0x1, 0, 0, 0, 0x1, 0, 0, 0];
+
+                assert_eq!(
+                    Move::read(MemIterator::new(0, bytes.as_slice()))
+                        .expect("should read")
+                        .instr,
+                    Instruction::Move(move_instr)
+                );
+                assert_eq!(
+                    Move::read_with(MemIterator::new(0, bytes.as_slice()), strict).err(),
+                    Some(DecodeError::ReservedEncoding(opcode))
+                );
+            }
+
+            // the defined encodings still decode
+            let imm_to_reg32 = [0x1, 0b0011_0000, 0, 0, 0, 0, 0];
+            assert!(
+                Instruction::read_with(MemIterator::new(0, imm_to_reg32.as_slice()), strict)
+                    .is_ok()
+            );
+            for move_instr in Move::variants_sample() {
+                let bytes = Instruction::Move(move_instr)
+                    .encode()
+                    .expect("should encode");
+
+                assert_eq!(
+                    Instruction::read_with(MemIterator::new(0, bytes.as_slice()), strict)
+                        .expect("should read")
+                        .instr,
+                    Instruction::Move(move_instr)
+                );
+            }
+        }
+
+        #[test]
+        fn strict_cpu_faults_on_reserved() {
+            let mut machine = Machine::new();
+            machine
+                .load_program(0, &[0x1, 0b1011_0000, 0, 0, 0, 0, 0])
+                .expect("should load");
+            machine
+                .cpu
+                .set_decode_options(DecodeOptions { strict: true });
+
+            assert!(machine.run_cycle().is_err());
+            assert_eq!(
+                machine.cpu.last_fault(),
+                Some(DecodeError::ReservedEncoding(0b1011_0000))
+            );
+        }
+
+        #[test]
+        fn read_indexed() {
+            let indexed_to_reg = [0b1010_0010u8, 0, 1, 2, 3];
+            let bad_scale = [0b1010_0010u8, 0, 1, 3, 3];
+
+            assert_eq!(
+                Move::read(MemIterator::new(0, indexed_to_reg.as_slice()))
+                    .expect("should read")
+                    .instr,
+                Instruction::Move(Move::IndexedToReg(
+                    Indexed {
+                        base: Register::A,
+                        index: Register::B,
+                        scale: 4,
+                    },
+                    Register::Y,
+                ))
+            );
+            assert_eq!(
+                Move::read(MemIterator::new(0, bad_scale.as_slice())).err(),
+                Some(DecodeError::InvalidScale(3))
+            );
+
+            let invalid = Move::RegToIndexed(
+                Register::A,
+                Indexed {
+                    base: Register::X,
+                    index: Register::Y,
+                    scale: 3,
+                },
+            );
+            assert!(invalid.encode().is_err());
+        }
+
+        #[test]
+        fn move_reg_to_reg() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::RegToReg(Register::A, Register::B));
+            machine.cpu.registers.a = 42;
+            machine.cpu.registers.b = 2;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, machine.cpu.registers.b);
+            assert_eq!(machine.cpu.registers.b, 42);
+        }
+
+        #[test]
+        fn move_reg_to_ip() {
+            let mut machine = Machine::new();
+            // MOV IP, A
+            machine
+                .load_program(0, &[0x1, 0b0000_0000, 0x0, 0x5])
+                .expect("should load");
+            machine.cpu.registers.a = 0x100;
+
+            let parsed =
+                Instruction::read(MemIterator::new(0, &machine.cpu.mem)).expect("should read");
+            assert_eq!(
+                parsed.instr,
+                Instruction::Move(Move::RegToReg(Register::A, Register::Ip))
+            );
+            assert_eq!(parsed.instr.to_string(), "MOV IP, A");
+
+            machine.run_cycle().expect("should cycle");
+
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x100);
+        }
+
+        #[test]
+        fn move_imm_to_reg32() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::ImmToReg32(0, Register::A));
+            machine.cpu.registers.a = 0xFFFF_FFFF;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0);
+        }
+
+        #[test]
+        fn move_imm_to_reg16() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::ImmToReg16(0, Register::A));
+            machine.cpu.registers.a = 0xFFFF_FFFF;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_0000);
+        }
+
+        #[test]
+        fn move_imm_to_reg8() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::ImmToReg8(0, Register::A.low()));
+            machine.cpu.registers.a = 0xFFFF_FFFF;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_FF00);
+        }
+
+        #[test]
+        fn move_imm_to_reg_cycle() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0x1234_5678;
+            machine.cpu.registers.b = 0x1234_5678;
+            machine.cpu.registers.x = 0x1234_5678;
+
+            // MOV 0xDEADBEEF, A
+            machine.cpu.mem[0] = 0x1;
+            machine.cpu.mem[1] = 0b0011_0000;
+            machine.cpu.mem[2] = 0xEF;
+            machine.cpu.mem[3] = 0xBE;
+            machine.cpu.mem[4] = 0xAD;
+            machine.cpu.mem[5] = 0xDE;
+            machine.cpu.mem[6] = 0x0;
+
+            // MOV 0xBEEF, B
+            machine.cpu.mem[7] = 0x1;
+            machine.cpu.mem[8] = 0b0010_0000;
+            machine.cpu.mem[9] = 0xEF;
+            machine.cpu.mem[10] = 0xBE;
+            machine.cpu.mem[11] = 0x1;
+
+            // MOV 0xEF, X
+            machine.cpu.mem[12] = 0x1;
+            machine.cpu.mem[13] = 0b0001_0000;
+            machine.cpu.mem[14] = 0xEF;
+            machine.cpu.mem[15] = 0x2;
+
+            machine.cpu.cycle().expect("should cycle");
+            assert_eq!(machine.cpu.registers.a, 0xDEAD_BEEF);
+            assert_eq!(machine.cpu.registers.instruction_pointer, 7);
+
+            machine.cpu.cycle().expect("should cycle");
+            assert_eq!(machine.cpu.registers.b, 0x1234_BEEF);
+            assert_eq!(machine.cpu.registers.instruction_pointer, 12);
+
+            machine.cpu.cycle().expect("should cycle");
+            assert_eq!(machine.cpu.registers.x, 0x1234_56EF);
+            assert_eq!(machine.cpu.registers.instruction_pointer, 16);
+        }
+
+        #[test]
+        fn move_reg_to_mem32() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::RegToMem32(Register::A, 0x0));
+            machine.cpu.registers.a = 0x0403_0201;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.mem[0], 0x01);
+            assert_eq!(machine.cpu.mem[1], 0x02);
+            assert_eq!(machine.cpu.mem[2], 0x03);
+            assert_eq!(machine.cpu.mem[3], 0x04);
+        }
+
+        #[test]
+        fn move_reg_to_mem16() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::RegToMem16(Register::A, 0x0));
+            machine.cpu.registers.a = 0x0403_0201;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.mem[0], 0x01);
+            assert_eq!(machine.cpu.mem[1], 0x02);
+            assert_eq!(machine.cpu.mem[2], 0x00);
+            assert_eq!(machine.cpu.mem[3], 0x00);
+        }
+
+        #[test]
+        fn move_reg_to_mem8() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::RegToMem8(Register::A.low(), 0x0));
+            machine.cpu.registers.a = 0x0403_0201;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.mem[0], 0x01);
+            assert_eq!(machine.cpu.mem[1], 0x00);
+            assert_eq!(machine.cpu.mem[2], 0x00);
+            assert_eq!(machine.cpu.mem[3], 0x00);
+        }
+
+        #[test]
+        fn move_reg_to_mem32_out_of_bounds() {
+            let mut machine = Machine::new();
+            let addr = (DEFAULT_MEM_SIZE - 1) as u32;
+            let instr = Instruction::Move(Move::RegToMem32(Register::A, addr));
+            machine.cpu.registers.a = 0x0403_0201;
+
+            assert!(machine.cpu.do_instruction(instr).is_err());
+            assert_eq!(machine.cpu.mem[DEFAULT_MEM_SIZE - 1], 0x00);
+            assert_eq!(machine.cpu.mem[0], 0x00);
+        }
+
+        #[test]
+        fn move_mem_to_reg32_straddling_end_of_mem() {
+            let mut machine = Machine::new();
+            let addr = (DEFAULT_MEM_SIZE - 2) as u32;
+            let instr = Instruction::Move(Move::MemToReg32(addr, Register::A));
+            machine.cpu.mem[DEFAULT_MEM_SIZE - 2] = 0x01;
+            machine.cpu.mem[0] = 0x03;
+
+            assert_eq!(
+                machine.cpu.do_instruction(instr),
+                Err(format!(
+                    "Tried to read 4 byte(s) out of bounds at 0x{addr:08x}"
+                ))
+            );
+            assert_eq!(machine.cpu.registers.a, 0);
+        }
+
+        #[test]
+        fn move_mem_to_reg16_at_end_of_mem() {
+            let mut machine = Machine::new();
+            let addr = (DEFAULT_MEM_SIZE - 2) as u32;
+            machine.cpu.mem[DEFAULT_MEM_SIZE - 2] = 0x01;
+            machine.cpu.mem[DEFAULT_MEM_SIZE - 1] = 0x02;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Move(Move::MemToReg16(addr, Register::A)))
+                .expect("should execute");
+            assert_eq!(machine.cpu.registers.a, 0x0201);
+
+            assert!(machine
+                .cpu
+                .do_instruction(Instruction::Move(Move::MemToReg16(addr + 1, Register::A)))
+                .is_err());
+        }
+
+        #[test]
+        fn move_reg_to_mem8_past_end_of_mem() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::RegToMem8(Register::A.low(), 0xFFFF_FFFF));
+
+            assert!(machine.cpu.do_instruction(instr).is_err());
+        }
+
+        #[test]
+        fn move_mem_to_reg32() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::MemToReg32(0, Register::A));
+            machine.cpu.registers.a = 0xFFFF_FFFF;
+
+            machine.cpu.mem[0] = 0x01;
+            machine.cpu.mem[1] = 0x02;
+            machine.cpu.mem[2] = 0x03;
+            machine.cpu.mem[3] = 0x04;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x0403_0201);
+        }
+
+        #[test]
+        fn move_mem_to_reg16() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::MemToReg16(0, Register::A));
+            machine.cpu.registers.a = 0xFFFF_FFFF;
+
+            machine.cpu.mem[0] = 0x01;
+            machine.cpu.mem[1] = 0x02;
+            machine.cpu.mem[2] = 0x03;
+            machine.cpu.mem[3] = 0x04;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_0201);
+        }
+
+        #[test]
+        fn move_mem_to_reg8() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::MemToReg8(0, Register::A.low()));
+            machine.cpu.registers.a = 0xFFFF_FFFF;
+
+            machine.cpu.mem[0] = 0x01;
+            machine.cpu.mem[1] = 0x02;
+            machine.cpu.mem[2] = 0x03;
+            machine.cpu.mem[3] = 0x04;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_FF01);
+        }
+
+        #[test]
+        fn move_zero_to_reg_sets_zero_flag() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::MemToReg8(0, Register::A.low()));
+            machine.cpu.registers.a = 0xFFFF_FFFF;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_FF00);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn move_nonzero_to_reg_clears_zero_flag() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::RegToReg(Register::A, Register::B));
+            machine.cpu.registers.a = 42;
+            machine.cpu.registers.flags |= ZERO;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.b, 42);
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn move_to_mem_keeps_zero_flag() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::RegToMem32(Register::A, 0x0));
+            machine.cpu.registers.a = 42;
+            machine.cpu.registers.flags |= ZERO;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn move_reg_indirect() {
+            let mut machine = Machine::new();
+            let store = Instruction::Move(Move::RegToRegIndirect(Register::B, Register::X));
+            let load = Instruction::Move(Move::RegIndirectToReg(Register::X, Register::A));
+            machine.cpu.registers.x = 0x40;
+            machine.cpu.registers.b = 0x0403_0201;
+
+            machine.cpu.do_instruction(store).expect("should execute");
+
+            assert_eq!(machine.cpu.mem[0x40], 0x01);
+            assert_eq!(machine.cpu.mem[0x41], 0x02);
+            assert_eq!(machine.cpu.mem[0x42], 0x03);
+            assert_eq!(machine.cpu.mem[0x43], 0x04);
+
+            machine.cpu.do_instruction(load).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x0403_0201);
+        }
+
+        #[test]
+        fn read_mem_reg_indirect() {
+            let reg_to_reg_indirect = vec![0b0110_1000u8, 1, 2];
+            let reg_indirect_to_reg = vec![0b1010_1000u8, 2, 0];
+
+            let reg_to_reg_indirect_instr =
+                Move::read(MemIterator::new(0, reg_to_reg_indirect.as_slice()))
+                    .expect("should read");
+            let reg_indirect_to_reg_instr =
+                Move::read(MemIterator::new(0, reg_indirect_to_reg.as_slice()))
+                    .expect("should read");
+
+            assert_eq!(
+                reg_to_reg_indirect_instr.instr,
+                Instruction::Move(Move::RegToRegIndirect(Register::B, Register::X))
+            );
+            assert_eq!(reg_to_reg_indirect_instr.delta_ip, 3);
+            assert_eq!(
+                reg_indirect_to_reg_instr.instr,
+                Instruction::Move(Move::RegIndirectToReg(Register::X, Register::A))
+            );
+            assert_eq!(reg_indirect_to_reg_instr.delta_ip, 3);
+        }
+
+        #[test]
+        fn read_mem_imm_to_mem() {
+            let imm_to_mem32 = vec![0b0110_0100u8, 0x01, 0x02, 0x03, 0x04, 0x10, 0, 0, 0];
+            let imm_to_mem16 = vec![0b0101_0100u8, 0x01, 0x02, 0x10, 0, 0, 0];
+            let imm_to_mem8 = vec![0b0100_0100u8, 0x01, 0x10, 0, 0, 0];
+
+            let imm_to_mem32_instr =
+                Move::read(MemIterator::new(0, imm_to_mem32.as_slice())).expect("should read");
+            let imm_to_mem16_instr =
+                Move::read(MemIterator::new(0, imm_to_mem16.as_slice())).expect("should read");
+            let imm_to_mem8_instr =
+                Move::read(MemIterator::new(0, imm_to_mem8.as_slice())).expect("should read");
+
+            assert_eq!(
+                imm_to_mem32_instr.instr,
+                Instruction::Move(Move::ImmToMem32(0x0403_0201, 0x10))
+            );
+            assert_eq!(imm_to_mem32_instr.delta_ip, 9);
+            assert_eq!(
+                imm_to_mem16_instr.instr,
+                Instruction::Move(Move::ImmToMem16(0x0201, 0x10))
+            );
+            assert_eq!(imm_to_mem16_instr.delta_ip, 7);
+            assert_eq!(
+                imm_to_mem8_instr.instr,
+                Instruction::Move(Move::ImmToMem8(0x01, 0x10))
+            );
+            assert_eq!(imm_to_mem8_instr.delta_ip, 6);
+        }
+
+        #[test]
+        fn move_imm_to_mem32() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::ImmToMem32(0x0403_0201, 0x0));
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.mem[0], 0x01);
+            assert_eq!(machine.cpu.mem[1], 0x02);
+            assert_eq!(machine.cpu.mem[2], 0x03);
+            assert_eq!(machine.cpu.mem[3], 0x04);
+        }
+
+        #[test]
+        fn move_imm_to_mem8() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::ImmToMem8(0x42, 0x1));
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.mem[0], 0x00);
+            assert_eq!(machine.cpu.mem[1], 0x42);
+            assert_eq!(machine.cpu.mem[2], 0x00);
+        }
+
+        #[test]
+        fn move_mem_to_mem32() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::MemToMem32(0x0, 0x4));
+
+            machine.cpu.mem[0] = 0x01;
+            machine.cpu.mem[1] = 0x02;
+            machine.cpu.mem[2] = 0x03;
+            machine.cpu.mem[3] = 0x04;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.mem[4], 0x01);
+            assert_eq!(machine.cpu.mem[5], 0x02);
+            assert_eq!(machine.cpu.mem[6], 0x03);
+            assert_eq!(machine.cpu.mem[7], 0x04);
+        }
+
+        #[test]
+        fn move_mem_to_mem16() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::MemToMem16(0x0, 0x4));
+
+            machine.cpu.mem[0] = 0x01;
+            machine.cpu.mem[1] = 0x02;
+            machine.cpu.mem[2] = 0x03;
+            machine.cpu.mem[3] = 0x04;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.mem[4], 0x01);
+            assert_eq!(machine.cpu.mem[5], 0x02);
+            assert_eq!(machine.cpu.mem[6], 0x00);
+            assert_eq!(machine.cpu.mem[7], 0x00);
+        }
+
+        #[test]
+        fn move_mem_to_mem8() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Move(Move::MemToMem8(0x0, 0x4));
+
+            machine.cpu.mem[0] = 0x01;
+            machine.cpu.mem[1] = 0x02;
+            machine.cpu.mem[2] = 0x03;
+            machine.cpu.mem[3] = 0x04;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.mem[4], 0x01);
+            assert_eq!(machine.cpu.mem[5], 0x00);
+            assert_eq!(machine.cpu.mem[6], 0x00);
+            assert_eq!(machine.cpu.mem[7], 0x00);
+        }
+    }
+
+    mod add {
+        use super::*;
+        use crate::{
+            cpu::{ArithMode, MemIterator, CARRY, OVERFLOW, ZERO},
+            Machine,
+        };
+
+        fn add_imm(a: u32, imm: u32) -> Machine {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = a;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Add(Add::ImmToReg(imm, Register::A)))
+                .expect("should execute");
+
+            machine
+        }
+
+        #[test]
+        fn arith_mode() {
+            let machine = add_imm(0xFFFF_FFFF, 1);
+            assert_eq!(machine.cpu.arith_mode(), ArithMode::Wrapping);
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert!(machine.cpu.registers.carry());
+            assert!(machine.cpu.registers.zero());
+
+            let mut machine = Machine::new();
+            machine.cpu.set_arith_mode(ArithMode::Saturating);
+            machine.cpu.registers.a = 0xFFFF_FFFF;
+            machine
+                .cpu
+                .do_instruction(Instruction::Add(Add::ImmToReg(1, Register::A)))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_FFFF);
+            assert!(machine.cpu.registers.carry());
+            assert!(!machine.cpu.registers.zero());
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Alu(Alu {
+                    op: AluOp::Add,
+                    src: AluSource::Imm(0x10),
+                    dst: Register::A,
+                }))
+                .expect("should execute");
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_FFFF);
+            assert!(machine.cpu.registers.carry());
+        }
+
+        #[test]
+        fn signed_overflow() {
+            let machine = add_imm(0x7FFF_FFFF, 1);
+            assert_eq!(machine.cpu.registers.a, 0x8000_0000);
+            assert!(machine.cpu.registers.flags.contains(OVERFLOW));
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+
+            let machine = add_imm(1, 1);
+            assert!(!machine.cpu.registers.flags.contains(OVERFLOW));
+
+            // -1 + 1 carries but doesn't overflow
+            let machine = add_imm(0xFFFF_FFFF, 1);
+            assert!(!machine.cpu.registers.flags.contains(OVERFLOW));
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+
+            let machine = add_imm(0x8000_0000, 0x8000_0000);
+            assert!(machine.cpu.registers.flags.contains(OVERFLOW));
+        }
+
+        #[test]
+        fn read_mem() {
+            let reg_to_reg = vec![0b0000_0000u8, 0, 1];
+            let imm_to_reg = vec![0b0100_0000u8, 0x04, 0x03, 0x02, 0x01, 0];
+            let mem_to_reg = vec![0b1000_0000u8, 0x10, 0, 0, 0, 1];
+            let invalid = vec![0b1100_0000u8, 0, 0];
+
+            let reg_to_reg_instr =
+                Add::read(MemIterator::new(0, reg_to_reg.as_slice())).expect("should read");
+            let imm_to_reg_instr =
+                Add::read(MemIterator::new(0, imm_to_reg.as_slice())).expect("should read");
+            let mem_to_reg_instr =
+                Add::read(MemIterator::new(0, mem_to_reg.as_slice())).expect("should read");
+
+            assert_eq!(
+                reg_to_reg_instr.instr,
+                Instruction::Add(Add::RegToReg(Register::A, Register::B))
+            );
+            assert_eq!(reg_to_reg_instr.delta_ip, 3);
+            assert_eq!(
+                imm_to_reg_instr.instr,
+                Instruction::Add(Add::ImmToReg(0x0102_0304, Register::A))
+            );
+            assert_eq!(imm_to_reg_instr.delta_ip, 6);
+            assert_eq!(
+                mem_to_reg_instr.instr,
+                Instruction::Add(Add::MemToReg(0x10, Register::B))
+            );
+            assert_eq!(mem_to_reg_instr.delta_ip, 6);
+
+            assert!(Add::read(MemIterator::new(0, invalid.as_slice())).is_err());
+        }
+
+        #[test]
+        fn add_reg_to_reg() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Add(Add::RegToReg(Register::A, Register::B));
+            machine.cpu.registers.a = 40;
+            machine.cpu.registers.b = 2;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 40);
+            assert_eq!(machine.cpu.registers.b, 42);
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn add_imm_to_reg() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Add(Add::ImmToReg(0xFFFF_FFFF, Register::A));
+            machine.cpu.registers.a = 2;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 1);
+        }
+
+        #[test]
+        fn add_mem_to_reg() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Add(Add::MemToReg(0, Register::A));
+            machine.cpu.registers.a = 0x0000_0001;
+
+            machine.cpu.mem[0] = 0x01;
+            machine.cpu.mem[1] = 0x02;
+            machine.cpu.mem[2] = 0x03;
+            machine.cpu.mem[3] = 0x04;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x0403_0202);
+        }
+
+        #[test]
+        fn add_sets_zero_flag() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Add(Add::ImmToReg(1, Register::A));
+            machine.cpu.registers.a = 0xFFFF_FFFF;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 1);
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn add_overflow_sets_carry_flag() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Add(Add::ImmToReg(2, Register::A));
+            machine.cpu.registers.a = 0xFFFF_FFFF;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 1);
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+        }
+
+        #[test]
+        fn add_no_overflow_clears_carry_flag() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Add(Add::ImmToReg(2, Register::A));
+            machine.cpu.registers.a = 40;
+            machine.cpu.registers.flags |= CARRY;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 42);
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+        }
+    }
+
+    mod sub {
+        use super::*;
+        use crate::{
+            cpu::{ArithMode, MemIterator, CARRY, ZERO},
+            Machine,
+        };
+
+        #[test]
+        fn arith_mode() {
+            let sub = |mode: ArithMode| {
+                let mut machine = Machine::new();
+                machine.cpu.set_arith_mode(mode);
+                machine.cpu.registers.a = 1;
+                machine
+                    .cpu
+                    .do_instruction(Instruction::Sub(Sub::ImmToReg(2, Register::A)))
+                    .expect("should execute");
+
+                machine.cpu.registers
+            };
+
+            let registers = sub(ArithMode::Wrapping);
+            assert_eq!(registers.a, 0xFFFF_FFFF);
+            assert!(registers.carry());
+
+            let registers = sub(ArithMode::Saturating);
+            assert_eq!(registers.a, 0);
+            assert!(registers.carry());
+            assert!(registers.zero());
+        }
+
+        #[test]
+        fn signed_overflow() {
+            let sub = |a: u32, imm: u32| {
+                let mut machine = Machine::new();
+                machine.cpu.registers.a = a;
+                machine
+                    .cpu
+                    .do_instruction(Instruction::Sub(Sub::ImmToReg(imm, Register::A)))
+                    .expect("should execute");
+
+                machine.cpu.registers.overflow()
+            };
+
+            // i32::MIN - 1 and i32::MAX - -1
+            assert!(sub(0x8000_0000, 1));
+            assert!(sub(0x7FFF_FFFF, 0xFFFF_FFFF));
+            assert!(!sub(1, 2));
+            assert!(!sub(0x8000_0000, 0x8000_0000));
+        }
+
+        #[test]
+        fn read_mem() {
+            let reg_to_reg = vec![0b0000_0000u8, 0, 1];
+            let imm_to_reg = vec![0b0100_0000u8, 0x04, 0x03, 0x02, 0x01, 0];
+            let mem_to_reg = vec![0b1000_0000u8, 0x10, 0, 0, 0, 1];
+
+            let reg_to_reg_instr =
+                Sub::read(MemIterator::new(0, reg_to_reg.as_slice())).expect("should read");
+            let imm_to_reg_instr =
+                Sub::read(MemIterator::new(0, imm_to_reg.as_slice())).expect("should read");
+            let mem_to_reg_instr =
+                Sub::read(MemIterator::new(0, mem_to_reg.as_slice())).expect("should read");
+
+            assert_eq!(
+                reg_to_reg_instr.instr,
+                Instruction::Sub(Sub::RegToReg(Register::A, Register::B))
+            );
+            assert_eq!(
+                imm_to_reg_instr.instr,
+                Instruction::Sub(Sub::ImmToReg(0x0102_0304, Register::A))
+            );
+            assert_eq!(
+                mem_to_reg_instr.instr,
+                Instruction::Sub(Sub::MemToReg(0x10, Register::B))
+            );
+        }
+
+        #[test]
+        fn sub_reg_to_reg() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Sub(Sub::RegToReg(Register::A, Register::B));
+            machine.cpu.registers.a = 2;
+            machine.cpu.registers.b = 44;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 2);
+            assert_eq!(machine.cpu.registers.b, 42);
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn sub_equal_values_sets_zero_flag() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Sub(Sub::ImmToReg(5, Register::A));
+            machine.cpu.registers.a = 5;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn sub_wraps_below_zero() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Sub(Sub::ImmToReg(1, Register::A));
+            machine.cpu.registers.a = 0;
+            machine.cpu.registers.flags |= ZERO;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_FFFF);
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+        }
+
+        #[test]
+        fn sub_no_borrow_clears_carry_flag() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Sub(Sub::ImmToReg(2, Register::A));
+            machine.cpu.registers.a = 44;
+            machine.cpu.registers.flags |= CARRY;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 42);
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+        }
+    }
+
+    mod jump {
+        use super::*;
+        use crate::{
+            cpu::{MemIterator, ZERO},
+            Machine,
+        };
+
+        #[test]
+        fn read_mem() {
+            let jump = vec![0x4u8, 0x00, 0x01, 0x00, 0x00];
+            let jump_if_zero = vec![0x5u8, 0x00, 0x01, 0x00, 0x00];
+            let jump_if_not_zero = vec![0x6u8, 0x00, 0x01, 0x00, 0x00];
+
+            let jump_instr =
+                Instruction::read(MemIterator::new(0, jump.as_slice())).expect("should read");
+            let jump_if_zero_instr =
+                Instruction::read(MemIterator::new(0, jump_if_zero.as_slice()))
+                    .expect("should read");
+            let jump_if_not_zero_instr =
+                Instruction::read(MemIterator::new(0, jump_if_not_zero.as_slice()))
+                    .expect("should read");
+
+            assert_eq!(jump_instr.instr, Instruction::Jump(0x100));
+            assert_eq!(jump_instr.delta_ip, 5);
+            assert_eq!(jump_if_zero_instr.instr, Instruction::JumpIfZero(0x100));
+            assert_eq!(jump_if_zero_instr.delta_ip, 5);
+            assert_eq!(
+                jump_if_not_zero_instr.instr,
+                Instruction::JumpIfNotZero(0x100)
+            );
+            assert_eq!(jump_if_not_zero_instr.delta_ip, 5);
+        }
+
+        #[test]
+        fn jump_sets_instruction_pointer() {
+            let mut machine = Machine::new();
+
+            machine.cpu.mem[0] = 0x4;
+            machine.cpu.mem[1] = 0x00;
+            machine.cpu.mem[2] = 0x01;
+            machine.cpu.mem[3] = 0x00;
+            machine.cpu.mem[4] = 0x00;
+
+            machine.run_cycle().expect("should cycle");
+
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x100);
+        }
+
+        #[test]
+        fn jump_rel_forward() {
+            let mut machine = Machine::new();
+            let program = Instruction::JumpRel(0x10).encode().expect("should encode");
+            machine.load_program(0x100, &program).expect("should load");
+            machine.cpu.registers.instruction_pointer = 0x100;
+
+            machine.run_cycle().expect("should cycle");
+
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x115);
+        }
+
+        #[test]
+        fn jump_rel_zero_falls_through() {
+            let mut machine = Machine::new();
+            let program = Instruction::JumpRel(0).encode().expect("should encode");
+            machine.load_program(0x100, &program).expect("should load");
+            machine.cpu.registers.instruction_pointer = 0x100;
+
+            machine.run_cycle().expect("should cycle");
+
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x105);
+        }
+
+        #[test]
+        fn jump_rel_backward_loop() {
+            let mut machine = Machine::new();
+
+            // count A up to 3 by jumping back over the INC until A - 3 is zero
+            let program = crate::asm::assemble(
+                r#"
+                INC A
+                CMP A, 3
+                JZ 0x13
+                JMPR -19
+            "#,
+            )
+            .expect("should assemble");
+            machine.load_program(0, &program).expect("should load");
+
+            for _ in 0..4 * 3 - 1 {
+                machine.run_cycle().expect("should cycle");
+            }
+
+            assert_eq!(machine.cpu.registers.a, 3);
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x13);
+        }
+
+        #[test]
+        fn jump_rel_wraps_around() {
+            let mut machine = Machine::new();
+
+            machine
+                .cpu
+                .do_instruction(Instruction::JumpRel(-1))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0xFFFF_FFFF);
+        }
+
+        #[test]
+        fn jump_if_zero_taken() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.flags |= ZERO;
+
+            machine.cpu.mem[0] = 0x5;
+            machine.cpu.mem[1] = 0x00;
+            machine.cpu.mem[2] = 0x01;
+
+            machine.run_cycle().expect("should cycle");
+
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x100);
+        }
+
+        #[test]
+        fn jump_if_zero_not_taken() {
+            let mut machine = Machine::new();
+
+            machine.cpu.mem[0] = 0x5;
+            machine.cpu.mem[1] = 0x00;
+            machine.cpu.mem[2] = 0x01;
+
+            machine.run_cycle().expect("should cycle");
+
+            assert_eq!(machine.cpu.registers.instruction_pointer, 5);
+        }
+
+        #[test]
+        fn jump_if_not_zero_taken() {
+            let mut machine = Machine::new();
+
+            machine.cpu.mem[0] = 0x6;
+            machine.cpu.mem[1] = 0x00;
+            machine.cpu.mem[2] = 0x01;
+
+            machine.run_cycle().expect("should cycle");
+
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x100);
+        }
+
+        #[test]
+        fn jump_if_not_zero_not_taken() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.flags |= ZERO;
+
+            machine.cpu.mem[0] = 0x6;
+            machine.cpu.mem[1] = 0x00;
+            machine.cpu.mem[2] = 0x01;
+
+            machine.run_cycle().expect("should cycle");
+
+            assert_eq!(machine.cpu.registers.instruction_pointer, 5);
+        }
+    }
+
+    mod stack {
+        use super::*;
+        use crate::{cpu::MemIterator, Machine};
+
+        #[test]
+        fn read_mem() {
+            let push = vec![0x7u8, 0x4];
+            let pop = vec![0x8u8, 0x1];
+
+            let push_instr =
+                Instruction::read(MemIterator::new(0, push.as_slice())).expect("should read");
+            let pop_instr =
+                Instruction::read(MemIterator::new(0, pop.as_slice())).expect("should read");
+
+            assert_eq!(push_instr.instr, Instruction::Push(Register::Sp));
+            assert_eq!(push_instr.delta_ip, 2);
+            assert_eq!(pop_instr.instr, Instruction::Pop(Register::B));
+            assert_eq!(pop_instr.delta_ip, 2);
+        }
+
+        #[test]
+        fn push_pop_round_trip() {
+            let mut machine = Machine::new();
+            let stack_top = machine.cpu.registers.stack_pointer;
+            machine.cpu.registers.a = 0xDEAD_BEEF;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Push(Register::A))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.stack_pointer, stack_top - 4);
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Pop(Register::B))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.stack_pointer, stack_top);
+            assert_eq!(machine.cpu.registers.a, machine.cpu.registers.b);
+        }
+
+        #[test]
+        fn nested_call_ret() {
+            let mut machine = Machine::new();
+            let stack_top = machine.cpu.registers.stack_pointer;
+
+            // 0x00: CALL 0x10
+            machine.cpu.mem[0x00] = 0x9;
+            machine.cpu.mem[0x01] = 0x10;
+
+            // 0x10: CALL 0x20
+            // 0x15: RET
+            machine.cpu.mem[0x10] = 0x9;
+            machine.cpu.mem[0x11] = 0x20;
+            machine.cpu.mem[0x15] = 0xA;
+
+            // 0x20: RET
+            machine.cpu.mem[0x20] = 0xA;
+
+            machine.run_cycle().expect("should cycle");
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x10);
+
+            machine.run_cycle().expect("should cycle");
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x20);
+            assert_eq!(machine.cpu.registers.stack_pointer, stack_top - 8);
+
+            machine.run_cycle().expect("should cycle");
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x15);
+
+            machine.run_cycle().expect("should cycle");
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x05);
+            assert_eq!(machine.cpu.registers.stack_pointer, stack_top);
+        }
+    }
+
+    mod display {
+        use super::*;
+
+        #[test]
+        fn instruction() {
+            assert_eq!(Instruction::Halt.to_string(), "HALT");
+            assert_eq!(Instruction::Ret.to_string(), "RET");
+            assert_eq!(Instruction::Jump(0x100).to_string(), "JMP 0x00000100");
+            assert_eq!(Instruction::Push(Register::Sp).to_string(), "PUSH SP");
+            assert_eq!(
+                Instruction::Add(Add::RegToReg(Register::A, Register::B)).to_string(),
+                "ADD B, A"
+            );
+            assert_eq!(
+                Instruction::Sub(Sub::ImmToReg(1, Register::X)).to_string(),
+                "SUB X, 0x00000001"
+            );
+        }
+
+        #[test]
+        fn mov() {
+            assert_eq!(
+                Instruction::Move(Move::RegToReg(Register::B, Register::A)).to_string(),
+                "MOV A, B"
+            );
+            assert_eq!(
+                Instruction::Move(Move::RegToMem32(Register::A, 0x4)).to_string(),
+                "MOV [0x00000004], A"
+            );
+            assert_eq!(
+                Instruction::Move(Move::MemToReg16(0x4, Register::Y)).to_string(),
+                "MOV Y, WORD [0x00000004]"
+            );
+            assert_eq!(
+                Instruction::Move(Move::ImmToReg8(0x42, Register::A.low())).to_string(),
+                "MOV A, BYTE 0x42"
+            );
+            assert_eq!(
+                Instruction::Move(Move::MemToMem32(0x0, 0x4)).to_string(),
+                "MOV [0x00000004], [0x00000000]"
+            );
+        }
+    }
+
+    mod encode {
+        use super::*;
+        use crate::cpu::MemIterator;
+
+        fn assert_round_trip(instr: Instruction) {
+            let bytes = instr.encode().expect("should encode");
+            let parsed =
+                Instruction::read(MemIterator::new(0, bytes.as_slice())).expect("should read");
+
+            assert_eq!(parsed.instr, instr);
+            assert_eq!(parsed.delta_ip as usize, bytes.len());
+        }
+
+        #[test]
+        fn round_trip_move() {
+            let moves = [
+                Move::RegToReg(Register::A, Register::Sp),
+                Move::ImmToReg32(0xDEAD_BEEF, Register::B),
+                Move::ImmToReg16(0xBEEF, Register::X),
+                Move::ImmToReg8(0xEF, Register::Y.low()),
+                Move::RegToMem32(Register::A, 0x0102_0304),
+                Move::RegToMem16(Register::B, 0x0102_0304),
+                Move::RegToMem8(Register::X.low(), 0x0102_0304),
+                Move::MemToReg32(0x0102_0304, Register::Y),
+                Move::MemToReg16(0x0102_0304, Register::A),
+                Move::MemToReg8(0x0102_0304, Register::B.low()),
+                Move::MemToMem32(0x0102_0304, 0x0506_0708),
+                Move::MemToMem16(0x0102_0304, 0x0506_0708),
+                Move::MemToMem8(0x0102_0304, 0x0506_0708),
+                Move::RegIndirectToReg(Register::X, Register::A),
+                Move::RegToRegIndirect(Register::A, Register::X),
+                Move::ImmToMem32(0xDEAD_BEEF, 0x0102_0304),
+                Move::ImmToMem16(0xBEEF, 0x0102_0304),
+                Move::ImmToMem8(0xEF, 0x0102_0304),
+                Move::IndexedToReg(
+                    Indexed {
+                        base: Register::A,
+                        index: Register::B,
+                        scale: 4,
+                    },
+                    Register::X,
+                ),
+                Move::RegToIndexed(
+                    Register::Y,
+                    Indexed {
+                        base: Register::Sp,
+                        index: Register::A,
+                        scale: 2,
+                    },
+                ),
+            ];
+
+            for move_instr in moves {
+                assert_round_trip(Instruction::Move(move_instr));
+            }
+        }
+
+        #[test]
+        fn round_trip_instruction() {
+            let instrs = [
+                Instruction::Halt,
+                Instruction::Add(Add::RegToReg(Register::A, Register::B)),
+                Instruction::Add(Add::ImmToReg(0xDEAD_BEEF, Register::X)),
+                Instruction::Add(Add::MemToReg(0x0102_0304, Register::Y)),
+                Instruction::Sub(Sub::RegToReg(Register::A, Register::B)),
+                Instruction::Sub(Sub::ImmToReg(0xDEAD_BEEF, Register::X)),
+                Instruction::Sub(Sub::MemToReg(0x0102_0304, Register::Y)),
+                Instruction::Jump(0x0102_0304),
+                Instruction::JumpIfZero(0x0102_0304),
+                Instruction::JumpIfNotZero(0x0102_0304),
+                Instruction::JumpRel(-0x0102_0304),
+                Instruction::Push(Register::A),
+                Instruction::Pop(Register::Sp),
+                Instruction::Call(0x0102_0304),
+                Instruction::Ret,
+                Instruction::Neg(Register::X),
+                Instruction::Logic(Logic::And(Register::A, Register::B)),
+                Instruction::Logic(Logic::Or(Register::X, Register::Y)),
+                Instruction::Logic(Logic::Xor(Register::Sp, Register::A)),
+                Instruction::Logic(Logic::Not(Register::B)),
+                Instruction::Shift(ShiftOp::Shl(Register::A, 1)),
+                Instruction::Shift(ShiftOp::Shr(Register::B, 31)),
+                Instruction::Shift(ShiftOp::Sar(Register::X, 0xFF)),
+                Instruction::Shift(ShiftOp::Rol(Register::Y, 8)),
+                Instruction::Shift(ShiftOp::Ror(Register::Sp, 16)),
+                Instruction::Cmp(Register::A, Register::B),
+                Instruction::CmpImm(Register::X, 0xDEAD_BEEF),
+                Instruction::Inc(Register::Y),
+                Instruction::Dec(Register::Sp),
+                Instruction::Nop,
+                Instruction::Swap(Register::A, Register::Sp),
+                Instruction::Mul(Register::B, Register::A),
+                Instruction::Div(Register::X, Register::B),
+                Instruction::Iret,
+                Instruction::Cli,
+                Instruction::Sti,
+                Instruction::Alu(Alu {
+                    op: AluOp::Xor,
+                    src: AluSource::Reg(Register::Y),
+                    dst: Register::A,
+                }),
+                Instruction::Alu(Alu {
+                    op: AluOp::Cmp,
+                    src: AluSource::Imm(0xDEAD_BEEF),
+                    dst: Register::X,
+                }),
+                Instruction::Alu(Alu {
+                    op: AluOp::And,
+                    src: AluSource::Mem(0x0102_0304),
+                    dst: Register::Sp,
+                }),
+                Instruction::Lea(
+                    Register::A,
+                    AddrExpr {
+                        base: Register::X,
+                        displacement: -0x0102_0304,
+                    },
+                ),
+            ];
+
+            for instr in instrs {
+                assert_round_trip(instr);
+            }
+        }
+
+        // exhaustive so that a new variant without a sample fails to build
+        fn move_variant(move_instr: Move) -> usize {
+            match move_instr {
+                Move::RegToReg(..) => 0,
+                Move::ImmToReg32(..) => 1,
+                Move::ImmToReg16(..) => 2,
+                Move::ImmToReg8(..) => 3,
+                Move::RegToMem32(..) => 4,
+                Move::RegToMem16(..) => 5,
+                Move::RegToMem8(..) => 6,
+                Move::MemToReg32(..) => 7,
+                Move::MemToReg16(..) => 8,
+                Move::MemToReg8(..) => 9,
+                Move::MemToMem32(..) => 10,
+                Move::MemToMem16(..) => 11,
+                Move::MemToMem8(..) => 12,
+                Move::ImmToMem32(..) => 13,
+                Move::ImmToMem16(..) => 14,
+                Move::ImmToMem8(..) => 15,
+                Move::RegIndirectToReg(..) => 16,
+                Move::RegToRegIndirect(..) => 17,
+                Move::IndexedToReg(..) => 18,
+                Move::RegToIndexed(..) => 19,
+            }
+        }
+
+        fn instruction_variant(instr: Instruction) -> usize {
+            match instr {
+                Instruction::Move(_) => 0,
+                Instruction::Add(_) => 1,
+                Instruction::Sub(_) => 2,
+                Instruction::Logic(_) => 3,
+                Instruction::Shift(_) => 4,
+                Instruction::Alu(_) => 5,
+                Instruction::Lea(..) => 6,
+                Instruction::Cmp(..) => 7,
+                Instruction::CmpImm(..) => 8,
+                Instruction::Jump(_) => 9,
+                Instruction::JumpIfZero(_) => 10,
+                Instruction::JumpIfNotZero(_) => 11,
+                Instruction::JumpRel(_) => 12,
+                Instruction::Push(_) => 13,
+                Instruction::Pop(_) => 14,
+                Instruction::Call(_) => 15,
+                Instruction::Ret => 16,
+                Instruction::Neg(_) => 17,
+                Instruction::Inc(_) => 18,
+                Instruction::Dec(_) => 19,
+                Instruction::Nop => 20,
+                Instruction::Swap(..) => 21,
+                Instruction::Mul(..) => 22,
+                Instruction::Div(..) => 23,
+                Instruction::Iret => 24,
+                Instruction::Cli => 25,
+                Instruction::Sti => 26,
+                Instruction::Halt => 27,
+                Instruction::BranchIfEqual(..) => 28,
+                Instruction::BranchIfNotEqual(..) => 29,
+                Instruction::JumpIfLess(_) => 30,
+                Instruction::JumpIfGreaterOrEqual(_) => 31,
+                Instruction::JumpIfGreater(_) => 32,
+                Instruction::JumpIfLessOrEqual(_) => 33,
+                Instruction::Xchg(..) => 34,
+                Instruction::PushAll => 35,
+                Instruction::PopAll => 36,
+            }
+        }
+
+        #[test]
+        fn samples_cover_every_variant() {
+            let moves = Move::variants_sample()
+                .into_iter()
+                .map(move_variant)
+                .collect::<alloc::collections::BTreeSet<_>>();
+            assert_eq!(moves, (0..20).collect());
+
+            let instrs = Instruction::variants_sample()
+                .into_iter()
+                .map(instruction_variant)
+                .collect::<alloc::collections::BTreeSet<_>>();
+            assert_eq!(instrs, (0..37).collect());
+        }
+
+        #[test]
+        fn round_trip_samples() {
+            for instr in Instruction::variants_sample() {
+                assert_round_trip(instr);
+            }
+        }
+
+        #[test]
+        fn ip_register() {
+            let instr = Instruction::Push(Register::Ip);
+
+            assert_eq!(instr.encode(), Ok(vec![0x7, 0x5]));
+            assert_round_trip(instr);
+        }
+    }
+
+    mod neg {
+        use super::*;
+        use crate::{cpu::ZERO, Machine};
+
+        #[test]
+        fn neg_one() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 1;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Neg(Register::A))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_FFFF);
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+            assert!(machine.cpu.registers.sign());
+            assert!(!machine.cpu.registers.overflow());
+        }
+
+        #[test]
+        fn neg_min_value() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0x8000_0000;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Neg(Register::A))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x8000_0000);
+            assert!(machine.cpu.registers.sign());
+            assert!(machine.cpu.registers.overflow());
+        }
+
+        #[test]
+        fn neg_zero_sets_zero_flag() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Neg(Register::A))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+    }
+
+    mod logic {
+        use super::*;
+        use crate::{cpu::ZERO, Machine};
+
+        #[test]
+        fn and_masks() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Logic(Logic::And(Register::B, Register::A));
+            machine.cpu.registers.a = 0x1234_5678;
+            machine.cpu.registers.b = 0x0000_FF00;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x0000_5600);
+            assert_eq!(machine.cpu.registers.b, 0x0000_FF00);
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn or_combines() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Logic(Logic::Or(Register::B, Register::A));
+            machine.cpu.registers.a = 0x1200_0034;
+            machine.cpu.registers.b = 0x0056_7800;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x1256_7834);
+        }
+
+        #[test]
+        fn xor_self_zeroes() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Logic(Logic::Xor(Register::A, Register::A));
+            machine.cpu.registers.a = 0xDEAD_BEEF;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn not_flips_bits() {
+            let mut machine = Machine::new();
+            let instr = Instruction::Logic(Logic::Not(Register::A));
+            machine.cpu.registers.a = 0xF0F0_0000;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x0F0F_FFFF);
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+        }
+    }
+
+    mod shift {
+        use super::*;
+        use crate::{
+            cpu::{MemIterator, CARRY, ZERO},
+            Machine,
+        };
+
+        fn shift(a: u32, shift_instr: ShiftOp) -> Machine {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = a;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Shift(shift_instr))
+                .expect("should execute");
+
+            machine
+        }
+
+        #[test]
+        fn read_mem() {
+            let mut machine = Machine::new();
+
+            // SAR A, 4
+            machine.cpu.mem[0] = 0xD;
+            machine.cpu.mem[1] = 0b0100_0000;
+            machine.cpu.mem[2] = 0x0;
+            machine.cpu.mem[3] = 4;
+
+            let parsed =
+                Instruction::read(MemIterator::new(0, &machine.cpu.mem)).expect("should read");
+
+            assert_eq!(
+                parsed.instr,
+                Instruction::Shift(ShiftOp::Sar(Register::A, 4))
+            );
+            assert_eq!(parsed.delta_ip, 4);
+
+            machine.cpu.mem[1] = 0b1110_0000;
+            assert!(Instruction::read(MemIterator::new(0, &machine.cpu.mem)).is_err());
+        }
+
+        #[test]
+        fn shl() {
+            let machine = shift(0x8000_0001, ShiftOp::Shl(Register::A, 1));
+
+            assert_eq!(machine.cpu.registers.a, 0x0000_0002);
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+
+            let machine = shift(0x4000_0001, ShiftOp::Shl(Register::A, 1));
+
+            assert_eq!(machine.cpu.registers.a, 0x8000_0002);
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+        }
+
+        #[test]
+        fn shr() {
+            let machine = shift(0x8000_0003, ShiftOp::Shr(Register::A, 1));
+
+            assert_eq!(machine.cpu.registers.a, 0x4000_0001);
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+
+            let machine = shift(0x0000_0001, ShiftOp::Shr(Register::A, 2));
+
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn sar_sign_extends() {
+            let machine = shift(0x8000_0010, ShiftOp::Sar(Register::A, 4));
+
+            assert_eq!(machine.cpu.registers.a, 0xF800_0001);
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+
+            let machine = shift(0x4000_0018, ShiftOp::Sar(Register::A, 4));
+
+            assert_eq!(machine.cpu.registers.a, 0x0400_0001);
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+        }
+
+        #[test]
+        fn rol() {
+            let machine = shift(0x8000_0001, ShiftOp::Rol(Register::A, 1));
+
+            assert_eq!(machine.cpu.registers.a, 0x0000_0003);
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+
+            let machine = shift(0x1234_5678, ShiftOp::Rol(Register::A, 8));
+
+            assert_eq!(machine.cpu.registers.a, 0x3456_7812);
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+        }
+
+        #[test]
+        fn ror() {
+            let machine = shift(0x0000_0001, ShiftOp::Ror(Register::A, 1));
+
+            assert_eq!(machine.cpu.registers.a, 0x8000_0000);
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+
+            let machine = shift(0x1234_5678, ShiftOp::Ror(Register::A, 8));
+
+            assert_eq!(machine.cpu.registers.a, 0x7812_3456);
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+        }
+
+        #[test]
+        fn count_is_masked() {
+            let machine = shift(0x0000_0001, ShiftOp::Shl(Register::A, 33));
+
+            assert_eq!(machine.cpu.registers.a, 0x0000_0002);
+
+            let machine = shift(0x0000_0001, ShiftOp::Shl(Register::A, 32));
+
+            assert_eq!(machine.cpu.registers.a, 0x0000_0001);
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+        }
+    }
+
+    mod cmp {
+        use super::*;
+        use crate::{
+            cpu::{CARRY, OVERFLOW, ZERO},
+            Machine,
+        };
+
+        #[test]
+        fn signed_overflow() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0x8000_0000;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::CmpImm(Register::A, 1))
+                .expect("should execute");
+
+            assert!(machine.cpu.registers.flags.contains(OVERFLOW));
+            assert_eq!(machine.cpu.registers.a, 0x8000_0000);
+        }
+
+        fn cmp(a: u32, b: u32) -> Machine {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = a;
+            machine.cpu.registers.b = b;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Cmp(Register::A, Register::B))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, a);
+            assert_eq!(machine.cpu.registers.b, b);
+
+            machine
+        }
+
+        #[test]
+        fn equal_sets_zero_only() {
+            let machine = cmp(42, 42);
+
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+        }
+
+        #[test]
+        fn smaller_than_larger_sets_carry() {
+            let machine = cmp(1, 42);
+
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+        }
+
+        #[test]
+        fn larger_than_smaller_sets_neither() {
+            let machine = cmp(42, 1);
+
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+        }
+
+        #[test]
+        fn immediate() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.x = 7;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::CmpImm(Register::X, 7))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.x, 7);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+
+            machine
+                .cpu
+                .do_instruction(Instruction::CmpImm(Register::X, 8))
+                .expect("should execute");
+
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+        }
+    }
+
+    mod inc_dec {
+        use super::*;
+        use crate::{cpu::ZERO, Machine};
+
+        fn run(a: u32, instr: Instruction) -> Machine {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = a;
+
+            machine.cpu.do_instruction(instr).expect("should execute");
+
+            machine
+        }
+
+        #[test]
+        fn inc_wraps_to_zero() {
+            let machine = run(0xFFFF_FFFF, Instruction::Inc(Register::A));
+
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn dec_reaches_zero() {
+            let machine = run(1, Instruction::Dec(Register::A));
+
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn dec_wraps_from_zero() {
+            let mut machine = run(0, Instruction::Dec(Register::A));
+
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_FFFF);
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Inc(Register::A))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn signed_flags() {
+            // (a, instr, sign, overflow)
+            let cases = [
+                (0x7FFF_FFFF, Instruction::Inc(Register::A), true, true),
+                (0xFFFF_FFFF, Instruction::Inc(Register::A), false, false),
+                (0x8000_0000, Instruction::Dec(Register::A), false, true),
+                (0, Instruction::Dec(Register::A), true, false),
+                (1, Instruction::Dec(Register::A), false, false),
+            ];
+
+            for (a, instr, sign, overflow) in cases {
+                let machine = run(a, instr);
+
+                assert_eq!(machine.cpu.registers.sign(), sign, "{instr} of {a:#x}");
+                assert_eq!(
+                    machine.cpu.registers.overflow(),
+                    overflow,
+                    "{instr} of {a:#x}"
+                );
+            }
+        }
+    }
+
+    mod nop {
+        use super::*;
+        use crate::{cpu::MemIterator, Machine};
+
+        #[test]
+        fn read_mem() {
+            let parsed =
+                Instruction::read(MemIterator::new(0, [0x12].as_slice())).expect("should read");
+
+            assert_eq!(parsed.instr, Instruction::Nop);
+            assert_eq!(parsed.delta_ip, 1);
+            assert_eq!(parsed.instr.to_string(), "NOP");
+        }
+
+        #[test]
+        fn nops_then_halt() {
+            let mut machine = Machine::new();
+            machine
+                .load_program(0, &[0x12, 0x12, 0x12, 0x0])
+                .expect("should load");
+
+            for _ in 0..3 {
+                machine.cpu.cycle().expect("should cycle");
+                assert!(!machine.cpu.is_halted());
+            }
+            assert_eq!(machine.cpu.registers.instruction_pointer, 3);
+
+            machine.cpu.cycle().expect("should cycle");
+            assert!(machine.cpu.is_halted());
+            assert_eq!(machine.cpu.registers.instruction_pointer, 4);
+        }
+    }
+
+    mod stack_bounds {
+        use crate::{Machine, RunResult};
+
+        // room for two dwords at 0x1000..0x1008
+        fn bounded_machine(src: &str) -> Machine {
+            let mut machine = Machine::new();
+            machine.load_asm(src, 0).expect("should load");
+            machine.set_stack_bounds(Some(0x1000..0x1008));
+            machine.cpu.registers.stack_pointer = 0x1008;
+
+            machine
+        }
+
+        fn assert_fault(result: RunResult, fault: &str) {
+            match result {
+                RunResult::ExecutionError(e) => assert!(e.starts_with(fault), "{e}"),
+                result => panic!("expected a stack fault, got {result:?}"),
+            }
+        }
+
+        #[test]
+        fn push_past_limit() {
+            let mut machine = bounded_machine("PUSH A\nPUSH A\nPUSH A\nHALT");
+
+            assert_fault(machine.run_until_halt(10), "Stack overflow");
+            assert!(machine.cpu.is_halted());
+            assert_eq!(machine.cpu.registers.stack_pointer, 0x1000);
+            assert_eq!(machine.cpu.instructions_retired(), 2);
+        }
+
+        #[test]
+        fn call_past_limit() {
+            let mut machine = bounded_machine("CALL 0x0");
+
+            assert_fault(machine.run_until_halt(10), "Stack overflow");
+            assert_eq!(machine.cpu.registers.stack_pointer, 0x1000);
+        }
+
+        #[test]
+        fn pop_below_base() {
+            let mut machine = bounded_machine("PUSH A\nPOP B\nPOP B\nHALT");
+
+            assert_fault(machine.run_until_halt(10), "Stack underflow");
+            assert!(machine.cpu.is_halted());
+            assert_eq!(machine.cpu.registers.stack_pointer, 0x1008);
+        }
+
+        #[test]
+        fn unbounded_by_default() {
+            let mut machine = Machine::new();
+            machine
+                .load_asm("PUSH A\nPUSH A\nPUSH A\nHALT", 0)
+                .expect("should load");
+            machine.cpu.registers.stack_pointer = 0x1008;
+
+            assert_eq!(machine.cpu.stack_bounds(), None);
+            assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+        }
+    }
+
+    mod push_all {
+        use super::*;
+        use crate::{
+            cpu::{CARRY, DEFAULT_MEM_SIZE, ZERO},
+            Machine,
+        };
+
+        #[test]
+        fn restores_every_register() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0xA;
+            machine.cpu.registers.b = 0xB;
+            machine.cpu.registers.x = 0xC;
+            machine.cpu.registers.y = 0xD;
+            machine.cpu.registers.flags.set(CARRY);
+            let saved = machine.cpu.registers;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::PushAll)
+                .expect("should execute");
             assert_eq!(
-                reg_to_reg_instr.instr,
-                Instruction::Move(Move::RegToReg(Register::A, Register::A))
-            );
-            assert_eq!(
-                imm_to_reg32_instr.instr,
-                Instruction::Move(Move::ImmToReg32(0, Register::A))
-            );
-            assert_eq!(
-                imm_to_reg16_instr.instr,
-                Instruction::Move(Move::ImmToReg16(0, Register::A))
-            );
-            assert_eq!(
-                imm_to_reg8_instr.instr,
-                Instruction::Move(Move::ImmToReg8(0, Register::A))
-            );
-            assert_eq!(
-                reg_to_mem32_instr.instr,
-                Instruction::Move(Move::RegToMem32(Register::A, 0))
+                machine.cpu.registers.stack_pointer,
+                DEFAULT_MEM_SIZE as u32 - 20
+            );
+
+            machine.cpu.registers.a = 0;
+            machine.cpu.registers.b = 0;
+            machine.cpu.registers.x = 0;
+            machine.cpu.registers.y = 0;
+            machine.cpu.registers.flags = ZERO.into();
+
+            machine
+                .cpu
+                .do_instruction(Instruction::PopAll)
+                .expect("should execute");
+
+            assert!(machine.cpu.registers == saved);
+        }
+
+        #[test]
+        fn push_order() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0xA;
+            machine.cpu.registers.y = 0xD;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::PushAll)
+                .expect("should execute");
+            // Y then the flags are on top, A is at the bottom
+            machine
+                .cpu
+                .do_instruction(Instruction::Pop(Register::B))
+                .expect("should execute");
+            machine
+                .cpu
+                .do_instruction(Instruction::Pop(Register::X))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.b, 0);
+            assert_eq!(machine.cpu.registers.x, 0xD);
+        }
+
+        #[test]
+        fn pop_underflow_changes_nothing() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0xA;
+            machine
+                .cpu
+                .do_instruction(Instruction::Push(Register::A))
+                .expect("should execute");
+            let saved = machine.cpu.registers;
+
+            // only one value is on the stack
+            assert!(machine.cpu.do_instruction(Instruction::PopAll).is_err());
+            assert!(machine.cpu.registers == saved);
+        }
+    }
+
+    mod swap {
+        use super::*;
+        use crate::Machine;
+
+        #[test]
+        fn swap_registers() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0xDEAD_BEEF;
+            machine.cpu.registers.b = 0x1234_5678;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Swap(Register::A, Register::B))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x1234_5678);
+            assert_eq!(machine.cpu.registers.b, 0xDEAD_BEEF);
+        }
+
+        #[test]
+        fn swap_self_is_noop() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0xDEAD_BEEF;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Swap(Register::A, Register::A))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0xDEAD_BEEF);
+        }
+
+        #[test]
+        fn swap_with_ip() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0x100;
+            machine.cpu.registers.instruction_pointer = 0x42;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Swap(Register::A, Register::Ip))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x42);
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x100);
+        }
+
+        #[test]
+        fn xchg_with_memory() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0xDEAD_BEEF;
+            machine
+                .load_program(0x10, &0x1234_5678u32.to_le_bytes())
+                .expect("should load");
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Xchg(Register::A, 0x10))
+                .expect("should execute");
+
+            assert_eq!(machine.cpu.registers.a, 0x1234_5678);
+            assert_eq!(
+                [0x10, 0x11, 0x12, 0x13].map(|addr| machine.cpu.mem[addr]),
+                0xDEAD_BEEFu32.to_le_bytes()
             );
+        }
+
+        #[test]
+        fn xchg_out_of_bounds() {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = 0xDEAD_BEEF;
+            let addr = machine.cpu.mem.size() as u32 - 2;
+
+            assert!(machine
+                .cpu
+                .do_instruction(Instruction::Xchg(Register::A, addr))
+                .is_err());
+            assert_eq!(machine.cpu.registers.a, 0xDEAD_BEEF);
+        }
+    }
+
+    mod decode_error {
+        use super::*;
+        use crate::cpu::MemIterator;
+
+        fn read(bytes: &[u8]) -> Result<ParsedInstruction, DecodeError> {
+            Instruction::read(MemIterator::with_len(0, bytes, bytes.len()))
+        }
+
+        #[test]
+        fn invalid_group() {
+            assert_eq!(read(&[0xFF]).err(), Some(DecodeError::InvalidGroup(0xFF)));
+        }
+
+        #[test]
+        fn invalid_register() {
+            // PUSH with a register id that doesn't exist
             assert_eq!(
-                reg_to_mem16_instr.instr,
-                Instruction::Move(Move::RegToMem16(Register::A, 0))
+                read(&[0x7, 0x9]).err(),
+                Some(DecodeError::InvalidRegister(0x9))
             );
+        }
+
+        #[test]
+        fn invalid_opcode() {
             assert_eq!(
-                reg_to_mem8_instr.instr,
-                Instruction::Move(Move::RegToMem8(Register::A, 0))
+                read(&[0x2, 0b1100_0000]).err(),
+                Some(DecodeError::InvalidAddOpcode(0b1100_0000))
             );
+        }
+
+        #[test]
+        fn truncated() {
             assert_eq!(
-                mem_to_reg32_instr.instr,
-                Instruction::Move(Move::MemToReg32(0, Register::A))
+                read(&[0x4, 0x0, 0x1]).err(),
+                Some(DecodeError::Truncated {
+                    needed: 4,
+                    available: 2
+                })
             );
+        }
+
+        #[test]
+        fn decode_empty() {
             assert_eq!(
-                mem_to_reg16_instr.instr,
-                Instruction::Move(Move::MemToReg16(0, Register::A))
+                decode(&[]),
+                Err(DecodeError::Truncated {
+                    needed: 1,
+                    available: 0
+                })
+            );
+        }
+
+        #[test]
+        fn decode_never_panics() {
+            fn check(bytes: &[u8]) {
+                if let Ok((instr, len)) = decode(bytes) {
+                    assert!(len > 0 && len <= bytes.len(), "{instr} from {bytes:02x?}");
+                }
+            }
+
+            for a in 0..=0xFF {
+                check(&[a]);
+                for b in 0..=0xFF {
+                    check(&[a, b]);
+                }
+            }
+
+            // xorshift, so the test is the same every run
+            let mut state = 0x2545_F491_u32;
+            let mut next = || {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state
+            };
+
+            for _ in 0..100_000 {
+                let len = next() as usize % 12;
+                let bytes = (0..len).map(|_| next() as u8).collect::<Vec<_>>();
+                check(&bytes);
+
+                // bias towards valid groups so the operands get exercised too
+                if let Some(group) = bytes.first().copied() {
+                    let mut bytes = bytes;
+                    bytes[0] = group % 0x25;
+                    check(&bytes);
+                }
+            }
+        }
+
+        #[test]
+        fn decode_round_trip() {
+            let instr = Instruction::Move(Move::ImmToMem16(0xBEEF, 0x10));
+            let bytes = instr.encode().expect("should encode");
+
+            assert_eq!(decode(&bytes), Ok((instr, bytes.len())));
+            assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        }
+
+        #[test]
+        fn display() {
+            assert_eq!(
+                DecodeError::InvalidRegister(0x9).to_string(),
+                "Got invalid register id: 0x9"
             );
+        }
+    }
+
+    mod mul {
+        use super::*;
+        use crate::{
+            cpu::{CARRY, ZERO},
+            Machine,
+        };
+
+        fn mul(a: u32, b: u32) -> Machine {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = a;
+            machine.cpu.registers.b = b;
+            machine.cpu.registers.y = 0xFFFF_FFFF;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Mul(Register::B, Register::A))
+                .expect("should execute");
+
+            machine
+        }
+
+        #[test]
+        fn mul_fits() {
+            let machine = mul(6, 7);
+
+            assert_eq!(machine.cpu.registers.a, 42);
+            assert_eq!(machine.cpu.registers.b, 7);
+            assert_eq!(machine.cpu.registers.y, 0);
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn mul_fills_high_word() {
+            let machine = mul(0xFFFF_FFFF, 0x10);
+
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_FFF0);
+            assert_eq!(machine.cpu.registers.y, 0xF);
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+        }
+
+        #[test]
+        fn mul_by_zero() {
+            let machine = mul(0xDEAD_BEEF, 0);
+
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert_eq!(machine.cpu.registers.y, 0);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
+        }
+
+        #[test]
+        fn mul_zero_low_word() {
+            let machine = mul(0x1_0000, 0x1_0000);
+
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert_eq!(machine.cpu.registers.y, 1);
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+            assert!(machine.cpu.registers.flags.contains(CARRY));
+        }
+    }
+
+    mod div {
+        use super::*;
+        use crate::{cpu::ZERO, Machine, RunResult};
+
+        fn div(a: u32, b: u32) -> (Machine, Result<(), String>) {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = a;
+            machine.cpu.registers.b = b;
+            machine.cpu.registers.y = 0xFFFF_FFFF;
+
+            let result = machine
+                .cpu
+                .do_instruction(Instruction::Div(Register::B, Register::A));
+
+            (machine, result)
+        }
+
+        #[test]
+        fn div_exact() {
+            let (machine, result) = div(42, 7);
+
+            assert!(result.is_ok());
+            assert_eq!(machine.cpu.registers.a, 6);
+            assert_eq!(machine.cpu.registers.b, 7);
+            assert_eq!(machine.cpu.registers.y, 0);
+            assert!(!machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn div_remainder() {
+            let (machine, result) = div(3, 7);
+
+            assert!(result.is_ok());
+            assert_eq!(machine.cpu.registers.a, 0);
+            assert_eq!(machine.cpu.registers.y, 3);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn div_by_zero_faults() {
+            let (machine, result) = div(42, 0);
+
+            assert!(result.is_err());
+            assert!(machine.cpu.is_halted());
+            assert_eq!(machine.cpu.registers.a, 42);
+            assert_eq!(machine.cpu.registers.y, 0xFFFF_FFFF);
+        }
+
+        #[test]
+        fn div_by_zero_stops_run() {
+            let mut machine = Machine::new();
+            let program = Instruction::Div(Register::B, Register::A)
+                .encode()
+                .expect("should encode");
+            machine.load_program(0, &program).expect("should load");
+
+            assert!(matches!(
+                machine.run_until_halt(10),
+                RunResult::ExecutionError(_)
+            ));
+        }
+    }
+
+    mod interrupt {
+        use super::*;
+        use crate::{
+            cpu::{MemIterator, DEFAULT_MEM_SIZE, INTERRUPT, INTERRUPT_VECTOR_BASE, ZERO},
+            Machine, RunResult,
+        };
+
+        fn machine_with_handler() -> Machine {
+            let mut machine = Machine::new();
+
+            let program = crate::asm::assemble("STI\nNOP\nHALT").expect("should assemble");
+            machine.load_program(0, &program).expect("should load");
+
+            let handler = crate::asm::assemble("INC B\nIRET").expect("should assemble");
+            machine.load_program(0x200, &handler).expect("should load");
+            machine
+                .load_program(INTERRUPT_VECTOR_BASE + 4, &0x200u32.to_le_bytes())
+                .expect("should load");
+
+            machine
+        }
+
+        #[test]
+        fn read_mem() {
+            for (byte, instr, text) in [
+                (0x17, Instruction::Iret, "IRET"),
+                (0x18, Instruction::Cli, "CLI"),
+                (0x19, Instruction::Sti, "STI"),
+            ] {
+                let parsed =
+                    Instruction::read(MemIterator::new(0, [byte].as_slice())).expect("should read");
+
+                assert_eq!(parsed.instr, instr);
+                assert_eq!(parsed.delta_ip, 1);
+                assert_eq!(parsed.instr.to_string(), text);
+            }
+        }
+
+        #[test]
+        fn raise_and_return() {
+            let mut machine = machine_with_handler();
+            machine.run_cycle().expect("should cycle");
+            machine.cpu.registers.flags.set(ZERO);
+
+            assert_eq!(machine.raise_interrupt(1), Ok(true));
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0x200);
             assert_eq!(
-                mem_to_reg8_instr.instr,
-                Instruction::Move(Move::MemToReg8(0, Register::A))
+                machine.cpu.registers.stack_pointer,
+                DEFAULT_MEM_SIZE as u32 - 8
             );
+            assert!(!machine.cpu.registers.flags.contains(INTERRUPT));
+
+            // INC B, IRET
+            machine.run_cycle().expect("should cycle");
+            machine.run_cycle().expect("should cycle");
+
+            assert_eq!(machine.cpu.registers.b, 1);
+            assert_eq!(machine.cpu.registers.instruction_pointer, 1);
+            assert_eq!(machine.cpu.registers.stack_pointer, DEFAULT_MEM_SIZE as u32);
+            assert!(machine.cpu.registers.flags.contains(INTERRUPT));
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn iret_fault_changes_nothing() {
+            let mut machine = Machine::from_instructions(&[Instruction::Iret]);
+            // only the IP was pushed, popping the flags runs off of memory
+            let stack_pointer = DEFAULT_MEM_SIZE as u32 - 4;
+            machine.cpu.registers.stack_pointer = stack_pointer;
+            machine
+                .load_program(stack_pointer, &0x200u32.to_le_bytes())
+                .expect("should load");
+            machine.cpu.registers.flags.set(ZERO);
+
+            assert!(machine.run_cycle().is_err());
+            assert_eq!(machine.cpu.registers.instruction_pointer, 1);
+            assert_eq!(machine.cpu.registers.stack_pointer, stack_pointer);
+            assert!(machine.cpu.registers.flags.contains(ZERO));
+        }
+
+        #[test]
+        fn ignored_when_disabled() {
+            let mut machine = machine_with_handler();
+
+            assert_eq!(machine.raise_interrupt(1), Ok(false));
+            assert_eq!(machine.cpu.registers.instruction_pointer, 0);
+
+            machine.run_cycle().expect("should cycle");
+            machine
+                .cpu
+                .do_instruction(Instruction::Cli)
+                .expect("should execute");
+
+            assert_eq!(machine.raise_interrupt(1), Ok(false));
+            assert_eq!(machine.cpu.registers.instruction_pointer, 1);
+        }
+
+        #[test]
+        fn wakes_halted_cpu() {
+            let mut machine = machine_with_handler();
+            machine.run_until_halt(10);
+            assert!(machine.cpu.is_halted());
+
+            assert_eq!(machine.raise_interrupt(1), Ok(true));
+            assert!(!machine.cpu.is_halted());
+            assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+            assert_eq!(machine.cpu.registers.b, 1);
+        }
+
+        #[test]
+        fn vector_table_out_of_bounds() {
+            let mut machine = Machine::with_mem_size(0x1000);
+            machine.cpu.registers.flags.set(INTERRUPT);
+
+            assert!(machine.raise_interrupt(1).is_err());
+            assert_eq!(machine.cpu.registers.stack_pointer, 0x1000);
+        }
+    }
+
+    mod alu {
+        use super::*;
+        use crate::{
+            cpu::{MemIterator, CARRY, ZERO},
+            Machine,
+        };
+
+        fn alu(op: AluOp, a: u32, src: AluSource) -> Machine {
+            let mut machine = Machine::new();
+            machine.cpu.registers.a = a;
+
+            machine
+                .cpu
+                .do_instruction(Instruction::Alu(Alu {
+                    op,
+                    src,
+                    dst: Register::A,
+                }))
+                .expect("should execute");
+
+            machine
+        }
+
+        #[test]
+        fn read_mem() {
+            let xor_reg = [0b0010_0000u8, 2, 0];
+            let cmp_imm = [0b0110_1000u8, 0x04, 0x03, 0x02, 0x01, 1];
+            let sub_mem = [0b1000_1000u8, 0x10, 0, 0, 0, 3];
+
+            let parsed = Alu::read(MemIterator::new(0, xor_reg.as_slice())).expect("should read");
             assert_eq!(
-                mem_to_mem32_instr.instr,
-                Instruction::Move(Move::MemToMem32(0, 0))
+                parsed.instr,
+                Instruction::Alu(Alu {
+                    op: AluOp::Xor,
+                    src: AluSource::Reg(Register::X),
+                    dst: Register::A,
+                })
             );
+            assert_eq!(parsed.delta_ip, 3);
+
+            let parsed = Alu::read(MemIterator::new(0, cmp_imm.as_slice())).expect("should read");
             assert_eq!(
-                mem_to_mem16_instr.instr,
-                Instruction::Move(Move::MemToMem16(0, 0))
+                parsed.instr,
+                Instruction::Alu(Alu {
+                    op: AluOp::Cmp,
+                    src: AluSource::Imm(0x0102_0304),
+                    dst: Register::B,
+                })
             );
+            assert_eq!(parsed.delta_ip, 6);
+
+            let parsed = Alu::read(MemIterator::new(0, sub_mem.as_slice())).expect("should read");
             assert_eq!(
-                mem_to_mem8_instr.instr,
-                Instruction::Move(Move::MemToMem8(0, 0))
+                parsed.instr,
+                Instruction::Alu(Alu {
+                    op: AluOp::Sub,
+                    src: AluSource::Mem(0x10),
+                    dst: Register::Y,
+                })
             );
+            assert_eq!(parsed.delta_ip, 6);
         }
 
         #[test]
-        fn move_reg_to_reg() {
-            let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::RegToReg(Register::A, Register::B));
-            machine.cpu.registers.a = 42;
-            machine.cpu.registers.b = 2;
+        fn invalid_opcode() {
+            for opcode in [0b0011_0000u8, 0b1100_0000, 0b0000_0001] {
+                assert_eq!(
+                    Alu::read(MemIterator::new(0, [opcode, 0, 0].as_slice())).err(),
+                    Some(DecodeError::InvalidAluOpcode(opcode))
+                );
+            }
+        }
+
+        #[test]
+        fn reg_and_imm_per_op() {
+            // (op, a, src, result, zero, carry)
+            let cases = [
+                (AluOp::Add, 40, 2, 42, false, false),
+                (AluOp::Add, 0xFFFF_FFFF, 1, 0, true, true),
+                (AluOp::Sub, 44, 2, 42, false, false),
+                (AluOp::Sub, 1, 2, 0xFFFF_FFFF, false, true),
+                (AluOp::And, 0x1234_5678, 0xFF00, 0x5600, false, false),
+                (AluOp::And, 0xF0, 0x0F, 0, true, false),
+                (
+                    AluOp::Or,
+                    0x1200_0034,
+                    0x0056_7800,
+                    0x1256_7834,
+                    false,
+                    false,
+                ),
+                (AluOp::Or, 0, 0, 0, true, false),
+                (AluOp::Xor, 0xFF00, 0x0FF0, 0xF0F0, false, false),
+                (AluOp::Xor, 0xDEAD_BEEF, 0xDEAD_BEEF, 0, true, false),
+                // CMP leaves the register alone
+                (AluOp::Cmp, 42, 42, 42, true, false),
+                (AluOp::Cmp, 1, 42, 1, false, true),
+            ];
 
-            machine.cpu.do_instruction(instr);
+            for (op, a, src, result, zero, carry) in cases {
+                let mut machine = Machine::new();
+                machine.cpu.registers.b = src;
+                machine.cpu.registers.a = a;
+                machine
+                    .cpu
+                    .do_instruction(Instruction::Alu(Alu {
+                        op,
+                        src: AluSource::Reg(Register::B),
+                        dst: Register::A,
+                    }))
+                    .expect("should execute");
 
-            assert_eq!(machine.cpu.registers.a, machine.cpu.registers.b);
-            assert_eq!(machine.cpu.registers.b, 42);
+                assert_eq!(machine.cpu.registers.a, result, "{op:?} reg");
+                assert_eq!(machine.cpu.registers.b, src);
+                assert_eq!(machine.cpu.registers.flags.contains(ZERO), zero);
+                assert_eq!(machine.cpu.registers.flags.contains(CARRY), carry);
+
+                let machine = alu(op, a, AluSource::Imm(src));
+
+                assert_eq!(machine.cpu.registers.a, result, "{op:?} imm");
+                assert_eq!(machine.cpu.registers.flags.contains(ZERO), zero);
+                assert_eq!(machine.cpu.registers.flags.contains(CARRY), carry);
+            }
         }
 
         #[test]
-        fn move_imm_to_reg32() {
+        fn bitwise_clears_carry() {
             let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::ImmToReg32(0, Register::A));
-            machine.cpu.registers.a = 0xFFFF_FFFF;
+            machine.cpu.registers.set_carry(true);
+            machine.cpu.registers.a = 1;
 
-            machine.cpu.do_instruction(instr);
+            machine
+                .cpu
+                .do_instruction(Instruction::Alu(Alu {
+                    op: AluOp::Or,
+                    src: AluSource::Imm(2),
+                    dst: Register::A,
+                }))
+                .expect("should execute");
 
-            assert_eq!(machine.cpu.registers.a, 0);
+            assert_eq!(machine.cpu.registers.a, 3);
+            assert!(!machine.cpu.registers.flags.contains(CARRY));
         }
 
         #[test]
-        fn move_imm_to_reg16() {
+        fn mem_source() {
             let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::ImmToReg16(0, Register::A));
-            machine.cpu.registers.a = 0xFFFF_FFFF;
+            machine.cpu.mem[0x100] = 0x02;
 
-            machine.cpu.do_instruction(instr);
+            machine
+                .cpu
+                .do_instruction(Instruction::Alu(Alu {
+                    op: AluOp::Add,
+                    src: AluSource::Mem(0x100),
+                    dst: Register::A,
+                }))
+                .expect("should execute");
 
-            assert_eq!(machine.cpu.registers.a, 0xFFFF_0000);
+            assert_eq!(machine.cpu.registers.a, 2);
         }
 
         #[test]
-        fn move_imm_to_reg8() {
+        fn aliases_match_alu() {
+            let run = |instr: Instruction| {
+                let mut machine = Machine::new();
+                machine.cpu.registers.a = 0x8000_0001;
+                machine.cpu.registers.b = 0x8000_0000;
+                machine.cpu.registers.x = 3;
+                machine.cpu.registers.y = 0xFFFF_FFFF;
+                machine.cpu.mem[0x10] = 0x80;
+                machine.cpu.do_instruction(instr).expect("should execute");
+                machine.cpu.registers
+            };
+
+            for instr in Instruction::variants_sample() {
+                if let Some(alu_instr) = instr.as_alu() {
+                    assert!(run(instr) == run(Instruction::Alu(alu_instr)), "{instr}");
+                }
+            }
+        }
+
+        #[test]
+        fn display() {
+            let instr = Instruction::Alu(Alu {
+                op: AluOp::Xor,
+                src: AluSource::Imm(0xFF),
+                dst: Register::B,
+            });
+
+            assert_eq!(instr.to_string(), "XOR B, 0x000000ff");
+            assert_eq!(instr.mnemonic(), "XOR");
+        }
+    }
+
+    mod lea {
+        use super::*;
+        use crate::Machine;
+
+        fn lea(x: u32, displacement: i32) -> Machine {
             let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::ImmToReg8(0, Register::A));
-            machine.cpu.registers.a = 0xFFFF_FFFF;
+            machine.cpu.registers.x = x;
 
-            machine.cpu.do_instruction(instr);
+            machine
+                .cpu
+                .do_instruction(Instruction::Lea(
+                    Register::A,
+                    AddrExpr {
+                        base: Register::X,
+                        displacement,
+                    },
+                ))
+                .expect("should execute");
 
-            assert_eq!(machine.cpu.registers.a, 0xFFFF_FF00);
+            machine
         }
 
         #[test]
-        fn move_reg_to_mem32() {
-            let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::RegToMem32(Register::A, 0x0));
-            machine.cpu.registers.a = 0x0403_0201;
+        fn base_plus_displacement() {
+            let machine = lea(0x10, 0x4);
 
-            machine.cpu.do_instruction(instr);
+            assert_eq!(machine.cpu.registers.a, 0x14);
+            assert_eq!(machine.cpu.registers.x, 0x10);
+        }
 
-            assert_eq!(machine.cpu.mem[0], 0x01);
-            assert_eq!(machine.cpu.mem[1], 0x02);
-            assert_eq!(machine.cpu.mem[2], 0x03);
-            assert_eq!(machine.cpu.mem[3], 0x04);
+        #[test]
+        fn negative_displacement_wraps() {
+            assert_eq!(lea(0x10, -0x4).cpu.registers.a, 0xC);
+            assert_eq!(lea(0, -1).cpu.registers.a, 0xFFFF_FFFF);
         }
 
         #[test]
-        fn move_reg_to_mem16() {
-            let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::RegToMem16(Register::A, 0x0));
-            machine.cpu.registers.a = 0x0403_0201;
+        fn doesnt_touch_memory() {
+            // way past the end of memory, so any access would fault
+            let machine = lea(0xFFFF_FFF0, 0x4);
 
-            machine.cpu.do_instruction(instr);
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_FFF4);
+            assert!(machine
+                .cpu
+                .mem
+                .page(machine.cpu.mem.page_count() - 1)
+                .is_none());
+        }
 
-            assert_eq!(machine.cpu.mem[0], 0x01);
-            assert_eq!(machine.cpu.mem[1], 0x02);
-            assert_eq!(machine.cpu.mem[2], 0x00);
-            assert_eq!(machine.cpu.mem[3], 0x00);
+        #[test]
+        fn display() {
+            let instr = |displacement| {
+                Instruction::Lea(
+                    Register::A,
+                    AddrExpr {
+                        base: Register::X,
+                        displacement,
+                    },
+                )
+                .to_string()
+            };
+
+            assert_eq!(instr(4), "LEA A, [X + 0x4]");
+            assert_eq!(instr(-4), "LEA A, [X - 0x4]");
+            assert_eq!(instr(i32::MIN), "LEA A, [X - 0x80000000]");
+        }
+    }
+
+    mod signed_jump {
+        use super::*;
+        use crate::Machine;
+
+        // which of JL, JGE, JG and JLE jump after `CMP A, B`
+        fn jumps(a: i32, b: i32) -> [bool; 4] {
+            let jumps = [
+                Instruction::JumpIfLess(0x100),
+                Instruction::JumpIfGreaterOrEqual(0x100),
+                Instruction::JumpIfGreater(0x100),
+                Instruction::JumpIfLessOrEqual(0x100),
+            ];
+
+            jumps.map(|jump| {
+                let mut machine = Machine::new();
+                machine.cpu.registers.a = a as u32;
+                machine.cpu.registers.b = b as u32;
+
+                machine
+                    .cpu
+                    .do_instruction(Instruction::Cmp(Register::A, Register::B))
+                    .expect("should execute");
+                machine.cpu.do_instruction(jump).expect("should execute");
+
+                machine.cpu.registers.instruction_pointer == 0x100
+            })
         }
 
         #[test]
-        fn move_reg_to_mem8() {
+        fn relations() {
+            let less = [true, false, false, true];
+            let equal = [false, true, false, true];
+            let greater = [false, true, true, false];
+
+            for (a, b, expected) in [
+                (1, 2, less),
+                (2, 2, equal),
+                (3, 2, greater),
+                (-5, 3, less),
+                (3, -5, greater),
+                (-5, -3, less),
+                (-3, -5, greater),
+                (-7, -7, equal),
+                // these overflow the subtraction
+                (i32::MIN, 1, less),
+                (i32::MAX, -1, greater),
+                (i32::MIN, i32::MAX, less),
+            ] {
+                assert_eq!(jumps(a, b), expected, "CMP {a}, {b}");
+            }
+        }
+
+        #[test]
+        fn sign_flag() {
             let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::RegToMem8(Register::A, 0x0));
-            machine.cpu.registers.a = 0x0403_0201;
+            machine.cpu.registers.a = 1;
 
-            machine.cpu.do_instruction(instr);
+            machine
+                .cpu
+                .do_instruction(Instruction::Sub(Sub::ImmToReg(2, Register::A)))
+                .expect("should execute");
+            assert!(machine.cpu.registers.sign());
 
-            assert_eq!(machine.cpu.mem[0], 0x01);
-            assert_eq!(machine.cpu.mem[1], 0x00);
-            assert_eq!(machine.cpu.mem[2], 0x00);
-            assert_eq!(machine.cpu.mem[3], 0x00);
+            machine
+                .cpu
+                .do_instruction(Instruction::Add(Add::ImmToReg(2, Register::A)))
+                .expect("should execute");
+            assert!(!machine.cpu.registers.sign());
         }
 
         #[test]
-        fn move_mem_to_reg32() {
+        fn jl_after_dec() {
+            // the CMP leaves SIGN set, which the DEC has to clear again
+            let program = crate::asm::assemble(
+                r#"
+                MOV A, 1
+                CMP A, 2
+                DEC A
+                JL wrong
+                MOV A, 2
+            count:
+                DEC A
+                JL done
+                INC B
+                JMP count
+            wrong:
+                MOV X, 1
+            done:
+                HALT
+            "#,
+            )
+            .expect("should assemble");
+
             let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::MemToReg32(0, Register::A));
-            machine.cpu.registers.a = 0xFFFF_FFFF;
+            machine.load_program(0, &program).expect("should load");
+            assert_eq!(machine.run_until_halt(100), crate::RunResult::Halted);
 
-            machine.cpu.mem[0] = 0x01;
-            machine.cpu.mem[1] = 0x02;
-            machine.cpu.mem[2] = 0x03;
-            machine.cpu.mem[3] = 0x04;
+            assert_eq!(machine.cpu.registers.x, 0);
+            assert_eq!(machine.cpu.registers.a, 0xFFFF_FFFF);
+            assert_eq!(machine.cpu.registers.b, 2);
+        }
+
+        #[test]
+        fn sign_follows_every_result() {
+            for instr in [
+                Instruction::Logic(Logic::Not(Register::A)),
+                Instruction::Shift(ShiftOp::Shl(Register::A, 31)),
+                Instruction::Mul(Register::B, Register::A),
+                Instruction::Div(Register::B, Register::A),
+            ] {
+                let mut machine = Machine::new();
+                machine.cpu.registers.set_sign(true);
+                // NOT 0xFFFF_FFFF, 2 << 31, 2 * 1 and 2 / 1 are all non-negative
+                machine.cpu.registers.a = if instr.mnemonic() == "NOT" {
+                    0xFFFF_FFFF
+                } else {
+                    2
+                };
+                machine.cpu.registers.b = 1;
 
-            machine.cpu.do_instruction(instr);
+                machine.cpu.do_instruction(instr).expect("should execute");
 
-            assert_eq!(machine.cpu.registers.a, 0x0403_0201);
+                assert!(!machine.cpu.registers.sign(), "{instr}");
+            }
         }
+    }
 
-        #[test]
-        fn move_mem_to_reg16() {
+    mod branch {
+        use super::*;
+        use crate::{
+            cpu::{CARRY, ZERO},
+            Machine,
+        };
+
+        fn branch(instr: Instruction, a: u32, b: u32) -> Machine {
             let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::MemToReg16(0, Register::A));
-            machine.cpu.registers.a = 0xFFFF_FFFF;
+            machine.cpu.registers.instruction_pointer = 0x10;
+            machine.cpu.registers.a = a;
+            machine.cpu.registers.b = b;
+            // flags that no comparison of a and b would give
+            machine.cpu.registers.set_zero(a != b);
+            machine.cpu.registers.set_carry(true);
 
-            machine.cpu.mem[0] = 0x01;
-            machine.cpu.mem[1] = 0x02;
-            machine.cpu.mem[2] = 0x03;
-            machine.cpu.mem[3] = 0x04;
+            machine.cpu.do_instruction(instr).expect("should execute");
 
-            machine.cpu.do_instruction(instr);
+            assert_eq!(machine.cpu.registers.zero(), a != b);
+            assert!(machine.cpu.registers.flags.contains(CARRY));
 
-            assert_eq!(machine.cpu.registers.a, 0xFFFF_0201);
+            machine
         }
 
         #[test]
-        fn move_mem_to_reg8() {
-            let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::MemToReg8(0, Register::A));
-            machine.cpu.registers.a = 0xFFFF_FFFF;
+        fn equal() {
+            let instr = Instruction::BranchIfEqual(Register::A, Register::B, 0x100);
 
-            machine.cpu.mem[0] = 0x01;
-            machine.cpu.mem[1] = 0x02;
-            machine.cpu.mem[2] = 0x03;
-            machine.cpu.mem[3] = 0x04;
+            let taken = branch(instr, 42, 42);
+            assert_eq!(taken.cpu.registers.instruction_pointer, 0x100);
 
-            machine.cpu.do_instruction(instr);
+            let not_taken = branch(instr, 42, 41);
+            assert_eq!(not_taken.cpu.registers.instruction_pointer, 0x10);
+        }
 
-            assert_eq!(machine.cpu.registers.a, 0xFFFF_FF01);
+        #[test]
+        fn not_equal() {
+            let instr = Instruction::BranchIfNotEqual(Register::A, Register::B, 0x100);
+
+            let taken = branch(instr, 1, 2);
+            assert_eq!(taken.cpu.registers.instruction_pointer, 0x100);
+
+            let not_taken = branch(instr, 2, 2);
+            assert_eq!(not_taken.cpu.registers.instruction_pointer, 0x10);
+            assert!(!not_taken.cpu.registers.flags.contains(ZERO));
         }
 
         #[test]
-        fn move_mem_to_mem32() {
-            let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::MemToMem32(0x0, 0x4));
+        fn read_mem() {
+            let bytes = [0x1D, 0x2, 0x3, 0x04, 0x03, 0x02, 0x01];
+            let parsed =
+                Instruction::read(MemIterator::new(0, bytes.as_slice())).expect("should read");
 
-            machine.cpu.mem[0] = 0x01;
-            machine.cpu.mem[1] = 0x02;
-            machine.cpu.mem[2] = 0x03;
-            machine.cpu.mem[3] = 0x04;
+            assert_eq!(
+                parsed.instr,
+                Instruction::BranchIfNotEqual(Register::X, Register::Y, 0x0102_0304)
+            );
+            assert_eq!(parsed.delta_ip, 7);
+            assert_eq!(parsed.instr.to_string(), "BNE X, Y, 0x01020304");
+        }
+    }
+
+    mod length_from {
+        use super::*;
 
-            machine.cpu.do_instruction(instr);
+        #[test]
+        fn every_move_mode_matches_read() {
+            let samples = Move::variants_sample();
 
-            assert_eq!(machine.cpu.mem[4], 0x01);
-            assert_eq!(machine.cpu.mem[5], 0x02);
-            assert_eq!(machine.cpu.mem[6], 0x03);
-            assert_eq!(machine.cpu.mem[7], 0x04);
+            for mode in Move::all_modes() {
+                let sample = samples
+                    .iter()
+                    .map(|move_instr| Instruction::Move(*move_instr).encode().unwrap())
+                    .find(|bytes| bytes[1] == mode.opcode)
+                    .unwrap_or_else(|| panic!("no sample for {}", mode.syntax));
+                let (_, len) = decode(&sample).expect("should decode");
+
+                assert_eq!(
+                    Instruction::length_from(&sample),
+                    Ok(len),
+                    "{}",
+                    mode.syntax
+                );
+                // the operands aren't needed
+                assert_eq!(
+                    Instruction::length_from(&sample[..2]),
+                    Ok(len),
+                    "{}",
+                    mode.syntax
+                );
+            }
         }
 
         #[test]
-        fn move_mem_to_mem16() {
-            let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::MemToMem16(0x0, 0x4));
+        fn every_instruction_matches_read() {
+            for instr in Instruction::variants_sample() {
+                let bytes = instr.encode().expect("should encode");
+                let (_, len) = decode(&bytes).expect("should decode");
 
-            machine.cpu.mem[0] = 0x01;
-            machine.cpu.mem[1] = 0x02;
-            machine.cpu.mem[2] = 0x03;
-            machine.cpu.mem[3] = 0x04;
+                assert_eq!(Instruction::length_from(&bytes), Ok(len), "{instr}");
+            }
+        }
+
+        #[test]
+        fn invalid_opcodes() {
+            assert_eq!(
+                Instruction::length_from(&[]),
+                Err(DecodeError::Truncated {
+                    needed: 1,
+                    available: 0
+                })
+            );
+            assert_eq!(
+                Instruction::length_from(&[0x1]),
+                Err(DecodeError::Truncated {
+                    needed: 1,
+                    available: 0
+                })
+            );
+            assert_eq!(
+                Instruction::length_from(&[0xFF]),
+                Err(DecodeError::InvalidGroup(0xFF))
+            );
+            assert_eq!(
+                Instruction::length_from(&[0x2, 0xC0]),
+                Err(DecodeError::InvalidAddOpcode(0xC0))
+            );
+            assert_eq!(
+                Instruction::length_from(&[0x1A, 0x01]),
+                Err(DecodeError::InvalidAluOpcode(0x01))
+            );
+        }
+    }
 
-            machine.cpu.do_instruction(instr);
+    mod cost {
+        use super::*;
+        use crate::Machine;
 
-            assert_eq!(machine.cpu.mem[4], 0x01);
-            assert_eq!(machine.cpu.mem[5], 0x02);
-            assert_eq!(machine.cpu.mem[6], 0x00);
-            assert_eq!(machine.cpu.mem[7], 0x00);
+        #[test]
+        fn memory_costs_more() {
+            let reg_to_reg = Instruction::Move(Move::RegToReg(Register::A, Register::B));
+            let mem_to_mem = Instruction::Move(Move::MemToMem32(0x100, 0x200));
+
+            assert_eq!(reg_to_reg.cost(), 1);
+            assert_eq!(mem_to_mem.cost(), 3);
+            // reads and then writes the dword
+            assert_eq!(Instruction::Xchg(Register::A, 0x100).cost(), 3);
+            assert_eq!(Instruction::PushAll.cost(), 6);
+            assert_eq!(Instruction::PopAll.cost(), 6);
+            assert!(Instruction::Add(Add::MemToReg(0x100, Register::A)).cost() > 1);
+            assert!(Instruction::variants_sample()
+                .iter()
+                .all(|instr| instr.cost() >= 1));
         }
 
         #[test]
-        fn move_mem_to_mem8() {
-            let mut machine = Machine::new();
-            let instr = Instruction::Move(Move::MemToMem8(0x0, 0x4));
+        fn one_cycle_per_memory_access() {
+            for instr in Instruction::variants_sample() {
+                let mut cpu = crate::cpu::Cpu::new();
+                cpu.registers.a = 0x100;
+                cpu.registers.b = 0x100;
+                cpu.registers.x = 0x100;
+                cpu.registers.y = 0x100;
+                cpu.registers.stack_pointer = 0x1000;
+                cpu.enable_access_log();
 
-            machine.cpu.mem[0] = 0x01;
-            machine.cpu.mem[1] = 0x02;
-            machine.cpu.mem[2] = 0x03;
-            machine.cpu.mem[3] = 0x04;
+                cpu.do_instruction(instr).expect("should execute");
+
+                let extra = match instr {
+                    Instruction::Mul(..) => 2,
+                    Instruction::Div(..) => 3,
+                    _ => 0,
+                };
+                let accesses = cpu.take_access_log().len() as u32;
+                assert_eq!(instr.cost(), 1 + accesses + extra, "{instr}");
+            }
+        }
 
-            machine.cpu.do_instruction(instr);
+        #[test]
+        fn cycles_accumulate() {
+            let mut machine = Machine::from_instructions(&[
+                Instruction::Move(Move::RegToReg(Register::A, Register::B)),
+                Instruction::Move(Move::MemToMem32(0x100, 0x200)),
+            ]);
 
-            assert_eq!(machine.cpu.mem[4], 0x01);
-            assert_eq!(machine.cpu.mem[5], 0x00);
-            assert_eq!(machine.cpu.mem[6], 0x00);
-            assert_eq!(machine.cpu.mem[7], 0x00);
+            machine.run_cycle().expect("should cycle");
+            assert_eq!(machine.cpu.cycles(), 1);
+
+            machine.run_cycle().expect("should cycle");
+            assert_eq!(machine.cpu.cycles(), 4);
+
+            // the implicit HALT
+            machine.cpu.cycle().expect("should cycle");
+            assert_eq!(machine.cpu.cycles(), 5);
+            assert_eq!(machine.cpu.instructions_retired(), 3);
+        }
+    }
+
+    mod mnemonics {
+        use alloc::collections::BTreeSet;
+
+        use super::*;
+
+        fn one_of_each() -> Vec<Instruction> {
+            vec![
+                Instruction::Halt,
+                Instruction::Move(Move::RegToReg(Register::A, Register::B)),
+                Instruction::Lea(
+                    Register::A,
+                    AddrExpr {
+                        base: Register::X,
+                        displacement: 4,
+                    },
+                ),
+                Instruction::Add(Add::ImmToReg(1, Register::A)),
+                Instruction::Sub(Sub::MemToReg(0x10, Register::X)),
+                Instruction::Jump(0x10),
+                Instruction::JumpIfZero(0x10),
+                Instruction::JumpIfNotZero(0x10),
+                Instruction::JumpRel(-4),
+                Instruction::JumpIfLess(0x10),
+                Instruction::JumpIfGreaterOrEqual(0x10),
+                Instruction::JumpIfGreater(0x10),
+                Instruction::JumpIfLessOrEqual(0x10),
+                Instruction::BranchIfEqual(Register::A, Register::B, 0x10),
+                Instruction::BranchIfNotEqual(Register::A, Register::B, 0x10),
+                Instruction::Push(Register::A),
+                Instruction::Pop(Register::B),
+                Instruction::Call(0x10),
+                Instruction::Ret,
+                Instruction::Neg(Register::Y),
+                Instruction::Logic(Logic::And(Register::A, Register::B)),
+                Instruction::Logic(Logic::Or(Register::A, Register::B)),
+                Instruction::Logic(Logic::Xor(Register::A, Register::B)),
+                Instruction::Logic(Logic::Not(Register::A)),
+                Instruction::Shift(ShiftOp::Shl(Register::A, 1)),
+                Instruction::Shift(ShiftOp::Shr(Register::A, 1)),
+                Instruction::Shift(ShiftOp::Sar(Register::A, 1)),
+                Instruction::Shift(ShiftOp::Rol(Register::A, 1)),
+                Instruction::Shift(ShiftOp::Ror(Register::A, 1)),
+                Instruction::Cmp(Register::A, Register::B),
+                Instruction::Inc(Register::A),
+                Instruction::Dec(Register::A),
+                Instruction::Nop,
+                Instruction::Swap(Register::A, Register::B),
+                Instruction::Xchg(Register::A, 0x10),
+                Instruction::Mul(Register::A, Register::B),
+                Instruction::Div(Register::A, Register::B),
+                Instruction::Iret,
+                Instruction::PushAll,
+                Instruction::PopAll,
+                Instruction::Cli,
+                Instruction::Sti,
+            ]
+        }
+
+        #[test]
+        fn all_mnemonics() {
+            let mnemonics = Instruction::all_mnemonics();
+            assert!(mnemonics.contains(&"MOV"));
+            assert!(mnemonics.contains(&"HALT"));
+
+            let covered = one_of_each()
+                .iter()
+                .map(|instr| instr.mnemonic())
+                .collect::<BTreeSet<_>>();
+            assert_eq!(covered, mnemonics.iter().copied().collect());
+        }
+
+        #[test]
+        fn mnemonics_assemble() {
+            for instr in one_of_each() {
+                let text = instr.to_string();
+                assert!(text.starts_with(instr.mnemonic()));
+
+                let bytes = crate::asm::assemble(&text).expect("should assemble");
+                assert_eq!(
+                    Instruction::read(crate::cpu::MemIterator::new(0, bytes.as_slice()))
+                        .expect("should read")
+                        .instr,
+                    // the assembler always picks the ALU encoding for the old groups
+                    instr.as_alu().map(Instruction::Alu).unwrap_or(instr)
+                );
+            }
+        }
+
+        #[test]
+        fn all_move_modes() {
+            let modes = Move::all_modes();
+
+            let opcodes = modes
+                .iter()
+                .map(|mode| mode.opcode)
+                .collect::<BTreeSet<_>>();
+            assert_eq!(opcodes.len(), modes.len());
+
+            for mode in modes {
+                let parsed = Move::read(crate::cpu::MemIterator::new(
+                    0,
+                    [mode.opcode, 0, 0, 0, 0, 0, 0, 0, 0].as_slice(),
+                ))
+                .expect("should read");
+
+                assert_eq!(
+                    parsed.instr.encode().expect("should encode")[1],
+                    mode.opcode
+                );
+                assert!(mode.syntax.starts_with("MOV "));
+            }
         }
     }
 }
@@ -1,84 +1,734 @@
 // Copyright (C) 2023  Patrick Cleavelin <patrick@spacegirl.nl>
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeSet,
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::cell::RefCell;
+
 use instr::ReadMem;
 
+pub mod asm;
 pub mod bitflag;
 pub mod cpu;
+pub mod device;
+pub mod exe;
+#[cfg(feature = "gdbstub")]
+pub mod gdbstub;
 pub mod instr;
+pub mod mem;
+mod state;
 
 pub struct Snapshot<'machine> {
     pub next_instr: Option<instr::Instruction>,
     pub registers: cpu::CpuRegisters,
-    pub mem_block: &'machine [u8],
+    pub cycles: u64,
+    pub instructions_retired: u64,
+    pub mem_block: &'machine mem::Memory,
+    /// The part of `mem_block` this snapshot is about, all of memory unless
+    /// it came from `Machine::snapshot_window`
+    pub mem_start: u32,
+    pub mem_len: usize,
+}
+
+/// A `Snapshot` that owns a copy of a window of memory instead of borrowing
+/// the whole thing, so it can outlive the machine or be sent elsewhere.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedSnapshot {
+    pub next_instr: Option<instr::Instruction>,
+    pub registers: cpu::CpuRegisters,
+    pub cycles: u64,
+    pub instructions_retired: u64,
+    pub mem_start: u32,
+    pub mem_block: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RegisterDiff {
+    pub register: cpu::Register,
+    pub old: u32,
+    pub new: u32,
+}
+
+/// What changed between an earlier `OwnedSnapshot` and a later `Snapshot`
+#[derive(Debug, Default, PartialEq)]
+pub struct SnapshotDiff {
+    pub registers: Vec<RegisterDiff>,
+    /// Old and new flags, if they changed
+    pub flags: Option<(u8, u8)>,
+    /// Start address and length of each run of changed bytes, only covering
+    /// the window of memory `prev` was taken with
+    pub mem: Vec<(u32, usize)>,
 }
 
 impl<'machine> Snapshot<'machine> {
+    /// The bytes inside of the window
+    pub fn window(&self) -> impl Iterator<Item = u8> + '_ {
+        let start = self.mem_start as usize;
+
+        (start..start + self.mem_len).map(|addr| self.mem_block[addr])
+    }
+
+    /// Copies `len` bytes of memory starting at `mem_start` into an
+    /// `OwnedSnapshot`, wrapping around the end of memory.
+    pub fn to_owned_window(&self, mem_start: u32, len: usize) -> OwnedSnapshot {
+        let mem_block = (0..len)
+            .map(|i| self.mem_block[(mem_start as usize + i) % self.mem_block.size()])
+            .collect();
+
+        OwnedSnapshot {
+            next_instr: self.next_instr,
+            registers: self.registers,
+            cycles: self.cycles,
+            instructions_retired: self.instructions_retired,
+            mem_start,
+            mem_block,
+        }
+    }
+
+    /// Compares against an earlier snapshot of the same machine. Memory is
+    /// only compared inside the window `prev` owns, since diffing all of it
+    /// would be far too slow.
+    pub fn diff(&self, prev: &OwnedSnapshot) -> SnapshotDiff {
+        let (old, new) = (&prev.registers, &self.registers);
+
+        let registers = [
+            (
+                cpu::Register::Ip,
+                old.instruction_pointer,
+                new.instruction_pointer,
+            ),
+            (cpu::Register::Sp, old.stack_pointer, new.stack_pointer),
+            (cpu::Register::A, old.a, new.a),
+            (cpu::Register::B, old.b, new.b),
+            (cpu::Register::X, old.x, new.x),
+            (cpu::Register::Y, old.y, new.y),
+        ]
+        .into_iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(register, old, new)| RegisterDiff { register, old, new })
+        .collect();
+
+        let flags = (old.flags.value() != new.flags.value())
+            .then(|| (old.flags.value(), new.flags.value()));
+
+        let mut mem: Vec<(u32, usize)> = Vec::new();
+        let mut run_continues = false;
+        for (i, old_byte) in prev.mem_block.iter().enumerate() {
+            let addr = ((prev.mem_start as usize + i) % self.mem_block.size()) as u32;
+
+            if self.mem_block[addr as usize] == *old_byte {
+                run_continues = false;
+                continue;
+            }
+
+            match mem.last_mut() {
+                Some((_, len)) if run_continues => *len += 1,
+                _ => mem.push((addr, 1)),
+            }
+            run_continues = true;
+        }
+
+        SnapshotDiff {
+            registers,
+            flags,
+            mem,
+        }
+    }
+
     pub fn pretty(&self) -> String {
-        let registers = format!(
+        let next_instrs = disassemble(
+            self.mem_block,
+            self.registers.instruction_pointer,
+            PRETTY_INSTRUCTIONS,
+        )
+        .into_iter()
+        .map(|(addr, instr)| match instr {
+            Ok(instr) => format!("0x{addr:08x}  {instr}"),
+            Err(_) => format!("0x{addr:08x}  <invalid instruction>"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+        let mut registers = format!(
             r#"
 ----- Registers -----
 |  IP:  0x{:08x}  |
+|  SP:  0x{:08x}  |
 |                   |
 |  A:   0x{:08x}  |
 |  B:   0x{:08x}  |
 |                   |
 |  X:   0x{:08x}  |
 |  Y:   0x{:08x}  |
+|                   |
+|  Z: {}       C: {}  |
+|  O: {}       S: {}  |
 ----- Registers -----
 
------ Next Instruction -----
-{:#?}
-----------------------------
+----- Next Instructions -----
+{}
+-----------------------------
 "#,
             self.registers.instruction_pointer,
+            self.registers.stack_pointer,
             self.registers.a,
             self.registers.b,
             self.registers.x,
             self.registers.y,
-            self.next_instr
+            u8::from(self.registers.zero()),
+            u8::from(self.registers.carry()),
+            u8::from(self.registers.overflow()),
+            u8::from(self.registers.sign()),
+            next_instrs,
         );
+
+        // dumping all of memory would be far too much
+        if self.mem_len < self.mem_block.size() {
+            registers += &format!(
+                "\n----- Memory -----\n{}------------------\n",
+                self.mem_block.hexdump(self.mem_start, self.mem_len)
+            );
+        }
+
         registers
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum StopReason {
+    BreakpointHit(u32),
+    WatchpointHit(cpu::WatchpointHit),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RunResult {
+    Halted,
+    CycleLimitReached,
+    Stopped(StopReason),
+    DecodeError(instr::DecodeError),
+    ExecutionError(String),
+}
+
+/// What happened during a single `Machine::step`
+#[derive(Debug, PartialEq)]
+pub struct StepResult {
+    /// The instruction that was decoded, `None` if the CPU is halted or it
+    /// failed to decode
+    pub instr: Option<instr::Instruction>,
+    pub ip_before: u32,
+    pub ip_after: u32,
+    pub error: Option<cpu::CycleError>,
+}
+
+type FaultHandler = Rc<RefCell<dyn FnMut(&instr::DecodeError)>>;
+
+/// Clones share the fault handler
+#[derive(Clone)]
 pub struct Machine {
     pub cpu: cpu::Cpu,
+    breakpoints: BTreeSet<u32>,
+    fault_handler: Option<FaultHandler>,
+    coverage: Option<BTreeSet<u32>>,
+    snapshot_window: (u32, usize),
 }
 
+/// The memory `run_collecting` copies unless told otherwise, the first 256
+/// bytes
+const DEFAULT_SNAPSHOT_WINDOW: (u32, usize) = (0, 0x100);
+
 #[allow(clippy::new_without_default)]
 impl Machine {
     pub fn new() -> Self {
+        Self::with_mem_size(cpu::DEFAULT_MEM_SIZE)
+    }
+
+    pub fn with_mem_size(size: usize) -> Self {
         Self {
-            cpu: cpu::Cpu::new(),
+            cpu: cpu::Cpu::with_mem_size(size),
+            breakpoints: BTreeSet::new(),
+            fault_handler: None,
+            coverage: None,
+            snapshot_window: DEFAULT_SNAPSHOT_WINDOW,
+        }
+    }
+
+    /// A new machine with `instrs` encoded back to back starting at 0
+    ///
+    /// Panics if an instruction can't be encoded or the program doesn't fit
+    /// in memory.
+    pub fn from_instructions(instrs: &[instr::Instruction]) -> Self {
+        let mut program = Vec::new();
+        for instr in instrs {
+            program.extend(
+                instr
+                    .encode()
+                    .unwrap_or_else(|e| panic!("Failed to encode {instr}: {e}")),
+            );
+        }
+
+        let mut machine = Self::new();
+        if let Err(e) = machine.load_program(0, &program) {
+            panic!("{e}");
         }
+
+        machine
     }
 
-    pub fn run_cycle(&mut self) {
-        self.cpu.cycle();
+    pub fn add_breakpoint(&mut self, addr: u32) {
+        self.breakpoints.insert(addr);
     }
 
-    pub fn snapshot(&self) -> Snapshot {
-        let parsed_instr = match instr::Instruction::read(cpu::MemIterator::new(
-            self.cpu.registers.instruction_pointer as usize,
-            self.cpu.mem.as_slice(),
-        )) {
-            Ok(v) => Some(v),
-            Err(e) => {
-                eprintln!("{e}");
-                None
+    /// Returns whether there was a breakpoint at `addr`
+    pub fn remove_breakpoint(&mut self, addr: u32) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn add_watchpoint(&mut self, addr: u32) {
+        self.cpu.add_watchpoint(addr);
+    }
+
+    /// Returns whether there was a watchpoint at `addr`
+    pub fn remove_watchpoint(&mut self, addr: u32) -> bool {
+        self.cpu.remove_watchpoint(addr)
+    }
+
+    /// See `Cpu::set_readonly`
+    pub fn set_readonly(&mut self, range: core::ops::Range<u32>) {
+        self.cpu.set_readonly(range);
+    }
+
+    /// See `Cpu::set_stack_bounds`
+    pub fn set_stack_bounds(&mut self, bounds: Option<core::ops::Range<u32>>) {
+        self.cpu.set_stack_bounds(bounds);
+    }
+
+    pub fn add_device(
+        &mut self,
+        range: core::ops::Range<u32>,
+        device: Box<dyn device::Device>,
+    ) -> Result<(), String> {
+        self.cpu.add_device(range, device)
+    }
+
+    /// Maps a `ConsoleDevice` at `device::CONSOLE_PORT`
+    pub fn install_console(&mut self) -> Result<(), String> {
+        self.add_device(
+            device::CONSOLE_PORT..device::CONSOLE_PORT + 1,
+            Box::new(device::ConsoleDevice::new()),
+        )
+    }
+
+    pub fn console(&self) -> Option<&device::ConsoleDevice> {
+        self.cpu.device(device::CONSOLE_PORT)
+    }
+
+    /// Maps a `TimerDevice` at `device::TIMER_PORT`
+    pub fn install_timer(&mut self) -> Result<(), String> {
+        self.add_device(
+            device::TIMER_PORT..device::TIMER_PORT + device::TimerDevice::LEN,
+            Box::new(device::TimerDevice::new()),
+        )
+    }
+
+    pub fn timer(&self) -> Option<&device::TimerDevice> {
+        self.cpu.device(device::TIMER_PORT)
+    }
+
+    /// Maps an `RngDevice` seeded with `seed` at `device::RNG_PORT`
+    pub fn install_rng(&mut self, seed: u32) -> Result<(), String> {
+        self.add_device(
+            device::RNG_PORT..device::RNG_PORT + device::RngDevice::LEN,
+            Box::new(device::RngDevice::new(seed)),
+        )
+    }
+
+    pub fn rng(&self) -> Option<&device::RngDevice> {
+        self.cpu.device(device::RNG_PORT)
+    }
+
+    /// Copies `bytes` into memory starting at `offset`
+    pub fn load_program(&mut self, offset: u32, bytes: &[u8]) -> Result<(), String> {
+        let start = offset as usize;
+        let end = start.saturating_add(bytes.len());
+
+        if end > self.cpu.mem.size() {
+            return Err(format!(
+                "Program of {} byte(s) at 0x{offset:08x} doesn't fit in memory",
+                bytes.len()
+            ));
+        }
+
+        for (i, byte) in bytes.iter().enumerate() {
+            self.cpu.mem[start + i] = *byte;
+        }
+
+        Ok(())
+    }
+
+    /// Assembles `src` and loads it with `load_program`. Labels are resolved
+    /// as if the program starts at 0, no matter what `offset` is.
+    pub fn load_asm(&mut self, src: &str, offset: u32) -> Result<(), String> {
+        let program = asm::assemble(src).map_err(|e| e.to_string())?;
+
+        self.load_program(offset, &program)
+    }
+
+    /// Loads a flat binary off disk with `load_program`, returning how many
+    /// bytes were loaded
+    #[cfg(feature = "std")]
+    pub fn load_file(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        offset: u32,
+    ) -> std::io::Result<usize> {
+        let bytes = std::fs::read(path)?;
+
+        self.load_program(offset, &bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+        Ok(bytes.len())
+    }
+
+    /// Loads a `nemu` executable image, see `exe`, and points IP at its
+    /// entry point
+    pub fn load_executable(&mut self, bytes: &[u8]) -> Result<(), exe::LoadError> {
+        let exe = exe::Executable::parse(bytes)?;
+
+        let mem_size = self.cpu.mem.size();
+        if exe.code.len() > mem_size {
+            return Err(exe::LoadError::TooLarge {
+                len: exe.code.len(),
+                mem_size,
+            });
+        }
+        if exe.entry as usize >= mem_size {
+            return Err(exe::LoadError::EntryOutOfBounds {
+                entry: exe.entry,
+                mem_size,
+            });
+        }
+
+        for (i, byte) in exe.code.iter().enumerate() {
+            self.cpu.mem[i] = *byte;
+        }
+        self.cpu.registers.instruction_pointer = exe.entry;
+
+        Ok(())
+    }
+
+    /// Serializes the CPU registers, counters and memory, untouched memory
+    /// takes up a single byte per page and the rest is run length encoded.
+    /// Breakpoints, watchpoints and devices aren't saved.
+    pub fn save_state(&self) -> Vec<u8> {
+        state::save(&self.cpu)
+    }
+
+    /// Restores a state from `save_state`, leaving the machine untouched if
+    /// `data` isn't valid
+    pub fn restore_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let (registers, halted, cycles, instructions_retired, mem) = state::restore(data)?;
+
+        self.cpu.mem = mem;
+        self.cpu.reset(false);
+        self.cpu.registers = registers;
+        self.cpu.halted = halted;
+        self.cpu.cycles = cycles;
+        self.cpu.instructions_retired = instructions_retired;
+
+        Ok(())
+    }
+
+    /// See `Cpu::interrupt`
+    pub fn raise_interrupt(&mut self, vector: u8) -> Result<bool, String> {
+        self.cpu.interrupt(vector)
+    }
+
+    /// Calls `f` with every decode error, before it's returned from
+    /// `run_cycle`, `step` or one of the `run_` methods. Replaces any
+    /// previous handler.
+    pub fn set_fault_handler(&mut self, f: impl FnMut(&instr::DecodeError) + 'static) {
+        self.fault_handler = Some(Rc::new(RefCell::new(f)));
+    }
+
+    pub fn clear_fault_handler(&mut self) {
+        self.fault_handler = None;
+    }
+
+    fn report_fault(&self, e: &instr::DecodeError) {
+        if let Some(handler) = &self.fault_handler {
+            (handler.borrow_mut())(e);
+        }
+    }
+
+    /// Starts recording the address of every instruction that gets fetched,
+    /// clearing anything recorded before
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(BTreeSet::new());
+    }
+
+    pub fn disable_coverage(&mut self) {
+        self.coverage = None;
+    }
+
+    /// Addresses of the instructions fetched since coverage was enabled, in
+    /// ascending order
+    pub fn coverage(&self) -> impl Iterator<Item = u32> + '_ {
+        self.coverage.iter().flatten().copied()
+    }
+
+    // `Cpu::fetch` plus everything the machine tracks about it
+    fn fetch(&mut self) -> Result<Option<instr::Instruction>, instr::DecodeError> {
+        let ip = self.cpu.registers.instruction_pointer;
+
+        let fetched = self.cpu.fetch();
+        match &fetched {
+            Ok(Some(_)) => {
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.insert(ip);
+                }
             }
+            Ok(None) => {}
+            Err(e) => self.report_fault(e),
+        }
+
+        fetched
+    }
+
+    pub fn run_cycle(&mut self) -> Result<(), cpu::CycleError> {
+        let cycles = self.cpu.cycles();
+
+        if let Some(instr) = self.fetch().map_err(cpu::CycleError::Decode)? {
+            self.cpu
+                .do_instruction(instr)
+                .map_err(cpu::CycleError::Execute)?;
+        }
+
+        self.cpu
+            .tick_devices(self.cpu.cycles() - cycles)
+            .map_err(cpu::CycleError::Execute)
+    }
+
+    /// Runs a single cycle like `run_cycle`, but also reports what was run
+    pub fn step(&mut self) -> StepResult {
+        let ip_before = self.cpu.registers.instruction_pointer;
+        let cycles = self.cpu.cycles();
+
+        let (instr, mut error) = match self.fetch() {
+            Ok(Some(instr)) => (
+                Some(instr),
+                self.cpu
+                    .do_instruction(instr)
+                    .err()
+                    .map(cpu::CycleError::Execute),
+            ),
+            Ok(None) => (None, None),
+            Err(e) => (None, Some(cpu::CycleError::Decode(e))),
         };
+        if error.is_none() {
+            error = self
+                .cpu
+                .tick_devices(self.cpu.cycles() - cycles)
+                .err()
+                .map(cpu::CycleError::Execute);
+        }
+
+        StepResult {
+            instr,
+            ip_before,
+            ip_after: self.cpu.registers.instruction_pointer,
+            error,
+        }
+    }
+
+    /// Keeps cycling the CPU until it executes a `Halt`, reaches a breakpoint,
+    /// writes to a watchpoint, or gives up once `max_cycles` have been spent,
+    /// counted like `Cpu::cycles`.
+    ///
+    /// Breakpoints are checked before the instruction is fetched, the one the
+    /// run starts on is skipped so that running again continues past it.
+    pub fn run_until_halt(&mut self, max_cycles: usize) -> RunResult {
+        let start = self.cpu.cycles();
+
+        while self.cpu.cycles() - start < max_cycles as u64 {
+            let ip = self.cpu.registers.instruction_pointer;
+            if self.cpu.cycles() > start && self.breakpoints.contains(&ip) {
+                return RunResult::Stopped(StopReason::BreakpointHit(ip));
+            }
+
+            match self.run_cycle() {
+                Ok(()) => {}
+                Err(cpu::CycleError::Decode(e)) => return RunResult::DecodeError(e),
+                Err(cpu::CycleError::Execute(e)) => return RunResult::ExecutionError(e),
+            }
+
+            // the write has already happened, so this stops *after* the
+            // instruction that did it
+            if let Some(hit) = self.cpu.take_watchpoint_hit() {
+                return RunResult::Stopped(StopReason::WatchpointHit(hit));
+            }
+
+            if self.cpu.is_halted() {
+                return RunResult::Halted;
+            }
+        }
+
+        RunResult::CycleLimitReached
+    }
+
+    /// Spends at most `max_cycles` cycles no matter what, so a program that
+    /// never halts can't hang whoever is running it. The last instruction
+    /// can go over if it costs more than what's left. Breakpoints and
+    /// watchpoints are ignored.
+    pub fn run_bounded(&mut self, max_cycles: usize) -> RunResult {
+        let start = self.cpu.cycles();
+
+        while self.cpu.cycles() - start < max_cycles as u64 {
+            match self.run_cycle() {
+                Ok(()) => {}
+                Err(cpu::CycleError::Decode(e)) => return RunResult::DecodeError(e),
+                Err(cpu::CycleError::Execute(e)) => return RunResult::ExecutionError(e),
+            }
+            self.cpu.take_watchpoint_hit();
+
+            if self.cpu.is_halted() {
+                return RunResult::Halted;
+            }
+        }
+
+        RunResult::CycleLimitReached
+    }
+
+    /// Sets the `len` bytes of memory starting at `start` that
+    /// `run_collecting` copies into each snapshot
+    pub fn set_snapshot_window(&mut self, start: u32, len: usize) {
+        self.snapshot_window = (start, len);
+    }
+
+    /// Runs up to `steps` cycles, taking an `OwnedSnapshot` of the snapshot
+    /// window after each one. Stops early once the CPU halts or a cycle
+    /// fails, that cycle still gets its snapshot. Breakpoints and watchpoints
+    /// are ignored.
+    pub fn run_collecting(&mut self, steps: usize) -> Vec<OwnedSnapshot> {
+        let (mem_start, len) = self.snapshot_window;
+        let mut snapshots = Vec::with_capacity(steps);
+
+        for _ in 0..steps {
+            let result = self.run_cycle();
+            self.cpu.take_watchpoint_hit();
+
+            snapshots.push(self.snapshot().to_owned_window(mem_start, len));
+
+            if result.is_err() || self.cpu.is_halted() {
+                break;
+            }
+        }
+
+        snapshots
+    }
+
+    /// Like `run_until_halt` but hands `f` a snapshot of the machine before
+    /// every instruction. Breakpoints and watchpoints are ignored.
+    pub fn run_traced(&mut self, max_cycles: usize, mut f: impl FnMut(&Snapshot)) -> RunResult {
+        let start = self.cpu.cycles();
+
+        while self.cpu.cycles() - start < max_cycles as u64 {
+            f(&self.snapshot());
+
+            match self.run_cycle() {
+                Ok(()) => {}
+                Err(cpu::CycleError::Decode(e)) => return RunResult::DecodeError(e),
+                Err(cpu::CycleError::Execute(e)) => return RunResult::ExecutionError(e),
+            }
+            self.cpu.take_watchpoint_hit();
+
+            if self.cpu.is_halted() {
+                return RunResult::Halted;
+            }
+        }
+
+        RunResult::CycleLimitReached
+    }
+
+    /// Decodes up to `count` instructions starting at `start`, stopping after
+    /// the first one that fails to decode.
+    pub fn disassemble(
+        &self,
+        start: u32,
+        count: usize,
+    ) -> Vec<(u32, Result<instr::Instruction, instr::DecodeError>)> {
+        disassemble(&self.cpu.mem, start, count)
+    }
+
+    /// `next_instr` is `None` if the instruction at IP doesn't decode, use
+    /// `disassemble` to find out why
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot_window(0, self.cpu.mem.size())
+    }
+
+    /// A snapshot that's only about the `len` bytes starting at `start`,
+    /// clamped to the end of memory
+    pub fn snapshot_window(&self, start: u32, len: usize) -> Snapshot {
+        let mem_start = (start as usize).min(self.cpu.mem.size());
+        let mem_len = len.min(self.cpu.mem.size() - mem_start);
+
+        let parsed_instr = instr::Instruction::read(cpu::MemIterator::new(
+            self.cpu.registers.instruction_pointer as usize,
+            &self.cpu.mem,
+        ));
 
         Snapshot {
-            next_instr: parsed_instr.map(|parsed| parsed.instr),
+            next_instr: parsed_instr.ok().map(|parsed| parsed.instr),
             registers: self.cpu.registers,
-            mem_block: self.cpu.mem.as_slice(),
+            cycles: self.cpu.cycles(),
+            instructions_retired: self.cpu.instructions_retired(),
+            mem_block: &self.cpu.mem,
+            mem_start: mem_start as u32,
+            mem_len,
+        }
+    }
+}
+
+/// How many instructions `Snapshot::pretty` disassembles from IP onwards
+const PRETTY_INSTRUCTIONS: usize = 5;
+
+fn disassemble(
+    mem: &mem::Memory,
+    start: u32,
+    count: usize,
+) -> Vec<(u32, Result<instr::Instruction, instr::DecodeError>)> {
+    let mut instrs = Vec::with_capacity(count);
+    let mut addr = start;
+
+    for _ in 0..count {
+        match instr::Instruction::read(cpu::MemIterator::new(addr as usize, mem)) {
+            Ok(parsed) => {
+                instrs.push((addr, Ok(parsed.instr)));
+
+                addr = ((addr as usize + parsed.delta_ip as usize) % mem.size()) as u32;
+            }
+            Err(e) => {
+                instrs.push((addr, Err(e)));
+                break;
+            }
         }
     }
+
+    instrs
 }
 
 #[cfg(test)]
 mod tests {
+    use alloc::vec;
+
     use super::*;
 
     #[test]
@@ -86,25 +736,964 @@ mod tests {
         let mut machine = Machine::new();
 
         machine.cpu.registers.instruction_pointer = 0xFFFF_FFFF;
-        machine.run_cycle();
+        machine.run_cycle().expect("should cycle");
 
         assert_eq!(machine.cpu.registers.instruction_pointer, 0);
     }
 
-    // #[test]
-    // fn idk() {
-    //     let mut machine = Machine::new();
+    #[test]
+    fn load_program() {
+        let mut machine = Machine::new();
 
-    //     // machine.run_cycle();
-    //     machine.cpu.registers.a = 0xFFF1_1FFF;
+        machine
+            .load_program(0x10, &[0x01, 0x02, 0x03, 0x04])
+            .expect("should load");
 
-    //     machine.cpu.mem[0] = 0x1;
-    //     machine.cpu.mem[1] = 0x8;
-    //     machine.cpu.mem[2] = 0x0;
-    //     machine.cpu.mem[3] = 0x2;
+        assert_eq!(machine.cpu.mem[0x0F], 0x00);
+        assert_eq!(machine.cpu.mem[0x10], 0x01);
+        assert_eq!(machine.cpu.mem[0x11], 0x02);
+        assert_eq!(machine.cpu.mem[0x12], 0x03);
+        assert_eq!(machine.cpu.mem[0x13], 0x04);
+        assert_eq!(machine.cpu.mem[0x14], 0x00);
+    }
 
-    //     let pretty = machine.snapshot().pretty();
+    #[test]
+    fn load_program_past_end_of_mem() {
+        let mut machine = Machine::new();
+        let end = machine.cpu.mem.size() as u32;
 
-    //     panic!("{pretty}");
-    // }
+        assert!(machine.load_program(end - 2, &[0x01, 0x02]).is_ok());
+        assert!(machine.load_program(end - 2, &[0x01, 0x02, 0x03]).is_err());
+        assert!(machine.load_program(0xFFFF_FFFF, &[0x01]).is_err());
+    }
+
+    #[test]
+    fn disassemble() {
+        let mut machine = Machine::new();
+        let program = asm::assemble(
+            r#"
+            MOV A, 0x42
+            PUSH A
+            HALT
+            "#,
+        )
+        .expect("should assemble");
+
+        machine.load_program(0x10, &program).expect("should load");
+        machine.cpu.mem[0x1A] = 0xFF;
+
+        let instrs = machine.disassemble(0x10, 5);
+        let addrs = instrs.iter().map(|(addr, _)| *addr).collect::<Vec<_>>();
+
+        assert_eq!(addrs, vec![0x10, 0x17, 0x19, 0x1A]);
+        assert_eq!(
+            instrs[0].1,
+            Ok(instr::Instruction::Move(instr::Move::ImmToReg32(
+                0x42,
+                cpu::Register::A
+            )))
+        );
+        assert_eq!(instrs[1].1, Ok(instr::Instruction::Push(cpu::Register::A)));
+        assert_eq!(instrs[2].1, Ok(instr::Instruction::Halt));
+        assert!(instrs[3].1.is_err());
+    }
+
+    #[test]
+    fn disassemble_wraps_around() {
+        let mut machine = Machine::new();
+        let end = machine.cpu.mem.size() as u32;
+
+        machine
+            .load_program(end - 2, &[0x7, 0x0])
+            .expect("should load");
+
+        let instrs = machine.disassemble(end - 2, 2);
+
+        assert_eq!(
+            instrs,
+            vec![
+                (end - 2, Ok(instr::Instruction::Push(cpu::Register::A))),
+                (0, Ok(instr::Instruction::Halt)),
+            ]
+        );
+    }
+
+    #[test]
+    fn owned_snapshot_window() {
+        let mut machine = Machine::new();
+        let end = machine.cpu.mem.size() as u32;
+
+        machine
+            .load_program(end - 2, &[0x01, 0x02])
+            .expect("should load");
+        machine.cpu.mem[0] = 0x03;
+
+        let owned = machine.snapshot().to_owned_window(end - 2, 4);
+
+        assert_eq!(owned.mem_start, end - 2);
+        assert_eq!(owned.mem_block, vec![0x01, 0x02, 0x03, 0x00]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn owned_snapshot_serde_round_trip() {
+        let mut machine = Machine::new();
+        machine.cpu.registers.instruction_pointer = 0x10;
+        machine.cpu.registers.a = 0xDEAD_BEEF;
+        machine.cpu.registers.y = 42;
+        machine.cpu.registers.flags |= cpu::ZERO;
+        machine
+            .load_program(0x10, &[0x7, 0x0])
+            .expect("should load");
+
+        let owned = machine.snapshot().to_owned_window(0x10, 2);
+        let json = serde_json::to_string(&owned).expect("should serialize");
+        let parsed: OwnedSnapshot = serde_json::from_str(&json).expect("should deserialize");
+
+        assert_eq!(
+            parsed.registers.instruction_pointer,
+            owned.registers.instruction_pointer
+        );
+        assert_eq!(
+            parsed.registers.stack_pointer,
+            owned.registers.stack_pointer
+        );
+        assert_eq!(parsed.registers.a, owned.registers.a);
+        assert_eq!(parsed.registers.b, owned.registers.b);
+        assert_eq!(parsed.registers.x, owned.registers.x);
+        assert_eq!(parsed.registers.y, owned.registers.y);
+        assert_eq!(
+            parsed.registers.flags.value(),
+            owned.registers.flags.value()
+        );
+        assert_eq!(
+            parsed.next_instr,
+            Some(instr::Instruction::Push(cpu::Register::A))
+        );
+        assert_eq!(parsed.mem_block, vec![0x7, 0x0]);
+    }
+
+    #[test]
+    fn halt_stops_cycling() {
+        let mut machine = Machine::new();
+
+        machine.run_cycle().expect("should cycle");
+        assert!(machine.cpu.is_halted());
+        assert_eq!(machine.cpu.registers.instruction_pointer, 1);
+
+        machine.run_cycle().expect("should cycle");
+        assert_eq!(machine.cpu.registers.instruction_pointer, 1);
+
+        machine.cpu.resume();
+        assert!(!machine.cpu.is_halted());
+
+        machine.run_cycle().expect("should cycle");
+        assert_eq!(machine.cpu.registers.instruction_pointer, 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn load_file() {
+        let path = std::env::temp_dir().join(format!("nemu_load_file_{}.bin", std::process::id()));
+        let program = instr::Instruction::Push(cpu::Register::B)
+            .encode()
+            .expect("should encode");
+        std::fs::write(&path, &program).expect("should write");
+
+        let mut machine = Machine::new();
+        let loaded = machine.load_file(&path, 0);
+        std::fs::remove_file(&path).expect("should remove");
+
+        assert_eq!(loaded.expect("should load"), program.len());
+        assert_eq!(
+            instr::Instruction::read(cpu::MemIterator::new(0, &machine.cpu.mem))
+                .expect("should decode")
+                .instr,
+            instr::Instruction::Push(cpu::Register::B)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn load_file_too_big() {
+        let path =
+            std::env::temp_dir().join(format!("nemu_load_file_too_big_{}.bin", std::process::id()));
+        std::fs::write(&path, [0x12; 0x20]).expect("should write");
+
+        let mut machine = Machine::with_mem_size(0x10);
+        let loaded = machine.load_file(&path, 0);
+        std::fs::remove_file(&path).expect("should remove");
+
+        assert_eq!(
+            loaded.expect_err("should not fit").kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn load_executable() {
+        let mut machine = Machine::new();
+        let image = exe::Executable {
+            entry: 0x2,
+            code: &[0x0, 0x0, 0x10, 0x0, 0x0],
+        }
+        .to_bytes();
+
+        machine.load_executable(&image).expect("should load");
+
+        assert_eq!(machine.cpu.registers.instruction_pointer, 0x2);
+        assert_eq!(machine.cpu.mem[0x2], 0x10);
+        assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+        assert_eq!(machine.cpu.registers.a, 1);
+    }
+
+    #[test]
+    fn load_executable_bad_magic() {
+        let mut machine = Machine::new();
+
+        assert_eq!(
+            machine.load_executable(b"ELF\x7f\0\0\0\0\0\0\0\0"),
+            Err(exe::LoadError::BadMagic(*b"ELF\x7f"))
+        );
+        assert_eq!(machine.cpu.registers.instruction_pointer, 0);
+    }
+
+    #[test]
+    fn load_executable_entry_out_of_bounds() {
+        let mut machine = Machine::with_mem_size(0x10);
+        let image = exe::Executable {
+            entry: 0x10,
+            code: &[0x12; 4],
+        }
+        .to_bytes();
+
+        assert_eq!(
+            machine.load_executable(&image),
+            Err(exe::LoadError::EntryOutOfBounds {
+                entry: 0x10,
+                mem_size: 0x10
+            })
+        );
+        assert_eq!(machine.cpu.registers.instruction_pointer, 0);
+        assert_eq!(machine.cpu.mem[0], 0);
+    }
+
+    #[test]
+    fn load_executable_too_large() {
+        let mut machine = Machine::with_mem_size(0x4);
+        let image = exe::Executable {
+            entry: 0,
+            code: &[0x12; 8],
+        }
+        .to_bytes();
+
+        assert_eq!(
+            machine.load_executable(&image),
+            Err(exe::LoadError::TooLarge {
+                len: 8,
+                mem_size: 4
+            })
+        );
+    }
+
+    #[test]
+    fn from_instructions() {
+        let mut machine = Machine::from_instructions(&[
+            instr::Instruction::Move(instr::Move::ImmToReg32(0x1234, cpu::Register::A)),
+            instr::Instruction::Inc(cpu::Register::A),
+        ]);
+
+        assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+        assert_eq!(machine.cpu.registers.a, 0x1235);
+        // MOV is 7 bytes, INC 2, then the implicit HALT
+        assert_eq!(machine.cpu.registers.instruction_pointer, 10);
+    }
+
+    #[test]
+    fn run_until_halt_halts() {
+        let mut machine = Machine::new();
+
+        assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+        assert_eq!(machine.cpu.registers.instruction_pointer, 1);
+    }
+
+    #[test]
+    fn run_until_halt_cycle_limit() {
+        let mut machine = Machine::new();
+
+        // JMP 0x0
+        machine.cpu.mem[0] = 0x4;
+
+        assert_eq!(machine.run_until_halt(10), RunResult::CycleLimitReached);
+        assert!(!machine.cpu.is_halted());
+    }
+
+    #[test]
+    fn run_until_halt_decode_error() {
+        let mut machine = Machine::new();
+
+        machine.cpu.mem[0] = 0xFF;
+
+        assert!(matches!(
+            machine.run_until_halt(10),
+            RunResult::DecodeError(_)
+        ));
+    }
+
+    #[test]
+    fn load_asm_runs() {
+        let mut machine = Machine::new();
+
+        machine
+            .load_asm("MOV A, 0x20\nADD A, 0x22\nHALT", 0)
+            .expect("should load");
+
+        assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+        assert_eq!(machine.cpu.registers.a, 0x42);
+    }
+
+    #[test]
+    fn load_asm_reports_line() {
+        let mut machine = Machine::new();
+
+        let e = machine
+            .load_asm("NOP\nBOGUS A", 0)
+            .expect_err("shouldn't assemble");
+        assert!(e.starts_with("line 2: "), "{e}");
+    }
+
+    #[test]
+    fn write_to_readonly_faults() {
+        let mut machine = Machine::new();
+        machine
+            .load_asm(
+                "MOV A, 0xDEADBEEF\nMOV [0x20], A\nMOV WORD [0xE], A\nHALT",
+                0x100,
+            )
+            .expect("should load");
+        machine.cpu.registers.instruction_pointer = 0x100;
+        machine.set_readonly(0..16);
+        let before: Vec<u8> = (0..16).map(|addr| machine.cpu.mem[addr]).collect();
+
+        match machine.run_until_halt(10) {
+            RunResult::ExecutionError(e) => assert!(e.starts_with("Write protect fault"), "{e}"),
+            result => panic!("expected a write protect fault, got {result:?}"),
+        }
+        assert!(machine.cpu.is_halted());
+        assert!(machine
+            .cpu
+            .execute_fault()
+            .expect("should have faulted")
+            .starts_with("Write protect fault"));
+
+        // the write just outside of the region went through
+        assert_eq!(machine.cpu.mem[0x20], 0xEF);
+        // neither byte of the straddling write did
+        assert_eq!(
+            (0..16)
+                .map(|addr| machine.cpu.mem[addr])
+                .collect::<Vec<_>>(),
+            before
+        );
+        assert_eq!(machine.cpu.mem[0x10], 0);
+
+        // reads still work
+        machine.cpu.mem[0x4] = 0x42;
+        machine
+            .load_asm("MOV A, BYTE [0x4]\nHALT", 0x100)
+            .expect("should load");
+        machine.cpu.registers.instruction_pointer = 0x100;
+        machine.cpu.resume();
+        assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+        assert_eq!(machine.cpu.registers.a & 0xFF, 0x42);
+    }
+
+    #[test]
+    fn run_bounded_stops_infinite_loop() {
+        let mut machine = Machine::from_instructions(&[instr::Instruction::JumpRel(-5)]);
+        // would stop a run_until_halt straight away
+        machine.add_breakpoint(0);
+
+        assert_eq!(machine.run_bounded(100), RunResult::CycleLimitReached);
+        assert_eq!(machine.cpu.instructions_retired(), 100);
+        assert!(!machine.cpu.is_halted());
+    }
+
+    #[test]
+    fn run_bounded_halts() {
+        let mut machine =
+            Machine::from_instructions(&[instr::Instruction::Nop, instr::Instruction::Halt]);
+
+        assert_eq!(machine.run_bounded(100), RunResult::Halted);
+        assert_eq!(machine.cpu.instructions_retired(), 2);
+    }
+
+    #[test]
+    fn run_bounded_counts_instruction_cost() {
+        let copy = instr::Instruction::Move(instr::Move::MemToMem32(0x100, 0x200));
+        let mut machine = Machine::from_instructions(&[copy, copy, copy, copy]);
+        let cost = copy.cost() as usize;
+        assert!(cost > 1);
+
+        assert_eq!(machine.run_bounded(cost * 2), RunResult::CycleLimitReached);
+        assert_eq!(machine.cpu.instructions_retired(), 2);
+        assert_eq!(machine.cpu.cycles(), cost as u64 * 2);
+    }
+
+    #[test]
+    fn run_collecting_snapshots_each_step() {
+        let mut machine = Machine::from_instructions(&[
+            instr::Instruction::Inc(cpu::Register::A),
+            instr::Instruction::Inc(cpu::Register::A),
+            instr::Instruction::Inc(cpu::Register::A),
+            instr::Instruction::Inc(cpu::Register::A),
+            instr::Instruction::Halt,
+        ]);
+        machine.set_snapshot_window(0, 4);
+
+        let snapshots = machine.run_collecting(4);
+
+        assert_eq!(snapshots.len(), 4);
+        let ips: Vec<_> = snapshots
+            .iter()
+            .map(|snapshot| snapshot.registers.instruction_pointer)
+            .collect();
+        assert_eq!(ips, vec![2, 4, 6, 8]);
+        for (i, snapshot) in snapshots.iter().enumerate() {
+            assert_eq!(snapshot.registers.a, i as u32 + 1);
+            assert_eq!(snapshot.mem_block.len(), 4);
+        }
+    }
+
+    #[test]
+    fn run_collecting_stops_on_halt() {
+        let mut machine =
+            Machine::from_instructions(&[instr::Instruction::Nop, instr::Instruction::Halt]);
+
+        let snapshots = machine.run_collecting(10);
+
+        assert_eq!(snapshots.len(), 2);
+        assert!(machine.cpu.is_halted());
+    }
+
+    #[test]
+    fn run_traced_sees_every_cycle() {
+        let mut machine = Machine::new();
+        let program = asm::assemble(
+            r#"
+            INC A
+            INC A
+            HALT
+        "#,
+        )
+        .expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+
+        let mut trace = Vec::new();
+        let result = machine.run_traced(10, |snapshot| {
+            trace.push((snapshot.registers.instruction_pointer, snapshot.next_instr))
+        });
+
+        assert_eq!(result, RunResult::Halted);
+        assert_eq!(
+            trace,
+            vec![
+                (0, Some(instr::Instruction::Inc(cpu::Register::A))),
+                (2, Some(instr::Instruction::Inc(cpu::Register::A))),
+                (4, Some(instr::Instruction::Halt)),
+            ]
+        );
+    }
+
+    #[test]
+    fn run_traced_cycle_limit() {
+        let mut machine = Machine::new();
+
+        // JMP 0x0
+        machine.cpu.mem[0] = 0x4;
+
+        let mut calls = 0;
+        let result = machine.run_traced(3, |_| calls += 1);
+
+        assert_eq!(result, RunResult::CycleLimitReached);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn clone_is_independent() {
+        let mut machine = Machine::new();
+        machine.install_console().expect("should install");
+        machine.cpu.mem[0x100] = 0x42;
+        machine.cpu.registers.a = 1;
+        machine.add_breakpoint(0x10);
+
+        let mut clone = machine.clone();
+        clone.cpu.mem[0x100] = 0x43;
+        clone.cpu.mem[0x2_0000] = 0x44;
+        clone.cpu.registers.a = 2;
+        clone.remove_breakpoint(0x10);
+        clone
+            .cpu
+            .do_instruction(instr::Instruction::Move(instr::Move::ImmToMem8(
+                b'!',
+                device::CONSOLE_PORT,
+            )))
+            .expect("should execute");
+
+        assert_eq!(machine.cpu.mem[0x100], 0x42);
+        assert_eq!(machine.cpu.mem[0x2_0000], 0);
+        assert_eq!(machine.cpu.registers.a, 1);
+        assert!(machine.remove_breakpoint(0x10));
+        assert_eq!(
+            machine.console().expect("should have console").output(),
+            b""
+        );
+        assert_eq!(clone.console().expect("should have console").output(), b"!");
+    }
+
+    #[test]
+    fn save_restore_state() {
+        let mut machine = Machine::new();
+        machine.cpu.mem[0x0] = 0x10;
+        machine.cpu.mem[0x1] = 0x1;
+        machine.cpu.mem[0x0123_4567] = 0x42;
+        for addr in 0x200..0x300 {
+            machine.cpu.mem[addr] = 0xAA;
+        }
+        machine.run_until_halt(10);
+        machine.cpu.registers.x = 0xDEAD_BEEF;
+        machine.cpu.registers.flags.set(cpu::CARRY);
+
+        let state = machine.save_state();
+        assert!(state.len() < 0x1000 * 4, "state should be compressed");
+
+        let mut restored = Machine::new();
+        restored.cpu.mem[0x300] = 0x1;
+        restored.restore_state(&state).expect("should restore");
+
+        assert_eq!(restored.cpu.registers.instruction_pointer, 3);
+        assert_eq!(restored.cpu.registers.b, 1);
+        assert_eq!(restored.cpu.registers.x, 0xDEAD_BEEF);
+        assert_eq!(
+            restored.cpu.registers.stack_pointer,
+            machine.cpu.registers.stack_pointer
+        );
+        assert!(restored.cpu.registers.flags.contains(cpu::CARRY));
+        assert!(restored.cpu.is_halted());
+        assert_eq!(restored.cpu.cycles(), machine.cpu.cycles());
+        assert_eq!(restored.cpu.mem[0x0123_4567], 0x42);
+        assert_eq!(restored.cpu.mem[0x2FF], 0xAA);
+        assert_eq!(restored.cpu.mem[0x300], 0);
+        assert_eq!(restored.cpu.mem.allocated_pages(), 2);
+    }
+
+    #[test]
+    fn restore_invalid_state() {
+        let mut machine = Machine::new();
+        machine.cpu.registers.a = 1;
+        let state = machine.save_state();
+
+        assert!(machine.restore_state(b"NOPE").is_err());
+        assert!(machine.restore_state(&state[..state.len() - 1]).is_err());
+        assert_eq!(machine.cpu.registers.a, 1);
+    }
+
+    #[test]
+    fn state_eq() {
+        let program = asm::assemble("MOV A, 0x10\nMOV [0x100], A\nHALT").expect("should assemble");
+
+        let mut machine = Machine::new();
+        let mut other = Machine::new();
+        for machine in [&mut machine, &mut other] {
+            machine.load_program(0, &program).expect("should load");
+            assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+        }
+
+        assert!(machine.cpu.state_eq(&other.cpu));
+        assert!(machine.cpu.state_eq(&machine.clone().cpu));
+
+        other.cpu.mem[0x101] = 0x1;
+        assert!(!machine.cpu.state_eq(&other.cpu));
+
+        other.cpu.mem[0x101] = 0x0;
+        other.cpu.registers.set_carry(true);
+        assert!(!machine.cpu.state_eq(&other.cpu));
+    }
+
+    #[test]
+    fn illegal_instruction_traps() {
+        let mut machine = Machine::new();
+        machine
+            .load_program(0, &[0x12, 0xFF, 0xFF])
+            .expect("should load");
+
+        assert_eq!(
+            machine.run_until_halt(10),
+            RunResult::DecodeError(instr::DecodeError::InvalidGroup(0xFF))
+        );
+        assert!(machine.cpu.is_halted());
+        assert_eq!(
+            machine.cpu.last_fault(),
+            Some(instr::DecodeError::InvalidGroup(0xFF))
+        );
+        assert_eq!(machine.cpu.registers.instruction_pointer, 1);
+        assert_eq!(machine.cpu.cycles(), 2);
+
+        // stays halted instead of faulting over and over
+        assert_eq!(machine.run_cycle(), Ok(()));
+        assert_eq!(machine.cpu.cycles(), 2);
+
+        machine.cpu.resume();
+        assert_eq!(machine.cpu.last_fault(), None);
+    }
+
+    #[test]
+    fn fault_handler() {
+        let faults = Rc::new(RefCell::new(Vec::new()));
+
+        let mut machine = Machine::new();
+        machine.load_program(0, &[0xFF]).expect("should load");
+        let recorded = faults.clone();
+        machine.set_fault_handler(move |e| recorded.borrow_mut().push(*e));
+
+        assert!(matches!(
+            machine.run_until_halt(10),
+            RunResult::DecodeError(_)
+        ));
+        assert_eq!(
+            *faults.borrow(),
+            vec![instr::DecodeError::InvalidGroup(0xFF)]
+        );
+
+        machine.cpu.resume();
+        assert!(machine.step().error.is_some());
+        assert_eq!(faults.borrow().len(), 2);
+
+        machine.cpu.resume();
+        machine.clear_fault_handler();
+        assert!(machine.run_cycle().is_err());
+        assert_eq!(faults.borrow().len(), 2);
+    }
+
+    #[test]
+    fn coverage() {
+        let program = asm::assemble(
+            r#"
+            MOV A, 1        ; 0x00
+            CMP A, 1        ; 0x07
+            JNZ skipped     ; 0x0e
+            INC B           ; 0x13
+            HALT            ; 0x15
+        skipped:
+            DEC B           ; 0x16
+            HALT
+        "#,
+        )
+        .expect("should assemble");
+
+        let mut machine = Machine::new();
+        machine.load_program(0, &program).expect("should load");
+        machine.run_cycle().expect("should cycle");
+        assert_eq!(machine.coverage().count(), 0);
+
+        machine.enable_coverage();
+        assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+
+        assert_eq!(
+            machine.coverage().collect::<Vec<_>>(),
+            vec![0x07, 0x0e, 0x13, 0x15]
+        );
+
+        machine.disable_coverage();
+        assert_eq!(machine.coverage().count(), 0);
+    }
+
+    #[test]
+    fn many_machines_stay_sparse() {
+        let machines = (0..1000).map(|_| Machine::new()).collect::<Vec<_>>();
+
+        for machine in &machines {
+            assert_eq!(machine.cpu.mem.allocated_pages(), 0);
+        }
+    }
+
+    // #[test]
+    // fn idk() {
+    //     let mut machine = Machine::new();
+
+    //     // machine.run_cycle().expect("should cycle");
+    //     machine.cpu.registers.a = 0xFFF1_1FFF;
+
+    //     machine.cpu.mem[0] = 0x1;
+    //     machine.cpu.mem[1] = 0x8;
+    //     machine.cpu.mem[2] = 0x0;
+    //     machine.cpu.mem[3] = 0x2;
+
+    //     let pretty = machine.snapshot().pretty();
+
+    //     panic!("{pretty}");
+    // }
+
+    #[test]
+    fn run_until_breakpoint() {
+        let mut machine = Machine::new();
+
+        let program = asm::assemble("INC A\nINC A\nINC A\nHALT").expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+        machine.add_breakpoint(0x4);
+
+        assert_eq!(
+            machine.run_until_halt(10),
+            RunResult::Stopped(StopReason::BreakpointHit(0x4))
+        );
+        assert_eq!(machine.cpu.registers.instruction_pointer, 0x4);
+        assert_eq!(machine.cpu.registers.a, 2);
+
+        assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+        assert_eq!(machine.cpu.registers.a, 3);
+    }
+
+    #[test]
+    fn removed_breakpoint_is_ignored() {
+        let mut machine = Machine::new();
+
+        let program = asm::assemble("INC A\nINC A\nHALT").expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+        machine.add_breakpoint(0x2);
+
+        assert!(machine.remove_breakpoint(0x2));
+        assert!(!machine.remove_breakpoint(0x2));
+        assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+    }
+
+    #[test]
+    fn run_until_watchpoint() {
+        let mut machine = Machine::new();
+
+        let program =
+            asm::assemble("MOV A, 0x11223344\nMOV [0x40], A\nHALT").expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+        machine.cpu.mem[0x42] = 0xFF;
+        machine.add_watchpoint(0x42);
+
+        assert_eq!(
+            machine.run_until_halt(10),
+            RunResult::Stopped(StopReason::WatchpointHit(cpu::WatchpointHit {
+                addr: 0x42,
+                old: 0xFF,
+                new: 0x22,
+            }))
+        );
+        assert_eq!(machine.cpu.mem[0x42], 0x22);
+
+        assert_eq!(machine.run_until_halt(10), RunResult::Halted);
+    }
+
+    #[test]
+    fn snapshot_diff() {
+        let mut machine = Machine::new();
+
+        let program = asm::assemble("MOV BYTE [0x20], A").expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+        machine.cpu.registers.a = 0x42;
+
+        let prev = machine.snapshot().to_owned_window(0, 0x40);
+        machine.run_cycle().expect("should cycle");
+        let diff = machine.snapshot().diff(&prev);
+
+        assert_eq!(
+            diff,
+            SnapshotDiff {
+                registers: vec![RegisterDiff {
+                    register: cpu::Register::Ip,
+                    old: 0,
+                    new: program.len() as u32,
+                }],
+                flags: None,
+                mem: vec![(0x20, 1)],
+            }
+        );
+    }
+
+    #[test]
+    fn snapshot_diff_groups_runs() {
+        let mut machine = Machine::new();
+
+        let program = asm::assemble("MOV [0x20], A\nMOV BYTE [0x30], A").expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+        machine.cpu.registers.a = 0xFFFF_FFFF;
+
+        let prev = machine.snapshot().to_owned_window(0x20, 0x20);
+        machine.run_cycle().expect("should cycle");
+        machine.run_cycle().expect("should cycle");
+
+        assert_eq!(
+            machine.snapshot().diff(&prev).mem,
+            vec![(0x20, 4), (0x30, 1)]
+        );
+    }
+
+    #[test]
+    fn pretty_shows_flags_and_instructions() {
+        let mut machine = Machine::new();
+
+        let program = asm::assemble("INC A\nDEC B\nHALT").expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+
+        let pretty = machine.snapshot().pretty();
+        assert!(pretty.contains("|  Z: 0       C: 0  |"));
+        assert!(pretty.contains("|  O: 0       S: 0  |"));
+
+        machine.cpu.registers.flags |= cpu::ZERO | cpu::SIGN;
+
+        let pretty = machine.snapshot().pretty();
+        assert!(pretty.contains("|  Z: 1       C: 0  |"));
+        assert!(pretty.contains("|  O: 0       S: 1  |"));
+        assert!(pretty.contains("0x00000000  INC A\n0x00000002  DEC B\n0x00000004  HALT\n"));
+    }
+
+    #[test]
+    fn snapshot_window_around_ip() {
+        let mut machine = Machine::new();
+        machine.cpu.registers.instruction_pointer = 0x100;
+        for i in 0..0x40 {
+            machine.cpu.mem[0xE0 + i] = i as u8;
+        }
+
+        let ip = machine.cpu.registers.instruction_pointer;
+        let snapshot = machine.snapshot_window(ip - 16, 32);
+
+        assert_eq!(snapshot.mem_start, 0xF0);
+        assert_eq!(snapshot.mem_len, 32);
+        assert_eq!(
+            snapshot.window().collect::<Vec<_>>(),
+            (0x10..0x30).collect::<Vec<u8>>()
+        );
+        assert!(snapshot
+            .pretty()
+            .contains("000000f0: 10 11 12 13 14 15 16 17"));
+        assert!(!machine.snapshot().pretty().contains("----- Memory -----"));
+    }
+
+    #[test]
+    fn snapshot_window_clamped() {
+        let machine = Machine::with_mem_size(64);
+
+        let snapshot = machine.snapshot_window(48, 32);
+        assert_eq!((snapshot.mem_start, snapshot.mem_len), (48, 16));
+
+        let snapshot = machine.snapshot_window(100, 32);
+        assert_eq!((snapshot.mem_start, snapshot.mem_len), (64, 0));
+        assert_eq!(snapshot.window().count(), 0);
+    }
+
+    #[test]
+    fn small_mem_wraps_around() {
+        let mut machine = Machine::with_mem_size(64);
+
+        assert_eq!(machine.cpu.mem.size(), 64);
+        assert_eq!(machine.cpu.registers.stack_pointer, 64);
+
+        // JMP 0x10, with the address wrapping around to the start of memory
+        machine.cpu.mem[62] = 0x4;
+        machine.cpu.mem[63] = 0x10;
+        machine.cpu.registers.instruction_pointer = 62;
+        machine.run_cycle().expect("should cycle");
+
+        assert_eq!(machine.cpu.registers.instruction_pointer, 0x10);
+
+        // data reads don't wrap though
+        let program = asm::assemble("MOV A, [63]\nHALT").expect("should assemble");
+        machine.load_program(0x10, &program).expect("should load");
+        machine.cpu.mem[63] = 0x42;
+        machine.cpu.mem[0] = 0x43;
+
+        assert_eq!(
+            machine.run_until_halt(10),
+            RunResult::ExecutionError(String::from(
+                "Tried to read 4 byte(s) out of bounds at 0x0000003f"
+            ))
+        );
+        assert_eq!(machine.cpu.registers.a, 0);
+    }
+
+    #[test]
+    fn small_mem_write_out_of_bounds() {
+        let mut machine = Machine::with_mem_size(64);
+
+        let program = asm::assemble("MOV [62], A").expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+
+        assert!(matches!(
+            machine.run_until_halt(10),
+            RunResult::ExecutionError(_)
+        ));
+    }
+
+    #[test]
+    fn cycle_and_instruction_counters() {
+        let mut machine = Machine::new();
+
+        let program = asm::assemble("INC A\nINC A\nINC A").expect("should assemble");
+        machine.load_program(0, &program).expect("should load");
+        machine.cpu.mem[program.len()] = 0xFF;
+
+        for _ in 0..3 {
+            machine.run_cycle().expect("should cycle");
+        }
+        assert!(machine.run_cycle().is_err());
+
+        assert_eq!(machine.cpu.cycles(), 4);
+        assert_eq!(machine.cpu.instructions_retired(), 3);
+
+        let snapshot = machine.snapshot();
+        assert_eq!(snapshot.cycles, 4);
+        assert_eq!(snapshot.instructions_retired, 3);
+
+        machine.cpu.reset(false);
+        assert_eq!(machine.cpu.cycles(), 0);
+        assert_eq!(machine.cpu.instructions_retired(), 0);
+    }
+
+    #[test]
+    fn step_reports_instruction() {
+        let mut machine = Machine::new();
+
+        let program = asm::assemble("MOV A, B").expect("should assemble");
+        machine.load_program(0x10, &program).expect("should load");
+        machine.cpu.registers.instruction_pointer = 0x10;
+        machine.cpu.registers.b = 0x42;
+
+        assert_eq!(
+            machine.step(),
+            StepResult {
+                instr: Some(instr::Instruction::Move(instr::Move::RegToReg(
+                    cpu::Register::B,
+                    cpu::Register::A
+                ))),
+                ip_before: 0x10,
+                ip_after: 0x10 + program.len() as u32,
+                error: None,
+            }
+        );
+        assert_eq!(machine.cpu.registers.a, 0x42);
+    }
+
+    #[test]
+    fn step_reports_decode_error() {
+        let mut machine = Machine::new();
+        machine.cpu.mem[0] = 0xFF;
+
+        let step = machine.step();
+
+        assert_eq!(step.instr, None);
+        assert_eq!(step.ip_before, 0);
+        assert_eq!(step.ip_after, 0);
+        assert_eq!(
+            step.error,
+            Some(cpu::CycleError::Decode(instr::DecodeError::InvalidGroup(
+                0xFF
+            )))
+        );
+    }
 }
@@ -0,0 +1,248 @@
+// Copyright (C) 2023  Patrick Cleavelin <patrick@spacegirl.nl>
+
+//! Sparse backing memory for the CPU
+
+use alloc::{format, rc::Rc, string::String, vec, vec::Vec};
+use core::ops::{Index, IndexMut};
+
+pub(crate) const PAGE_SIZE: usize = 0x1_0000;
+
+/// Anything that bytes can be read out of by a `MemIterator`
+pub trait MemSource {
+    fn size(&self) -> usize;
+    fn read8(&self, index: usize) -> u8;
+}
+
+impl MemSource for [u8] {
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn read8(&self, index: usize) -> u8 {
+        self[index]
+    }
+}
+
+/// Memory split up into pages that only get allocated the first time they
+/// are written to, untouched pages read back as zero.
+///
+/// Cloning is cheap, clones share their pages until one of them writes to
+/// a page, which then gets copied for the writer.
+#[derive(Clone)]
+pub struct Memory {
+    size: usize,
+    pages: Vec<Option<Rc<Vec<u8>>>>,
+}
+
+impl Memory {
+    pub fn new(size: usize) -> Self {
+        let page_count = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+
+        Self {
+            size,
+            pages: (0..page_count).map(|_| None).collect(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Zeroes all of memory by throwing away every allocated page
+    pub fn clear(&mut self) {
+        self.pages.iter_mut().for_each(|page| *page = None);
+    }
+
+    /// Number of pages that have been written to so far
+    pub fn allocated_pages(&self) -> usize {
+        self.pages.iter().filter(|page| page.is_some()).count()
+    }
+
+    /// Formats `len` bytes from `addr` as lines of `offset: bytes |ascii|`, 16
+    /// bytes to a line. Stops at the end of memory.
+    pub fn hexdump(&self, addr: u32, len: usize) -> String {
+        let start = (addr as usize).min(self.size);
+        let end = start.saturating_add(len).min(self.size);
+
+        let mut dump = String::new();
+        for line_start in (start..end).step_by(16) {
+            let line = (line_start..end.min(line_start + 16))
+                .map(|addr| self[addr])
+                .collect::<Vec<_>>();
+
+            let hex = line
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let ascii = line
+                .iter()
+                .map(|byte| {
+                    if byte.is_ascii_graphic() || *byte == b' ' {
+                        *byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+
+            // a short last line is padded so the ascii still lines up
+            dump += &format!("{line_start:08x}: {hex:<47}  |{ascii}|\n");
+        }
+
+        dump
+    }
+
+    pub(crate) fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The bytes of page `index` that are inside of memory, `None` if the
+    /// page was never written to
+    pub(crate) fn page(&self, index: usize) -> Option<&[u8]> {
+        let len = PAGE_SIZE.min(self.size - index * PAGE_SIZE);
+
+        self.pages[index].as_ref().map(|page| &page[..len])
+    }
+
+    fn check_index(&self, index: usize) {
+        if index >= self.size {
+            panic!(
+                "memory index out of bounds: the size is 0x{:08x} but the index is 0x{index:08x}",
+                self.size
+            );
+        }
+    }
+}
+
+impl PartialEq for Memory {
+    fn eq(&self, other: &Self) -> bool {
+        let is_zero = |page: &Vec<u8>| page.iter().all(|byte| *byte == 0);
+
+        self.size == other.size
+            && self
+                .pages
+                .iter()
+                .zip(&other.pages)
+                .all(|pages| match pages {
+                    (Some(a), Some(b)) => Rc::ptr_eq(a, b) || a == b,
+                    (Some(page), None) | (None, Some(page)) => is_zero(page),
+                    (None, None) => true,
+                })
+    }
+}
+
+impl Eq for Memory {}
+
+impl MemSource for Memory {
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn read8(&self, index: usize) -> u8 {
+        self[index]
+    }
+}
+
+impl Index<usize> for Memory {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        self.check_index(index);
+
+        match &self.pages[index / PAGE_SIZE] {
+            Some(page) => &page[index % PAGE_SIZE],
+            None => &0,
+        }
+    }
+}
+
+impl IndexMut<usize> for Memory {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.check_index(index);
+
+        let page =
+            self.pages[index / PAGE_SIZE].get_or_insert_with(|| Rc::new(vec![0u8; PAGE_SIZE]));
+
+        &mut Rc::make_mut(page)[index % PAGE_SIZE]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod memory {
+        use super::*;
+
+        #[test]
+        fn pages_allocated_on_write() {
+            let mut mem = Memory::new(PAGE_SIZE * 4);
+
+            assert_eq!(mem[PAGE_SIZE * 2], 0);
+            assert_eq!(mem.allocated_pages(), 0);
+
+            mem[PAGE_SIZE * 2 + 1] = 0x42;
+
+            assert_eq!(mem[PAGE_SIZE * 2 + 1], 0x42);
+            assert_eq!(mem[PAGE_SIZE * 2], 0);
+            assert_eq!(mem.allocated_pages(), 1);
+        }
+
+        #[test]
+        fn clone_copies_on_write() {
+            let mut mem = Memory::new(PAGE_SIZE * 4);
+            mem[0] = 0x42;
+
+            let mut clone = mem.clone();
+            clone[0] = 0x43;
+            clone[PAGE_SIZE] = 0x44;
+
+            assert_eq!(mem[0], 0x42);
+            assert_eq!(mem[PAGE_SIZE], 0);
+            assert_eq!(mem.allocated_pages(), 1);
+            assert_eq!(clone[0], 0x43);
+            assert_eq!(clone[PAGE_SIZE], 0x44);
+        }
+
+        #[test]
+        fn eq() {
+            let mut mem = Memory::new(PAGE_SIZE * 4);
+            let mut other = Memory::new(PAGE_SIZE * 4);
+            assert!(mem == other);
+
+            mem[PAGE_SIZE] = 0x42;
+            assert!(mem != other);
+
+            other[PAGE_SIZE] = 0x42;
+            assert!(mem == other);
+
+            // a written page of zeroes is the same as an untouched one
+            other[PAGE_SIZE * 2] = 0;
+            assert!(mem == other);
+
+            assert!(mem != Memory::new(PAGE_SIZE * 3));
+        }
+
+        #[test]
+        fn clear() {
+            let mut mem = Memory::new(PAGE_SIZE * 4);
+
+            mem[0] = 0x42;
+            mem[PAGE_SIZE * 3] = 0x42;
+            mem.clear();
+
+            assert_eq!(mem[0], 0);
+            assert_eq!(mem[PAGE_SIZE * 3], 0);
+            assert_eq!(mem.allocated_pages(), 0);
+        }
+
+        #[test]
+        #[should_panic]
+        fn index_out_of_bounds() {
+            let mem = Memory::new(PAGE_SIZE);
+
+            let _ = mem[PAGE_SIZE];
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright (C) 2023  Patrick Cleavelin <patrick@spacegirl.nl>
+
+//! Saving and restoring the state of a `Cpu` to a byte buffer
+//!
+//! The layout is the magic `b"NEMS"` and a version byte, then the registers,
+//! flags, halted state and counters, the memory size, and finally every page
+//! of memory. Untouched pages are a single `0` byte, the rest are a `1`
+//! followed by run length encoded `(count: u32, byte)` pairs.
+
+use alloc::{format, string::String, vec::Vec};
+
+use crate::{
+    cpu::{Cpu, CpuRegisters},
+    mem::{Memory, PAGE_SIZE},
+};
+
+const MAGIC: [u8; 4] = *b"NEMS";
+const VERSION: u8 = 1;
+
+pub(crate) fn save(cpu: &Cpu) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    bytes.extend(MAGIC);
+    bytes.push(VERSION);
+
+    let registers = &cpu.registers;
+    for value in [
+        registers.instruction_pointer,
+        registers.stack_pointer,
+        registers.a,
+        registers.b,
+        registers.x,
+        registers.y,
+    ] {
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes.push(registers.flags.value());
+    bytes.push(cpu.halted as u8);
+    bytes.extend(cpu.cycles.to_le_bytes());
+    bytes.extend(cpu.instructions_retired.to_le_bytes());
+
+    bytes.extend((cpu.mem.size() as u64).to_le_bytes());
+    for index in 0..cpu.mem.page_count() {
+        match cpu.mem.page(index) {
+            Some(page) => {
+                bytes.push(1);
+                encode_runs(page, &mut bytes);
+            }
+            None => bytes.push(0),
+        }
+    }
+
+    bytes
+}
+
+/// Returns the registers, halted state, counters and memory stored by `save`
+pub(crate) fn restore(data: &[u8]) -> Result<(CpuRegisters, bool, u64, u64, Memory), String> {
+    let mut reader = Reader { data, pos: 0 };
+
+    if reader.take(4)? != MAGIC {
+        return Err(String::from("Not a saved machine state"));
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(format!("Unsupported machine state version {version}"));
+    }
+
+    let registers = CpuRegisters {
+        instruction_pointer: reader.u32()?,
+        stack_pointer: reader.u32()?,
+        a: reader.u32()?,
+        b: reader.u32()?,
+        x: reader.u32()?,
+        y: reader.u32()?,
+        flags: reader.u8()?.into(),
+    };
+    let halted = reader.u8()? != 0;
+    let cycles = reader.u64()?;
+    let instructions_retired = reader.u64()?;
+
+    let size = reader.u64()?;
+    // addresses are only 32 bits wide
+    if size == 0 || size > 1 << 32 {
+        return Err(format!("Invalid memory size 0x{size:x}"));
+    }
+    let size = size as usize;
+    let mut mem = Memory::new(size);
+    for index in 0..mem.page_count() {
+        match reader.u8()? {
+            0 => {}
+            1 => {
+                let start = index * PAGE_SIZE;
+                let len = PAGE_SIZE.min(size - start);
+                decode_runs(&mut reader, &mut mem, start, len)?;
+            }
+            tag => return Err(format!("Invalid page tag {tag} for page {index}")),
+        }
+    }
+
+    if reader.pos != data.len() {
+        return Err(format!(
+            "{} trailing byte(s) after machine state",
+            data.len() - reader.pos
+        ));
+    }
+
+    Ok((registers, halted, cycles, instructions_retired, mem))
+}
+
+fn encode_runs(page: &[u8], bytes: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < page.len() {
+        let byte = page[i];
+        let run = page[i..].iter().take_while(|b| **b == byte).count();
+
+        bytes.extend((run as u32).to_le_bytes());
+        bytes.push(byte);
+
+        i += run;
+    }
+}
+
+fn decode_runs(
+    reader: &mut Reader,
+    mem: &mut Memory,
+    start: usize,
+    len: usize,
+) -> Result<(), String> {
+    let mut offset = 0;
+    while offset < len {
+        let run = reader.u32()? as usize;
+        let byte = reader.u8()?;
+
+        if run == 0 || run > len - offset {
+            return Err(format!(
+                "Invalid run of {run} byte(s) at 0x{:08x}",
+                start + offset
+            ));
+        }
+
+        // zero runs are skipped so that memory stays sparse
+        if byte != 0 {
+            for i in 0..run {
+                mem[start + offset + i] = byte;
+            }
+        }
+
+        offset += run;
+    }
+
+    Ok(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| String::from("Machine state is truncated"))?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut value = [0; 8];
+        value.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(value))
+    }
+}